@@ -0,0 +1,93 @@
+// Precompresses the frontend bundle so the server can send Brotli and gzip
+// without compressing on every request. The variants are written to
+// `$OUT_DIR/frontend` as `<path>.br` and `<path>.gz` and embedded next to
+// the originals.
+
+use std::io::Write;
+
+const DIST_DIR: &str = "src/frontend/dist";
+// Already compressed formats gain nothing
+const COMPRESSIBLE: &[&str] = &[
+    "html",
+    "js",
+    "mjs",
+    "css",
+    "json",
+    "map",
+    "svg",
+    "txt",
+    "xml",
+    "ico",
+    "wasm",
+    "webmanifest",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", DIST_DIR);
+
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("frontend");
+    // Start clean so files removed from the bundle don't linger
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(&out_dir).expect("can't create precompressed asset dir");
+
+    let dist = std::path::Path::new(DIST_DIR);
+    if !dist.exists() {
+        return;
+    }
+
+    for entry in walkdir::WalkDir::new(dist) {
+        let entry = entry.expect("can't read frontend bundle");
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let compressible = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSIBLE.contains(&ext));
+        if !compressible {
+            continue;
+        }
+
+        let contents = std::fs::read(path).expect("can't read frontend asset");
+        let relative = path.strip_prefix(dist).unwrap();
+        let target = out_dir.join(relative);
+        std::fs::create_dir_all(target.parent().unwrap())
+            .expect("can't create precompressed asset dir");
+
+        let brotli = brotli(&contents);
+        if brotli.len() < contents.len() {
+            std::fs::write(with_suffix(&target, "br"), brotli)
+                .expect("can't write precompressed asset");
+        }
+
+        let gzip = gzip(&contents);
+        if gzip.len() < contents.len() {
+            std::fs::write(with_suffix(&target, "gz"), gzip)
+                .expect("can't write precompressed asset");
+        }
+    }
+}
+
+fn brotli(contents: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 11,
+        ..Default::default()
+    };
+    brotli::BrotliCompress(&mut &contents[..], &mut output, &params).expect("can't compress");
+    output
+}
+
+fn gzip(contents: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(contents).expect("can't compress");
+    encoder.finish().expect("can't compress")
+}
+
+fn with_suffix(path: &std::path::Path, suffix: &str) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    path.into()
+}
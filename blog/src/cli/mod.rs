@@ -0,0 +1,458 @@
+mod seed;
+
+use crate::db::repositories::Queries;
+
+#[derive(clap::Parser)]
+#[command(name = "landing", about = "Landing blog server and admin tasks")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Run the web server (the default)
+    Serve,
+    /// Apply or inspect database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manage accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Maintain posts
+    #[command(subcommand)]
+    Posts(PostsCommand),
+    /// Manage the keys session tokens are signed with
+    #[command(subcommand)]
+    Keys(KeysCommand),
+    /// Fill the database with fake accounts, posts and comments for development
+    Seed {
+        #[arg(long, default_value_t = 5)]
+        accounts: usize,
+        #[arg(long, default_value_t = 20)]
+        posts: usize,
+        #[arg(long, default_value_t = 50)]
+        comments: usize,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum MigrateCommand {
+    /// Apply pending migrations
+    Up,
+    /// List migrations and whether they are applied
+    Status,
+}
+
+#[derive(clap::Subcommand)]
+pub enum UserCommand {
+    /// Create an account, prompting for the password unless
+    /// `--password-stdin` is given
+    Create {
+        email: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long, value_enum, default_value_t = Role::Author)]
+        role: Role,
+        /// Read the password from the first line of stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Change the role of an account. Admins can edit and delete any post
+    SetRole {
+        email: String,
+        #[arg(value_enum)]
+        role: Role,
+    },
+    /// Replace the password of an account
+    ResetPassword {
+        email: String,
+        /// Read the password from the first line of stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Block an account from logging in and revoke its sessions
+    Disable { email: String },
+    /// Turn off two-factor authentication, for accounts that lost their
+    /// authenticator and recovery codes
+    DisableTotp { email: String },
+}
+
+#[derive(clap::Subcommand)]
+pub enum PostsCommand {
+    /// Rebuild data derived from post contents: rendered HTML, media
+    /// attachments and cached preview images
+    Reindex,
+}
+
+#[derive(clap::Subcommand)]
+pub enum KeysCommand {
+    /// List signing keys and their state
+    List,
+    /// Generate a key and publish it in the JWKS. It starts signing on the
+    /// next `keys rotate`
+    Generate {
+        #[arg(long, value_enum, default_value_t = KeyAlgorithm::Eddsa)]
+        algorithm: KeyAlgorithm,
+    },
+    /// Start signing with the newest generated key, once running servers
+    /// have loaded it. The previous key keeps verifying until its tokens
+    /// expire
+    Rotate,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum KeyAlgorithm {
+    Eddsa,
+    Rs256,
+}
+
+impl KeyAlgorithm {
+    fn to_jwt(self) -> crate::jwt::KeyAlgorithm {
+        match self {
+            KeyAlgorithm::Eddsa => crate::jwt::KeyAlgorithm::EdDsa,
+            KeyAlgorithm::Rs256 => crate::jwt::KeyAlgorithm::Rs256,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Role {
+    Admin,
+    Author,
+}
+
+impl Role {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Author => "author",
+        }
+    }
+}
+
+/// Runs an admin subcommand. Everything except `serve` ends up here.
+pub async fn run(command: Command, config: &crate::config::Config) -> Result<(), String> {
+    let pool = crate::db::connect(&config.database)
+        .await
+        .map_err(|err| format!("can't connect to database: {}", err))?;
+    let queries = Queries::new(pool.clone());
+
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Migrate(MigrateCommand::Up) => {
+            crate::db::MIGRATOR
+                .run(&pool)
+                .await
+                .map_err(|err| format!("can't run database migrations: {}", err))?;
+            println!("migrations are up to date");
+            Ok(())
+        }
+        Command::Migrate(MigrateCommand::Status) => migrate_status(&queries).await,
+        Command::User(command) => {
+            ensure_migrated(&queries).await?;
+            user(&queries, command).await
+        }
+        Command::Posts(PostsCommand::Reindex) => {
+            ensure_migrated(&queries).await?;
+            reindex_posts(&queries, config).await
+        }
+        Command::Keys(command) => {
+            ensure_migrated(&queries).await?;
+            keys(&queries, command, config.auth.token_lifetime_secs).await
+        }
+        Command::Seed {
+            accounts,
+            posts,
+            comments,
+        } => {
+            if config.environment == crate::config::Environment::Production {
+                return Err("refusing to seed a production database".to_string());
+            }
+            ensure_migrated(&queries).await?;
+            seed::run(&queries, accounts, posts, comments).await
+        }
+    }
+}
+
+async fn migrate_status(queries: &Queries) -> Result<(), String> {
+    // The migrations table doesn't exist until the first migration runs
+    let applied = queries.applied_migrations().await.unwrap_or_default();
+    let pending = crate::db::pending_migrations(&applied);
+
+    for migration in crate::db::MIGRATOR.iter() {
+        if migration.migration_type.is_down_migration() {
+            continue;
+        }
+
+        let status = if pending.contains(&migration.version) {
+            "pending"
+        } else {
+            "applied"
+        };
+        println!(
+            "{:<8} {} {}",
+            status, migration.version, migration.description
+        );
+    }
+
+    Ok(())
+}
+
+async fn ensure_migrated(queries: &Queries) -> Result<(), String> {
+    let applied = queries.applied_migrations().await.unwrap_or_default();
+    if crate::db::pending_migrations(&applied).is_empty() {
+        Ok(())
+    } else {
+        Err("database has pending migrations, run `landing migrate up` first".to_string())
+    }
+}
+
+async fn user(queries: &Queries, command: UserCommand) -> Result<(), String> {
+    match command {
+        UserCommand::Create {
+            email,
+            name,
+            role,
+            password_stdin,
+        } => {
+            let password = read_password(password_stdin)?;
+
+            let params = crate::db::repositories::accounts::CreateAccountParams {
+                email,
+                password,
+                account_name: name,
+            };
+            let account = queries
+                .create_account_with_role(&params, role.as_str())
+                .await
+                .map_err(|err| err.to_string())?;
+
+            println!(
+                "created {} ({}) as {}",
+                account.email,
+                account.id,
+                role.as_str()
+            );
+            Ok(())
+        }
+        UserCommand::SetRole { email, role } => {
+            let account = find_account(queries, &email).await?;
+            queries
+                .set_account_role(account.id, role.as_str())
+                .await
+                .map_err(|err| err.to_string())?;
+
+            println!("{} is now {}", account.email, role.as_str());
+            Ok(())
+        }
+        UserCommand::ResetPassword {
+            email,
+            password_stdin,
+        } => {
+            let account = find_account(queries, &email).await?;
+            let password = read_password(password_stdin)?;
+            queries
+                .set_account_password(account.id, &password)
+                .await
+                .map_err(|err| err.to_string())?;
+
+            println!("password reset for {}", account.email);
+            Ok(())
+        }
+        UserCommand::Disable { email } => {
+            let account = find_account(queries, &email).await?;
+            queries
+                .disable_account(account.id)
+                .await
+                .map_err(|err| err.to_string())?;
+
+            println!("disabled {}", account.email);
+            Ok(())
+        }
+        UserCommand::DisableTotp { email } => {
+            let account = find_account(queries, &email).await?;
+            queries
+                .disable_totp(account.id)
+                .await
+                .map_err(|err| err.to_string())?;
+
+            println!("two-factor authentication disabled for {}", account.email);
+            Ok(())
+        }
+    }
+}
+
+async fn keys(
+    queries: &Queries,
+    command: KeysCommand,
+    token_lifetime_secs: i64,
+) -> Result<(), String> {
+    match command {
+        KeysCommand::List => {
+            let keys = queries
+                .list_signing_keys()
+                .await
+                .map_err(|err| err.to_string())?;
+            let now = chrono::Utc::now();
+
+            for key in keys {
+                let status = match (key.activated_at, key.retired_at, key.expires_at) {
+                    (_, _, Some(expires_at)) if expires_at <= now => "expired".to_string(),
+                    (_, Some(_), Some(expires_at)) => {
+                        format!("retired, verifies until {}", expires_at.to_rfc3339())
+                    }
+                    (Some(_), None, _) => "active".to_string(),
+                    _ => "pending".to_string(),
+                };
+                println!(
+                    "{} {:<6} {} {}",
+                    key.id,
+                    key.algorithm,
+                    key.created_at.to_rfc3339(),
+                    status
+                );
+            }
+            Ok(())
+        }
+        KeysCommand::Generate { algorithm } => {
+            let id = generate_key(queries, algorithm).await?;
+
+            println!(
+                "generated {}, run `landing keys rotate` in {} seconds to start signing with it",
+                id,
+                crate::jwt::REFRESH_INTERVAL_SECS
+            );
+            Ok(())
+        }
+        KeysCommand::Rotate => {
+            let Some(key) = queries
+                .pending_signing_key()
+                .await
+                .map_err(|err| err.to_string())?
+            else {
+                return Err("no pending key, run `landing keys generate` first".to_string());
+            };
+
+            // Servers that haven't loaded the key yet would reject its tokens
+            let published_secs = (chrono::Utc::now() - key.created_at).num_seconds();
+            let wait_secs = crate::jwt::REFRESH_INTERVAL_SECS as i64 - published_secs;
+            if wait_secs > 0 {
+                return Err(format!(
+                    "key {} isn't loaded by every server yet, try again in {} seconds",
+                    key.id, wait_secs
+                ));
+            }
+
+            activate_key(queries, key.id, token_lifetime_secs).await?;
+
+            println!("{} signs new tokens", key.id);
+            Ok(())
+        }
+    }
+}
+
+async fn generate_key(queries: &Queries, algorithm: KeyAlgorithm) -> Result<uuid::Uuid, String> {
+    let algorithm = algorithm.to_jwt();
+    let key = crate::jwt::generate(algorithm)?;
+
+    queries
+        .create_signing_key(algorithm.as_str(), &key.private_key, &key.public_jwk)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn activate_key(
+    queries: &Queries,
+    id: uuid::Uuid,
+    token_lifetime_secs: i64,
+) -> Result<(), String> {
+    // Tokens signed by the retired key stay valid for at most one lifetime
+    if queries
+        .activate_signing_key(id, token_lifetime_secs)
+        .await
+        .map_err(|err| err.to_string())?
+    {
+        Ok(())
+    } else {
+        Err(format!("key {} can't be activated", id))
+    }
+}
+
+async fn find_account(
+    queries: &Queries,
+    email: &str,
+) -> Result<crate::db::repositories::accounts::Account, String> {
+    queries
+        .find_account_by_email(email)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| format!("no account with email {}", email))
+}
+
+/// Reads the password from stdin for scripts, or prompts for it twice.
+fn read_password(from_stdin: bool) -> Result<String, String> {
+    if !from_stdin {
+        return prompt_password();
+    }
+
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .map_err(|err| err.to_string())?;
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    if password.is_empty() {
+        return Err("password can't be empty".to_string());
+    }
+
+    Ok(password.to_string())
+}
+
+fn prompt_password() -> Result<String, String> {
+    let password = rpassword::prompt_password("Password: ").map_err(|err| err.to_string())?;
+    let confirmation =
+        rpassword::prompt_password("Confirm password: ").map_err(|err| err.to_string())?;
+
+    if password.is_empty() {
+        return Err("password can't be empty".to_string());
+    }
+    if password != confirmation {
+        return Err("passwords don't match".to_string());
+    }
+
+    Ok(password)
+}
+
+async fn reindex_posts(queries: &Queries, config: &crate::config::Config) -> Result<(), String> {
+    let posts = queries.all_posts().await.map_err(|err| err.to_string())?;
+
+    for post in &posts {
+        let rendered_contents =
+            crate::routes::media::with_srcset(&post.contents, &config.images.widths);
+        let media_ids = crate::routes::media::referenced_media(&post.contents);
+        queries
+            .reindex_post(post, &rendered_contents, &media_ids)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    // Preview images are rendered again on their next request
+    let mut removed = 0;
+    if let Ok(mut entries) = tokio::fs::read_dir(&config.og.cache_dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.path().extension().is_some_and(|ext| ext == "png")
+                && tokio::fs::remove_file(entry.path()).await.is_ok()
+            {
+                removed += 1;
+            }
+        }
+    }
+
+    println!(
+        "reindexed {} posts, removed {} cached preview images",
+        posts.len(),
+        removed
+    );
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::db::repositories::Queries;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+
+// Every seeded account can log in with this password
+const SEED_PASSWORD: &str = "password";
+
+#[rustfmt::skip]
+const FIRST_NAMES: &[&str] = &[
+    "Aisyah", "Daniel", "Farah", "Hafiz", "Irene", "Jun", "Kavya", "Liam", "Mei", "Nurul",
+    "Omar", "Priya", "Rafael", "Siti", "Tomas", "Wei",
+];
+
+#[rustfmt::skip]
+const LAST_NAMES: &[&str] = &[
+    "Abdullah", "Chen", "Fernandez", "Hassan", "Ibrahim", "Kumar", "Lim", "Muller", "Nair",
+    "Okafor", "Rahman", "Tan", "Wong", "Yusof",
+];
+
+#[rustfmt::skip]
+const WORDS: &[&str] = &[
+    "async", "borrow", "cache", "compiler", "container", "database", "deploy", "error",
+    "feature", "graph", "handler", "index", "kernel", "latency", "lifetime", "memory",
+    "migration", "module", "network", "pipeline", "pointer", "query", "queue", "release",
+    "request", "runtime", "schema", "server", "service", "storage", "stream", "testing",
+    "thread", "trait", "type", "vector", "workflow", "better", "faster", "simple", "small",
+    "reliable", "practical", "modern", "notes", "lessons", "guide", "journey", "debugging",
+    "building", "shipping", "learning", "scaling", "refactoring", "measuring", "with",
+    "without", "from", "into", "about", "for", "and", "the", "a", "my", "our",
+];
+
+/// Inserts fake accounts, posts and comments through the regular queries.
+pub async fn run(
+    queries: &Queries,
+    accounts: usize,
+    posts: usize,
+    comments: usize,
+) -> Result<(), String> {
+    if accounts == 0 && (posts > 0 || comments > 0) {
+        return Err("posts and comments need at least one account".to_string());
+    }
+
+    let mut rng = rand::rngs::StdRng::from_os_rng();
+
+    let mut account_ids = Vec::with_capacity(accounts);
+    for _ in 0..accounts {
+        let first = FIRST_NAMES.choose(&mut rng).unwrap();
+        let last = LAST_NAMES.choose(&mut rng).unwrap();
+
+        let params = crate::db::repositories::accounts::CreateAccountParams {
+            email: format!(
+                "{}.{}.{:04}@example.com",
+                first.to_lowercase(),
+                last.to_lowercase(),
+                rng.random_range(0..10_000)
+            ),
+            password: SEED_PASSWORD.to_string(),
+            account_name: format!("{} {}", first, last),
+        };
+
+        let account = queries
+            .create_account(&params)
+            .await
+            .map_err(|err| err.to_string())?;
+        println!("account {} / {}", account.email, SEED_PASSWORD);
+        account_ids.push(account.id);
+    }
+
+    let mut post_ids = Vec::with_capacity(posts);
+    for _ in 0..posts {
+        let account_id = *account_ids.choose(&mut rng).unwrap();
+        let contents = post_contents(&mut rng);
+        let params = crate::db::repositories::posts::CreatePostParams {
+            content: contents.clone(),
+        };
+
+        // Seeded posts don't reference uploads, there's nothing to render
+        let post = queries
+            .create_post(account_id, params, &contents, &[])
+            .await
+            .map_err(|err| err.to_string())?;
+        post_ids.push(post.id);
+    }
+
+    if comments > 0 && post_ids.is_empty() {
+        return Err("comments need at least one post".to_string());
+    }
+
+    for _ in 0..comments {
+        let account_id = *account_ids.choose(&mut rng).unwrap();
+        let post_id = *post_ids.choose(&mut rng).unwrap();
+        let count = rng.random_range(1..=3);
+        let contents = (0..count)
+            .map(|_| sentence(&mut rng))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        queries
+            .create_comment(account_id, post_id, &contents)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    println!(
+        "seeded {} accounts, {} posts and {} comments",
+        accounts, posts, comments
+    );
+    Ok(())
+}
+
+/// Builds editor-style HTML: a heading followed by a few paragraphs.
+fn post_contents(rng: &mut impl Rng) -> String {
+    let mut contents = format!("<h1>{}</h1>", title(rng));
+
+    for _ in 0..rng.random_range(2..=5) {
+        let paragraph = (0..rng.random_range(3..=6))
+            .map(|_| sentence(rng))
+            .collect::<Vec<_>>()
+            .join(" ");
+        contents.push_str(&format!("<p>{}</p>", paragraph));
+    }
+
+    contents
+}
+
+fn title(rng: &mut impl Rng) -> String {
+    (0..rng.random_range(3..=7))
+        .map(|_| capitalize(WORDS.choose(rng).unwrap()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn sentence(rng: &mut impl Rng) -> String {
+    let words: Vec<&str> = (0..rng.random_range(6..=16))
+        .map(|_| *WORDS.choose(rng).unwrap())
+        .collect();
+
+    format!("{}.", capitalize(&words.join(" ")))
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
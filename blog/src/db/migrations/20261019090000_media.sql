@@ -0,0 +1,16 @@
+-- Add migration script here
+create table media (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now(),
+    deleted_at timestamptz,
+    account_id uuid not null,
+    post_id uuid,
+    content_hash text not null,
+    content_type text not null,
+    size_bytes bigint not null,
+    file_name text not null
+);
+
+create unique index media_account_content_hash_unique on media (account_id, content_hash) where deleted_at is null;
+create index media_post_id on media (post_id) where deleted_at is null;
@@ -0,0 +1,6 @@
+-- Add migration script here
+alter table accounts
+    add column role text not null default 'author',
+    add column disabled_at timestamptz;
+
+alter table accounts add constraint accounts_role_check check (role in ('admin', 'author'));
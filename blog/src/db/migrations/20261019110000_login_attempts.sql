@@ -0,0 +1,11 @@
+-- Add migration script here
+create table login_attempts (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    email text not null,
+    ip_address text not null,
+    result text not null check (result in ('success', 'failure', 'throttled'))
+);
+
+create index login_attempts_email_created_at on login_attempts (email, created_at);
+create index login_attempts_ip_address_created_at on login_attempts (ip_address, created_at);
@@ -0,0 +1,7 @@
+-- Add migration script here
+create table rate_limits (
+    key text primary key,
+    window_start timestamptz not null,
+    window_secs double precision not null,
+    count bigint not null
+);
@@ -0,0 +1,15 @@
+-- Add migration script here
+alter table accounts
+    add column totp_secret text,
+    add column totp_enabled_at timestamptz,
+    add column totp_last_step bigint;
+
+create table recovery_codes (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    used_at timestamptz,
+    account_id uuid not null,
+    code_hash text not null
+);
+
+create index recovery_codes_account_id on recovery_codes (account_id);
@@ -0,0 +1,25 @@
+-- Add migration script here
+create table passkeys (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now(),
+    last_used_at timestamptz,
+    account_id uuid not null,
+    name text not null,
+    credential_id bytea not null,
+    public_key bytea not null,
+    algorithm bigint not null,
+    sign_count bigint not null default 0,
+    transports text[] not null default '{}'
+);
+
+create unique index passkeys_credential_id_unique on passkeys (credential_id);
+create index passkeys_account_id on passkeys (account_id);
+
+create table passkey_challenges (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    account_id uuid,
+    purpose text not null check (purpose in ('registration', 'authentication')),
+    challenge bytea not null
+);
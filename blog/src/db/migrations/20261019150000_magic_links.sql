@@ -0,0 +1,12 @@
+-- Add migration script here
+create table magic_links (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    expires_at timestamptz not null,
+    used_at timestamptz,
+    account_id uuid not null,
+    token_hash text not null,
+    browser_hash text
+);
+
+create unique index magic_links_token_hash_unique on magic_links (token_hash);
@@ -0,0 +1,16 @@
+-- Add migration script here
+create table access_tokens (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    expires_at timestamptz,
+    last_used_at timestamptz,
+    revoked_at timestamptz,
+    account_id uuid not null,
+    name text not null,
+    token_prefix text not null,
+    token_hash text not null,
+    scopes text[] not null
+);
+
+create unique index access_tokens_token_hash_unique on access_tokens (token_hash);
+create index access_tokens_account_id on access_tokens (account_id);
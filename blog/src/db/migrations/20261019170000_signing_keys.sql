@@ -0,0 +1,15 @@
+-- Add migration script here
+create table signing_keys (
+    id uuid primary key default gen_random_uuid(),
+    created_at timestamptz not null default now(),
+    activated_at timestamptz,
+    retired_at timestamptz,
+    expires_at timestamptz,
+    algorithm text not null check (algorithm in ('EdDSA', 'RS256')),
+    private_key bytea not null,
+    public_jwk text not null
+);
+
+-- Only one key signs at a time
+create unique index signing_keys_active_unique on signing_keys ((true))
+    where activated_at is not null and retired_at is null;
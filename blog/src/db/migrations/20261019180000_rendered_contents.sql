@@ -0,0 +1,7 @@
+-- Add migration script here
+alter table posts add column rendered_contents text;
+
+-- `landing posts reindex` renders these again from contents
+update posts set rendered_contents = contents;
+
+alter table posts alter column rendered_contents set not null;
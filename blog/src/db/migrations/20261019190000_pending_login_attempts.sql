@@ -0,0 +1,7 @@
+-- Add migration script here
+alter table login_attempts drop constraint login_attempts_result_check;
+
+-- A login holds a pending attempt while it checks credentials, so concurrent
+-- attempts count it as a failure until it's settled
+alter table login_attempts add constraint login_attempts_result_check
+    check (result in ('pending', 'success', 'failure', 'throttled'));
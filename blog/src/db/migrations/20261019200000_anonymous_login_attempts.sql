@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- Passkey logins with a credential nobody registered have no email, they're
+-- only counted against the IP address
+alter table login_attempts alter column email drop not null;
@@ -0,0 +1,7 @@
+-- Add migration script here
+alter table login_attempts drop constraint login_attempts_result_check;
+
+-- Sign-in link requests are counted per address, so nobody can flood an
+-- inbox with links
+alter table login_attempts add constraint login_attempts_result_check
+    check (result in ('pending', 'success', 'failure', 'throttled', 'link_requested'));
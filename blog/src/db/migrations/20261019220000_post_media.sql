@@ -0,0 +1,16 @@
+-- Add migration script here
+-- An upload can be shown in several posts, so links live in their own table
+create table post_media (
+    post_id uuid not null,
+    media_id uuid not null,
+    created_at timestamptz not null default now(),
+    primary key (post_id, media_id)
+);
+
+create index post_media_media_id on post_media (media_id);
+
+insert into post_media (post_id, media_id)
+select post_id, id from media where post_id is not null;
+
+drop index media_post_id;
+alter table media drop column post_id;
@@ -1 +1,60 @@
 pub mod repositories;
+
+use sqlx::migrate::MigrateDatabase;
+
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("src/db/migrations");
+
+/// Versions of the embedded migrations that are not in `applied`.
+pub fn pending_migrations(applied: &[i64]) -> Vec<i64> {
+    MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect()
+}
+
+const INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Connects to the database, retrying with exponential backoff until
+/// `connect_timeout_secs` elapses so the app can start before Postgres is up.
+/// When `create_database` is set the target database is created if missing.
+pub async fn connect(config: &crate::config::DatabaseConfig) -> Result<sqlx::PgPool, sqlx::Error> {
+    let deadline =
+        tokio::time::Instant::now() + std::time::Duration::from_secs(config.connect_timeout_secs);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        match try_connect(config).await {
+            Ok(pool) => return Ok(pool),
+            Err(err) if tokio::time::Instant::now() + backoff < deadline => {
+                tracing::warn!(
+                    "can't connect to database (attempt {}), retrying in {:?}: {}",
+                    attempt,
+                    backoff,
+                    err
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn try_connect(config: &crate::config::DatabaseConfig) -> Result<sqlx::PgPool, sqlx::Error> {
+    if config.create_database && !sqlx::Postgres::database_exists(&config.url).await? {
+        tracing::info!("creating database");
+        sqlx::Postgres::create_database(&config.url).await?;
+    }
+
+    sqlx::postgres::PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(std::time::Duration::from_secs(config.acquire_timeout_secs))
+        .connect(&config.url)
+        .await
+}
@@ -0,0 +1,146 @@
+use super::{Error, Queries};
+
+#[derive(sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
+pub struct AccessToken {
+    pub id: sqlx::types::Uuid,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub name: String,
+    /// The start of the token, enough to recognise it
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+}
+
+/// A valid token with the account it acts for.
+pub struct AccessTokenGrant {
+    pub id: sqlx::types::Uuid,
+    pub account_id: sqlx::types::Uuid,
+    pub email: String,
+    pub account_name: String,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub scopes: Vec<String>,
+}
+
+pub struct CreateAccessTokenParams<'a> {
+    pub name: &'a str,
+    pub token_prefix: &'a str,
+    pub token_hash: &'a str,
+    pub scopes: &'a [String],
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Queries {
+    #[tracing::instrument(skip_all)]
+    pub async fn create_access_token(
+        &self,
+        account_id: sqlx::types::Uuid,
+        params: &CreateAccessTokenParams<'_>,
+    ) -> Result<AccessToken, Error> {
+        let token = sqlx::query_as!(
+            AccessToken,
+            r#"
+            insert into access_tokens (account_id, name, token_prefix, token_hash, scopes, expires_at)
+            values ($1, $2, $3, $4, $5, $6)
+            returning id, created_at, expires_at, last_used_at, name, token_prefix, scopes
+            "#,
+            account_id,
+            params.name,
+            params.token_prefix,
+            params.token_hash,
+            params.scopes,
+            params.expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list_access_tokens(
+        &self,
+        account_id: sqlx::types::Uuid,
+    ) -> Result<Vec<AccessToken>, Error> {
+        let tokens = sqlx::query_as!(
+            AccessToken,
+            r#"
+            select id, created_at, expires_at, last_used_at, name, token_prefix, scopes
+            from access_tokens
+            where account_id = $1 and revoked_at is null
+            order by created_at desc
+            "#,
+            account_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tokens)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn revoke_access_token(
+        &self,
+        account_id: sqlx::types::Uuid,
+        id: sqlx::types::Uuid,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            update access_tokens
+            set revoked_at = now()
+            where account_id = $1 and id = $2 and revoked_at is null
+            "#,
+            account_id,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Looks up an unexpired, unrevoked token whose account can still log in.
+    #[tracing::instrument(skip_all)]
+    pub async fn find_access_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<AccessTokenGrant>, Error> {
+        let grant = sqlx::query_as!(
+            AccessTokenGrant,
+            r#"
+            select access_tokens.id, access_tokens.account_id, accounts.email,
+                accounts.account_name, access_tokens.expires_at, access_tokens.scopes
+            from access_tokens
+            join accounts on accounts.id = access_tokens.account_id
+            where access_tokens.token_hash = $1
+                and access_tokens.revoked_at is null
+                and (access_tokens.expires_at is null or access_tokens.expires_at > now())
+                and accounts.deleted_at is null
+                and accounts.disabled_at is null
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(grant)
+    }
+
+    /// Records a use, at most once a minute so busy tokens don't write on
+    /// every request.
+    #[tracing::instrument(skip_all)]
+    pub async fn touch_access_token(&self, id: sqlx::types::Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            update access_tokens
+            set last_used_at = now()
+            where id = $1 and (last_used_at is null or last_used_at < now() - interval '1 minute')
+            "#,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,118 +1,364 @@
 use super::{Error, Queries};
 
-#[derive(sqlx::FromRow, serde::Serialize)]
+#[derive(sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
 pub struct Account {
     pub id: sqlx::types::Uuid,
     pub email: String,
     pub account_name: String,
+    pub role: String,
     pub email_verified_at: Option<chrono::DateTime<chrono::Utc>>,
     pub photo_identifier: Option<String>,
+    pub totp_enabled: bool,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct CreateAccountParams {
     pub email: String,
     pub password: String,
     pub account_name: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct LoginParams {
     pub email: String,
     pub password: String,
 }
 
 impl Queries {
+    #[tracing::instrument(skip_all)]
     pub async fn create_account(&self, params: &CreateAccountParams) -> Result<Account, Error> {
-        let password_hash = bcrypt::hash(params.password.clone(), bcrypt::DEFAULT_COST)
-            .map_err(|err| Error::from(err))?;
+        self.create_account_with_role(params, "author").await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn create_account_with_role(
+        &self,
+        params: &CreateAccountParams,
+        role: &str,
+    ) -> Result<Account, Error> {
+        let password_hash = crate::passwords::hash(params.password.clone())
+            .await
+            .map_err(Error::DatabaseError)?;
 
         let account = sqlx::query_as!(
             Account,
             r#"
-            insert into accounts (email, password_hash, account_name)
-            values ($1, $2, $3)
-            returning id, email, account_name, email_verified_at, photo_identifier
+            insert into accounts (email, password_hash, account_name, role)
+            values ($1, $2, $3, $4)
+            returning id, email, account_name, role, email_verified_at, photo_identifier,
+                totp_enabled_at is not null as "totp_enabled!"
             "#,
             params.email,
             password_hash,
-            params.account_name
+            params.account_name,
+            role,
         )
         .fetch_one(&self.pool)
         .await
-        .map_err(|err| Error::from(err))?;
+        .map_err(Error::from)?;
 
         Ok(account)
     }
 
-    async fn verify_password<'e, E>(tx: E, email: String, password: String) -> Result<bool, Error>
-    where
-        E: 'e + sqlx::Executor<'e, Database = sqlx::Postgres>,
-    {
+    /// Checks the password and upgrades the stored hash when it uses an
+    /// outdated algorithm or parameters.
+    #[tracing::instrument(skip_all)]
+    async fn verify_password(&self, email: &str, password: String) -> Result<bool, Error> {
         struct AccountPassword {
+            id: sqlx::types::Uuid,
             password_hash: String,
         }
 
         let account_password = sqlx::query_as!(
             AccountPassword,
             r#"
-            select password_hash
+            select id, password_hash
             from accounts
-            where email = $1 and deleted_at is null
+            where email = $1 and deleted_at is null and disabled_at is null
             "#,
             email,
         )
-        .fetch_one(tx)
+        .fetch_optional(&self.pool)
         .await
-        .map_err(|err| Error::from(err))?;
+        .map_err(Error::from)?;
+
+        // Unknown accounts cost as much as wrong passwords so timing doesn't
+        // reveal which emails are registered
+        let Some(account_password) = account_password else {
+            crate::passwords::verify_dummy(password)
+                .await
+                .map_err(Error::DatabaseError)?;
+            return Ok(false);
+        };
 
-        let verified = bcrypt::verify(password, &account_password.password_hash)
-            .map_err(|err| Error::from(err))?;
+        let verification =
+            crate::passwords::verify(password.clone(), account_password.password_hash.clone())
+                .await
+                .map_err(Error::DatabaseError)?;
 
-        Ok(verified)
+        if verification.valid && verification.needs_rehash {
+            // The login still succeeds if the upgrade fails, it is retried next time
+            if let Err(err) = self
+                .rehash_password(
+                    account_password.id,
+                    &account_password.password_hash,
+                    password,
+                )
+                .await
+            {
+                tracing::warn!("can't rehash password: {}", err);
+            }
+        }
+
+        Ok(verification.valid)
     }
 
-    pub async fn login(&self, params: LoginParams) -> Result<Account, Error> {
-        let mut tx = self.pool.begin().await?;
+    #[tracing::instrument(skip_all)]
+    async fn rehash_password(
+        &self,
+        account_id: sqlx::types::Uuid,
+        old_hash: &str,
+        password: String,
+    ) -> Result<(), Error> {
+        let password_hash = crate::passwords::hash(password)
+            .await
+            .map_err(Error::DatabaseError)?;
 
-        let verified =
-            Self::verify_password(&mut *tx, params.email.clone(), params.password).await?;
+        // Only replace the hash that was verified, not one set concurrently
+        sqlx::query!(
+            r#"
+            update accounts
+            set password_hash = $1
+            where id = $2 and password_hash = $3
+            "#,
+            password_hash,
+            account_id,
+            old_hash,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn login(&self, params: LoginParams) -> Result<Account, Error> {
+        let verified = self.verify_password(&params.email, params.password).await?;
         if !verified {
-            return Err(Error::LoginError("account not found".to_string()));
+            return Err(Error::LoginError("invalid email or password".to_string()));
         }
 
         let account = sqlx::query_as!(
             Account,
             r#"
-            select id, email, account_name, email_verified_at, photo_identifier
+            select id, email, account_name, role, email_verified_at, photo_identifier,
+                totp_enabled_at is not null as "totp_enabled!"
             from accounts
-            where deleted_at is null and email = $1
+            where deleted_at is null and disabled_at is null and email = $1
             "#,
             params.email,
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&self.pool)
         .await
-        .map_err(|err| Error::from(err))?;
-
-        tx.commit().await?;
+        .map_err(Error::from)?;
 
         Ok(account)
     }
 
+    #[tracing::instrument(skip_all)]
     pub async fn me(&self, account_id: sqlx::types::Uuid) -> Result<Account, Error> {
         let account = sqlx::query_as!(
             Account,
             r#"
-            select id, email, account_name, email_verified_at, photo_identifier
+            select id, email, account_name, role, email_verified_at, photo_identifier,
+                totp_enabled_at is not null as "totp_enabled!"
             from accounts
-            where deleted_at is null and id = $1
+            where deleted_at is null and disabled_at is null and id = $1
             "#,
             account_id,
         )
         .fetch_one(&self.pool)
         .await
-        .map_err(|err| Error::from(err))?;
+        .map_err(Error::from)?;
 
         Ok(account)
     }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn is_account_active(&self, account_id: sqlx::types::Uuid) -> Result<bool, Error> {
+        let active = sqlx::query_scalar!(
+            r#"
+            select exists (
+                select 1
+                from accounts
+                where deleted_at is null and disabled_at is null and id = $1
+            ) as "active!"
+            "#,
+            account_id,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(active)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn find_account_by_email(&self, email: &str) -> Result<Option<Account>, Error> {
+        let account = sqlx::query_as!(
+            Account,
+            r#"
+            select id, email, account_name, role, email_verified_at, photo_identifier,
+                totp_enabled_at is not null as "totp_enabled!"
+            from accounts
+            where deleted_at is null and email = $1
+            "#,
+            email,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(account)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn set_account_role(
+        &self,
+        account_id: sqlx::types::Uuid,
+        role: &str,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            update accounts
+            set updated_at = now(), role = $1
+            where deleted_at is null and id = $2
+            "#,
+            role,
+            account_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn set_account_password(
+        &self,
+        account_id: sqlx::types::Uuid,
+        password: &str,
+    ) -> Result<(), Error> {
+        let password_hash = crate::passwords::hash(password.to_string())
+            .await
+            .map_err(Error::DatabaseError)?;
+
+        sqlx::query!(
+            r#"
+            update accounts
+            set updated_at = now(), password_hash = $1
+            where deleted_at is null and id = $2
+            "#,
+            password_hash,
+            account_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn disable_account(&self, account_id: sqlx::types::Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            update accounts
+            set updated_at = now(), disabled_at = coalesce(disabled_at, now())
+            where deleted_at is null and id = $1
+            "#,
+            account_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery";
+
+    async fn create_account(queries: &Queries) -> Account {
+        queries
+            .create_account(&CreateAccountParams {
+                email: "someone@example.com".to_string(),
+                password: PASSWORD.to_string(),
+                account_name: "someone".to_string(),
+            })
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap()
+    }
+
+    async fn set_password_hash(pool: &sqlx::PgPool, account_id: sqlx::types::Uuid, hash: &str) {
+        sqlx::query("update accounts set password_hash = $1 where id = $2")
+            .bind(hash)
+            .bind(account_id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn password_hash(pool: &sqlx::PgPool, account_id: sqlx::types::Uuid) -> String {
+        sqlx::query_scalar("select password_hash from accounts where id = $1")
+            .bind(account_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn logging_in_upgrades_bcrypt_hashes_to_argon2id(pool: sqlx::PgPool) {
+        let queries = Queries::new(pool.clone());
+        let account = create_account(&queries).await;
+        set_password_hash(&pool, account.id, &bcrypt::hash(PASSWORD, 4).unwrap()).await;
+
+        for _ in 0..2 {
+            queries
+                .login(LoginParams {
+                    email: account.email.clone(),
+                    password: PASSWORD.to_string(),
+                })
+                .await
+                .map_err(|err| err.to_string())
+                .unwrap();
+
+            let hash = password_hash(&pool, account.id).await;
+            assert!(hash.starts_with("$argon2id$"), "{}", hash);
+        }
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn rehashing_keeps_a_hash_changed_in_the_meantime(pool: sqlx::PgPool) {
+        let queries = Queries::new(pool.clone());
+        let account = create_account(&queries).await;
+        let verified = bcrypt::hash(PASSWORD, 4).unwrap();
+
+        // The password was changed after the login verified the old hash
+        let changed = crate::passwords::hash("a newer password".to_string())
+            .await
+            .unwrap();
+        set_password_hash(&pool, account.id, &changed).await;
+
+        queries
+            .rehash_password(account.id, &verified, PASSWORD.to_string())
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap();
+
+        assert_eq!(password_hash(&pool, account.id).await, changed);
+    }
 }
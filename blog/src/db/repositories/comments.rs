@@ -0,0 +1,35 @@
+use super::{Error, Queries};
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct Comment {
+    pub id: sqlx::types::Uuid,
+    pub account_id: sqlx::types::Uuid,
+    pub post_id: sqlx::types::Uuid,
+    pub contents: String,
+}
+
+impl Queries {
+    #[tracing::instrument(skip_all)]
+    pub async fn create_comment(
+        &self,
+        account_id: sqlx::types::Uuid,
+        post_id: sqlx::types::Uuid,
+        contents: &str,
+    ) -> Result<Comment, Error> {
+        let comment = sqlx::query_as!(
+            Comment,
+            r#"
+            insert into comments (account_id, post_id, contents)
+            values ($1, $2, $3)
+            returning id, account_id, post_id, contents
+            "#,
+            account_id,
+            post_id,
+            contents,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(comment)
+    }
+}
@@ -0,0 +1,27 @@
+use super::{Error, Queries};
+
+impl Queries {
+    #[tracing::instrument(skip_all)]
+    pub async fn ping(&self) -> Result<(), Error> {
+        sqlx::query("select 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Versions of the migrations that have been applied successfully.
+    #[tracing::instrument(skip_all)]
+    pub async fn applied_migrations(&self) -> Result<Vec<i64>, Error> {
+        // The migrations table is managed by sqlx, so it isn't checked at compile time
+        let versions = sqlx::query_scalar::<_, i64>(
+            r#"
+            select version
+            from _sqlx_migrations
+            where success
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+}
@@ -0,0 +1,335 @@
+use super::{Error, Queries};
+
+/// Recent failed logins for one email or IP address.
+pub struct LoginFailures {
+    pub failures: i64,
+    pub last_failure: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub enum LoginReservation {
+    /// The attempt may go ahead, settle it with `settle_login_attempt`.
+    Reserved(sqlx::types::Uuid),
+    /// Seconds until another attempt is allowed.
+    Throttled(i64),
+}
+
+impl Queries {
+    #[tracing::instrument(skip_all)]
+    pub async fn record_login_attempt(
+        &self,
+        email: &str,
+        ip_address: &str,
+        result: &str,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            insert into login_attempts (email, ip_address, result)
+            values ($1, $2, $3)
+            "#,
+            email,
+            ip_address,
+            result,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Starts a login attempt for `email` from `ip_address` unless
+    /// `retry_after` says their recent failures must wait. Attempts for the
+    /// same email or IP address are serialized, and pending ones count as
+    /// failures, so concurrent guesses can't all pass the check. Without an
+    /// email only the IP address is locked and counted.
+    #[tracing::instrument(skip_all)]
+    pub async fn reserve_login_attempt(
+        &self,
+        email: Option<&str>,
+        ip_address: &str,
+        window_secs: i64,
+        retry_after: impl FnOnce(&LoginFailures, &LoginFailures) -> Option<i64>,
+    ) -> Result<LoginReservation, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Always email first, then IP address, so two attempts can't wait on
+        // each other. A null email skips its lock, the functions are strict.
+        sqlx::query!(
+            r#"
+            select
+                pg_advisory_xact_lock(hashtextextended('login_attempts:email:' || $1, 0)),
+                pg_advisory_xact_lock(hashtextextended('login_attempts:ip:' || $2, 0))
+            "#,
+            email,
+            ip_address,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let account_failures = sqlx::query_as!(
+            LoginFailures,
+            r#"
+            select count(*) as "failures!", max(created_at) as last_failure
+            from login_attempts
+            where email = $1
+                and result in ('failure', 'pending')
+                and created_at > now() - make_interval(secs => $2)
+                and created_at > coalesce(
+                    (select max(created_at) from login_attempts where email = $1 and result = 'success'),
+                    '-infinity'
+                )
+            "#,
+            email,
+            window_secs as f64,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // Successes don't reset the count for an IP address, so one valid
+        // account can't unlock guessing at others
+        let ip_failures = sqlx::query_as!(
+            LoginFailures,
+            r#"
+            select count(*) as "failures!", max(created_at) as last_failure
+            from login_attempts
+            where ip_address = $1
+                and result in ('failure', 'pending')
+                and created_at > now() - make_interval(secs => $2)
+            "#,
+            ip_address,
+            window_secs as f64,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let reservation = match retry_after(&account_failures, &ip_failures) {
+            Some(retry_after) => {
+                sqlx::query!(
+                    r#"
+                    insert into login_attempts (email, ip_address, result)
+                    values ($1, $2, 'throttled')
+                    "#,
+                    email,
+                    ip_address,
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                LoginReservation::Throttled(retry_after)
+            }
+            None => {
+                let id = sqlx::query_scalar!(
+                    r#"
+                    insert into login_attempts (email, ip_address, result)
+                    values ($1, $2, 'pending')
+                    returning id
+                    "#,
+                    email,
+                    ip_address,
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+
+                LoginReservation::Reserved(id)
+            }
+        };
+
+        tx.commit().await?;
+
+        Ok(reservation)
+    }
+
+    /// Records a sign-in link request for `email` from `ip_address`, or
+    /// returns the seconds until another is allowed when `max_requests`
+    /// were made for the email within the window. Requests for the same
+    /// email are serialized so concurrent ones can't all get through.
+    #[tracing::instrument(skip_all)]
+    pub async fn record_link_request(
+        &self,
+        email: &str,
+        ip_address: &str,
+        window_secs: i64,
+        max_requests: i64,
+    ) -> Result<Option<i64>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            select pg_advisory_xact_lock(hashtextextended('login_attempts:email:' || $1, 0))
+            "#,
+            email,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // Once over the limit, the oldest request in the window decides when
+        // the next one is allowed
+        let allowed_in = sqlx::query_scalar!(
+            r#"
+            select ceil(extract(epoch from
+                min(created_at) + make_interval(secs => $2) - now()
+            ))::bigint as allowed_in
+            from (
+                select created_at
+                from login_attempts
+                where email = $1
+                    and result = 'link_requested'
+                    and created_at > now() - make_interval(secs => $2)
+                order by created_at desc
+                limit $3
+            ) recent
+            having count(*) >= $3
+            "#,
+            email,
+            window_secs as f64,
+            max_requests,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .flatten();
+
+        let result = if allowed_in.is_some() {
+            "throttled"
+        } else {
+            "link_requested"
+        };
+        sqlx::query!(
+            r#"
+            insert into login_attempts (email, ip_address, result)
+            values ($1, $2, $3)
+            "#,
+            email,
+            ip_address,
+            result,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(allowed_in.map(|secs| secs.max(1)))
+    }
+
+    /// Records how a reserved attempt ended, `success` or `failure`.
+    #[tracing::instrument(skip_all)]
+    pub async fn settle_login_attempt(
+        &self,
+        id: sqlx::types::Uuid,
+        result: &str,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            update login_attempts
+            set result = $1
+            where id = $2 and result = 'pending'
+            "#,
+            result,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Drops a reserved attempt that neither succeeded nor failed, like a
+    /// correct password that still needs its second factor.
+    #[tracing::instrument(skip_all)]
+    pub async fn cancel_login_attempt(&self, id: sqlx::types::Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            delete from login_attempts
+            where id = $1 and result = 'pending'
+            "#,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes attempts that left the throttling window, except failed and
+    /// throttled ones, which stay for the audit retention period.
+    #[tracing::instrument(skip_all)]
+    pub async fn purge_login_attempts(
+        &self,
+        window_secs: i64,
+        audit_retention_secs: i64,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            delete from login_attempts
+            where created_at < now() - make_interval(secs => $1)
+                and (
+                    result not in ('failure', 'throttled')
+                    or created_at < now() - make_interval(secs => $2)
+                )
+            "#,
+            window_secs as f64,
+            audit_retention_secs as f64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn purge_keeps_failures_for_the_audit_period(pool: sqlx::PgPool) {
+        let queries = Queries::new(pool.clone());
+        for (result, age_secs) in [
+            ("success", 60),
+            ("success", 3600),
+            ("link_requested", 3600),
+            ("failure", 60),
+            ("failure", 3600),
+            ("throttled", 3600),
+            ("failure", 3 * 86400),
+            ("throttled", 3 * 86400),
+        ] {
+            sqlx::query(
+                "insert into login_attempts (email, ip_address, result, created_at)
+                values ('someone@example.com', '203.0.113.1', $1, now() - make_interval(secs => $2))",
+            )
+            .bind(result)
+            .bind(age_secs as f64)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        queries
+            .purge_login_attempts(15 * 60, 2 * 86400)
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap();
+
+        let mut kept: Vec<(String, f64)> = sqlx::query_as(
+            "select result, extract(epoch from now() - created_at)::float8 from login_attempts",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        kept.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let kept: Vec<_> = kept
+            .into_iter()
+            .map(|(result, age)| (result, age.round() as i64))
+            .collect();
+
+        assert_eq!(
+            kept,
+            [
+                ("failure".to_string(), 60),
+                ("failure".to_string(), 3600),
+                ("success".to_string(), 60),
+                ("throttled".to_string(), 3600),
+            ]
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use super::{Error, Queries};
+
+impl Queries {
+    /// Stores a sign-in link by the hash of its token and clears out
+    /// expired ones.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_magic_link(
+        &self,
+        account_id: sqlx::types::Uuid,
+        token_hash: &str,
+        browser_hash: Option<&str>,
+        lifetime_secs: i64,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            delete from magic_links
+            where expires_at < now()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            insert into magic_links (account_id, token_hash, browser_hash, expires_at)
+            values ($1, $2, $3, now() + make_interval(secs => $4))
+            "#,
+            account_id,
+            token_hash,
+            browser_hash,
+            lifetime_secs as f64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks a link as used and returns its account. Links that are used,
+    /// expired or bound to another browser return None and stay as they are.
+    #[tracing::instrument(skip_all)]
+    pub async fn consume_magic_link(
+        &self,
+        token_hash: &str,
+        browser_hash: Option<&str>,
+    ) -> Result<Option<sqlx::types::Uuid>, Error> {
+        let account_id = sqlx::query_scalar!(
+            r#"
+            update magic_links
+            set used_at = now()
+            where token_hash = $1
+                and used_at is null
+                and expires_at > now()
+                and (browser_hash is null or browser_hash = $2)
+            returning account_id
+            "#,
+            token_hash,
+            browser_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(account_id)
+    }
+}
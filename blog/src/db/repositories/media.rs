@@ -0,0 +1,316 @@
+use super::{Error, Queries};
+
+#[derive(sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
+pub struct Media {
+    pub id: sqlx::types::Uuid,
+    pub account_id: sqlx::types::Uuid,
+    pub content_hash: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub file_name: String,
+}
+
+pub struct CreateMediaParams {
+    pub content_hash: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub file_name: String,
+}
+
+pub enum CreatedMedia {
+    Created(Media),
+    /// The account had uploaded the same file before.
+    Existing(Media),
+    QuotaExceeded,
+}
+
+impl Queries {
+    /// Records an upload unless it would take the account over `quota_bytes`.
+    /// The account row is locked while checking so concurrent uploads can't
+    /// both fit under the quota, and a file the account already uploaded
+    /// returns the existing media.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_media(
+        &self,
+        account_id: sqlx::types::Uuid,
+        params: CreateMediaParams,
+        quota_bytes: i64,
+    ) -> Result<CreatedMedia, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Locked in its own statement, so the sum below sees uploads
+        // committed while this one waited for the lock
+        sqlx::query!(
+            r#"
+            select id from accounts
+            where id = $1
+            for update
+            "#,
+            account_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let usage = sqlx::query_scalar!(
+            r#"
+            select coalesce(sum(size_bytes), 0)::bigint as "usage!"
+            from media
+            where account_id = $1 and deleted_at is null
+            "#,
+            account_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let inserted = sqlx::query_as!(
+            Media,
+            r#"
+            insert into media (account_id, content_hash, content_type, size_bytes, file_name)
+            select $1, $2, $3, $4, $5
+            where $6
+            on conflict (account_id, content_hash) where deleted_at is null do nothing
+            returning id, account_id, content_hash, content_type, size_bytes, file_name
+            "#,
+            account_id,
+            params.content_hash,
+            params.content_type,
+            params.size_bytes,
+            params.file_name,
+            usage + params.size_bytes <= quota_bytes,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let created = match inserted {
+            Some(media) => CreatedMedia::Created(media),
+            None => sqlx::query_as!(
+                Media,
+                r#"
+                select id, account_id, content_hash, content_type, size_bytes, file_name
+                from media
+                where account_id = $1 and content_hash = $2 and deleted_at is null
+                "#,
+                account_id,
+                params.content_hash,
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .map_or(CreatedMedia::QuotaExceeded, CreatedMedia::Existing),
+        };
+
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_media(&self, id: sqlx::types::Uuid) -> Result<Option<Media>, Error> {
+        let media = sqlx::query_as!(
+            Media,
+            r#"
+            select id, account_id, content_hash, content_type, size_bytes, file_name
+            from media
+            where id = $1 and deleted_at is null
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(media)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn find_media_by_hash(
+        &self,
+        account_id: sqlx::types::Uuid,
+        content_hash: &str,
+    ) -> Result<Option<Media>, Error> {
+        let media = sqlx::query_as!(
+            Media,
+            r#"
+            select id, account_id, content_hash, content_type, size_bytes, file_name
+            from media
+            where account_id = $1 and content_hash = $2 and deleted_at is null
+            "#,
+            account_id,
+            content_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(media)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn media_usage(&self, account_id: sqlx::types::Uuid) -> Result<i64, Error> {
+        let usage = sqlx::query_scalar!(
+            r#"
+            select coalesce(sum(size_bytes), 0)::bigint as "usage!"
+            from media
+            where account_id = $1 and deleted_at is null
+            "#,
+            account_id,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+}
+
+/// Links the account's media referenced by a post to it, and unlinks media
+/// the post no longer references. An upload can be linked to any number of
+/// its author's posts. Runs in the caller's transaction, next to the write
+/// that changed the post.
+pub(super) async fn attach_media(
+    executor: &mut sqlx::PgConnection,
+    account_id: sqlx::types::Uuid,
+    post_id: sqlx::types::Uuid,
+    media_ids: &[sqlx::types::Uuid],
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+        delete from post_media
+        where post_id = $1 and media_id <> all($2)
+        "#,
+        post_id,
+        media_ids,
+    )
+    .execute(&mut *executor)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        insert into post_media (post_id, media_id)
+        select $2, id
+        from media
+        where account_id = $1 and deleted_at is null and id = any($3)
+        on conflict (post_id, media_id) do nothing
+        "#,
+        account_id,
+        post_id,
+        media_ids,
+    )
+    .execute(&mut *executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_account(queries: &Queries) -> sqlx::types::Uuid {
+        queries
+            .create_account(&super::super::accounts::CreateAccountParams {
+                email: "someone@example.com".to_string(),
+                password: "correct horse battery".to_string(),
+                account_name: "someone".to_string(),
+            })
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap()
+            .id
+    }
+
+    fn params(content_hash: &str, size_bytes: i64) -> CreateMediaParams {
+        CreateMediaParams {
+            content_hash: content_hash.to_string(),
+            content_type: "image/png".to_string(),
+            size_bytes,
+            file_name: "image.png".to_string(),
+        }
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn concurrent_uploads_cannot_exceed_the_quota(pool: sqlx::PgPool) {
+        let queries = Queries::new(pool);
+        let account_id = create_account(&queries).await;
+
+        let uploads: Vec<_> = (0..4)
+            .map(|i| {
+                let queries = queries.clone();
+                tokio::spawn(async move {
+                    queries
+                        .create_media(account_id, params(&format!("hash-{}", i), 60), 100)
+                        .await
+                        .map_err(|err| err.to_string())
+                        .unwrap()
+                })
+            })
+            .collect();
+        let mut created = 0;
+        for upload in uploads {
+            if let CreatedMedia::Created(_) = upload.await.unwrap() {
+                created += 1;
+            }
+        }
+
+        assert_eq!(created, 1);
+        assert_eq!(
+            queries
+                .media_usage(account_id)
+                .await
+                .map_err(|err| err.to_string())
+                .unwrap(),
+            60
+        );
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn an_upload_can_be_shown_in_several_posts(pool: sqlx::PgPool) {
+        let queries = Queries::new(pool.clone());
+        let account_id = create_account(&queries).await;
+        let CreatedMedia::Created(media) = queries
+            .create_media(account_id, params("hash", 60), 100)
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap()
+        else {
+            panic!("the upload wasn't created");
+        };
+
+        let mut posts = Vec::new();
+        for _ in 0..2 {
+            let post = queries
+                .create_post(
+                    account_id,
+                    super::super::posts::CreatePostParams {
+                        content: String::new(),
+                    },
+                    "",
+                    &[media.id],
+                )
+                .await
+                .map_err(|err| err.to_string())
+                .unwrap();
+            posts.push(post);
+        }
+
+        let linked = || async {
+            let mut linked: Vec<sqlx::types::Uuid> =
+                sqlx::query_scalar("select post_id from post_media where media_id = $1")
+                    .bind(media.id)
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+            linked.sort();
+            linked
+        };
+        let mut both = vec![posts[0].id, posts[1].id];
+        both.sort();
+        assert_eq!(linked().await, both);
+
+        // Dropping the image from one post keeps it linked to the other
+        queries
+            .reindex_post(&posts[0], "", &[])
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap();
+        assert_eq!(linked().await, [posts[1].id]);
+    }
+}
@@ -1,5 +1,16 @@
+pub mod access_tokens;
 pub mod accounts;
+pub mod comments;
+pub mod health;
+pub mod login_attempts;
+pub mod magic_links;
+pub mod media;
+pub mod passkeys;
 pub mod posts;
+pub mod rate_limits;
+pub mod signing_keys;
+pub mod sitemap;
+pub mod totp;
 pub mod utils;
 
 #[derive(Clone)]
@@ -13,21 +24,29 @@ impl Queries {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+/// How a failed query is reported in `{"error": ...}` bodies.
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = QueryError)]
+// The variant names are part of the serialized error bodies
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     SqlxError(String),
     DatabaseError(String),
     LoginError(String),
 }
 
-impl From<sqlx::Error> for Error {
-    fn from(value: sqlx::Error) -> Self {
-        Error::SqlxError(value.to_string())
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::SqlxError(err) | Error::DatabaseError(err) | Error::LoginError(err) => {
+                write!(f, "{}", err)
+            }
+        }
     }
 }
 
-impl From<bcrypt::BcryptError> for Error {
-    fn from(value: bcrypt::BcryptError) -> Self {
-        Error::DatabaseError(value.to_string())
+impl From<sqlx::Error> for Error {
+    fn from(value: sqlx::Error) -> Self {
+        Error::SqlxError(value.to_string())
     }
 }
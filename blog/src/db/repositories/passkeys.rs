@@ -0,0 +1,274 @@
+use super::{Error, Queries};
+
+#[derive(sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
+pub struct Passkey {
+    pub id: sqlx::types::Uuid,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub name: String,
+    pub transports: Vec<String>,
+}
+
+/// Identifies a credential to the browser, for `excludeCredentials`.
+pub struct PasskeyDescriptor {
+    pub credential_id: Vec<u8>,
+    pub transports: Vec<String>,
+}
+
+/// A credential with what's needed to check an assertion made with it.
+pub struct PasskeyCredential {
+    pub id: sqlx::types::Uuid,
+    pub account_id: sqlx::types::Uuid,
+    pub email: String,
+    pub public_key: Vec<u8>,
+    pub algorithm: i64,
+    pub sign_count: i64,
+}
+
+pub enum CreatedPasskey {
+    Created(Passkey),
+    /// Some account already registered the credential.
+    AlreadyRegistered,
+}
+
+pub struct PasskeyChallenge {
+    pub account_id: Option<sqlx::types::Uuid>,
+    pub challenge: Vec<u8>,
+}
+
+impl Queries {
+    /// Stores a challenge for a ceremony and clears out expired ones.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_passkey_challenge(
+        &self,
+        account_id: Option<sqlx::types::Uuid>,
+        purpose: &str,
+        challenge: &[u8],
+        lifetime_secs: i64,
+    ) -> Result<sqlx::types::Uuid, Error> {
+        sqlx::query!(
+            r#"
+            delete from passkey_challenges
+            where created_at < now() - make_interval(secs => $1)
+            "#,
+            lifetime_secs as f64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let id = sqlx::query_scalar!(
+            r#"
+            insert into passkey_challenges (account_id, purpose, challenge)
+            values ($1, $2, $3)
+            returning id
+            "#,
+            account_id,
+            purpose,
+            challenge,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Removes and returns a challenge, so each one answers one ceremony.
+    #[tracing::instrument(skip_all)]
+    pub async fn take_passkey_challenge(
+        &self,
+        id: sqlx::types::Uuid,
+        purpose: &str,
+        lifetime_secs: i64,
+    ) -> Result<Option<PasskeyChallenge>, Error> {
+        let challenge = sqlx::query_as!(
+            PasskeyChallenge,
+            r#"
+            delete from passkey_challenges
+            where id = $1 and purpose = $2 and created_at > now() - make_interval(secs => $3)
+            returning account_id, challenge
+            "#,
+            id,
+            purpose,
+            lifetime_secs as f64,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn create_passkey(
+        &self,
+        account_id: sqlx::types::Uuid,
+        name: &str,
+        credential: &crate::webauthn::RegisteredCredential,
+    ) -> Result<CreatedPasskey, Error> {
+        let passkey = sqlx::query_as!(
+            Passkey,
+            r#"
+            insert into passkeys (account_id, name, credential_id, public_key, algorithm, sign_count, transports)
+            values ($1, $2, $3, $4, $5, $6, $7)
+            returning id, created_at, last_used_at, name, transports
+            "#,
+            account_id,
+            name,
+            credential.credential_id,
+            credential.public_key,
+            credential.algorithm,
+            credential.sign_count,
+            &credential.transports,
+        )
+        .fetch_one(&self.pool)
+        .await;
+
+        match passkey {
+            Ok(passkey) => Ok(CreatedPasskey::Created(passkey)),
+            Err(err)
+                if err.as_database_error().is_some_and(|db_err| {
+                    db_err.is_unique_violation()
+                        && db_err.constraint() == Some("passkeys_credential_id_unique")
+                }) =>
+            {
+                Ok(CreatedPasskey::AlreadyRegistered)
+            }
+            Err(err) => Err(Error::from(err)),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list_passkeys(
+        &self,
+        account_id: sqlx::types::Uuid,
+    ) -> Result<Vec<Passkey>, Error> {
+        let passkeys = sqlx::query_as!(
+            Passkey,
+            r#"
+            select id, created_at, last_used_at, name, transports
+            from passkeys
+            where account_id = $1
+            order by created_at
+            "#,
+            account_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(passkeys)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn passkey_descriptors(
+        &self,
+        account_id: sqlx::types::Uuid,
+    ) -> Result<Vec<PasskeyDescriptor>, Error> {
+        let descriptors = sqlx::query_as!(
+            PasskeyDescriptor,
+            r#"
+            select credential_id, transports
+            from passkeys
+            where account_id = $1
+            "#,
+            account_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(descriptors)
+    }
+
+    /// Looks up a credential, only if its account can still log in.
+    #[tracing::instrument(skip_all)]
+    pub async fn find_passkey_credential(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<Option<PasskeyCredential>, Error> {
+        let credential = sqlx::query_as!(
+            PasskeyCredential,
+            r#"
+            select passkeys.id, passkeys.account_id, accounts.email, passkeys.public_key,
+                passkeys.algorithm, passkeys.sign_count
+            from passkeys
+            join accounts on accounts.id = passkeys.account_id
+            where passkeys.credential_id = $1
+                and accounts.deleted_at is null
+                and accounts.disabled_at is null
+            "#,
+            credential_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    /// Stores the new signature counter. Returns false when another login
+    /// with the same counter got there first.
+    #[tracing::instrument(skip_all)]
+    pub async fn use_passkey(
+        &self,
+        id: sqlx::types::Uuid,
+        previous_sign_count: i64,
+        sign_count: i64,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            update passkeys
+            set last_used_at = now(), sign_count = $1
+            where id = $2 and sign_count = $3
+            "#,
+            sign_count,
+            id,
+            previous_sign_count,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn rename_passkey(
+        &self,
+        account_id: sqlx::types::Uuid,
+        id: sqlx::types::Uuid,
+        name: &str,
+    ) -> Result<Option<Passkey>, Error> {
+        let passkey = sqlx::query_as!(
+            Passkey,
+            r#"
+            update passkeys
+            set updated_at = now(), name = $1
+            where account_id = $2 and id = $3
+            returning id, created_at, last_used_at, name, transports
+            "#,
+            name,
+            account_id,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(passkey)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn delete_passkey(
+        &self,
+        account_id: sqlx::types::Uuid,
+        id: sqlx::types::Uuid,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            delete from passkeys
+            where account_id = $1 and id = $2
+            "#,
+            account_id,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -1,14 +1,27 @@
 use super::{Error, Queries, utils::ListParams, utils::total_pages};
 
-#[derive(sqlx::FromRow, serde::Serialize)]
+#[derive(sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
 pub struct Post {
     pub id: sqlx::types::Uuid,
     pub account_id: sqlx::types::Uuid,
     pub account_name: String,
+    /// The HTML as written, what an editor should load.
     pub contents: String,
+    /// `contents` prepared for display, with `srcset` on uploaded images.
+    pub rendered_contents: String,
 }
 
-#[derive(serde::Serialize)]
+pub struct PostCard {
+    pub id: sqlx::types::Uuid,
+    pub account_name: String,
+    /// Storage key of the author's photo, when `photo_identifier` names one
+    /// of their uploads.
+    pub photo_hash: Option<String>,
+    pub contents: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
 pub struct PostsList {
     pub data: Vec<Post>,
     pub total: i64,
@@ -17,21 +30,22 @@ pub struct PostsList {
     pub page_total: i64,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct CreatePostParams {
     pub content: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct UpdatePostParams {
     pub contents: String,
 }
 
 impl Queries {
+    #[tracing::instrument(skip_all)]
     async fn list_posts_data(&self, params: &ListParams) -> Result<Vec<Post>, Error> {
         let mut query = sqlx::QueryBuilder::new(
             r#"
-            select p.id, p.account_id, a.account_name, p.contents
+            select p.id, p.account_id, a.account_name, p.contents, p.rendered_contents
             from posts p
             join accounts a on p.account_id = a.id
             where p.deleted_at is null and a.deleted_at is null
@@ -50,6 +64,12 @@ impl Queries {
                     query.push("p.contents like ");
                     query.push_bind(format!("%%{}%%", value));
                 }
+                "account_id" => {
+                    let account_id = sqlx::types::Uuid::parse_str(value)
+                        .map_err(|_| Error::DatabaseError("invalid filter".to_string()))?;
+                    query.push("p.account_id = ");
+                    query.push_bind(account_id);
+                }
                 _ => {
                     return Err(Error::DatabaseError("invalid filter".to_string()));
                 }
@@ -90,6 +110,7 @@ impl Queries {
         Ok(posts)
     }
 
+    #[tracing::instrument(skip_all)]
     async fn list_posts_total(&self, params: &ListParams) -> Result<i64, Error> {
         let mut query = sqlx::QueryBuilder::new(
             r#"
@@ -112,6 +133,12 @@ impl Queries {
                     query.push("p.contents like ");
                     query.push_bind(format!("%%{}%%", value));
                 }
+                "account_id" => {
+                    let account_id = sqlx::types::Uuid::parse_str(value)
+                        .map_err(|_| Error::DatabaseError("invalid filter".to_string()))?;
+                    query.push("p.account_id = ");
+                    query.push_bind(account_id);
+                }
                 _ => {
                     return Err(Error::DatabaseError("invalid filter".to_string()));
                 }
@@ -126,6 +153,7 @@ impl Queries {
         Ok(count)
     }
 
+    #[tracing::instrument(skip_all)]
     pub async fn list_posts(&self, params: ListParams) -> Result<PostsList, Error> {
         let data = self.list_posts_data(&params).await?;
         let total = self.list_posts_total(&params).await?;
@@ -139,11 +167,12 @@ impl Queries {
         })
     }
 
+    #[tracing::instrument(skip_all)]
     pub async fn get_post(&self, id: sqlx::types::Uuid) -> Result<Post, Error> {
         let post = sqlx::query_as!(
             Post,
             r#"
-            select p.id, p.account_id, a.account_name, p.contents
+            select p.id, p.account_id, a.account_name, p.contents, p.rendered_contents
             from posts p
             join accounts a on p.account_id = a.id
             where p.id = $1 and p.deleted_at is null and a.deleted_at is null
@@ -152,68 +181,139 @@ impl Queries {
         )
         .fetch_one(&self.pool)
         .await
-        .map_err(|err| Error::from(err))?;
+        .map_err(Error::from)?;
 
         Ok(post)
     }
 
+    #[tracing::instrument(skip_all)]
+    pub async fn find_post(&self, id: sqlx::types::Uuid) -> Result<Option<Post>, Error> {
+        let post = sqlx::query_as!(
+            Post,
+            r#"
+            select p.id, p.account_id, a.account_name, p.contents, p.rendered_contents
+            from posts p
+            join accounts a on p.account_id = a.id
+            where p.id = $1 and p.deleted_at is null and a.deleted_at is null
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(post)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_post_card(&self, id: sqlx::types::Uuid) -> Result<Option<PostCard>, Error> {
+        let card = sqlx::query_as!(
+            PostCard,
+            r#"
+            select p.id, a.account_name, m.content_hash as "photo_hash?", p.contents,
+                greatest(p.updated_at, a.updated_at) as "updated_at!"
+            from posts p
+            join accounts a on p.account_id = a.id
+            left join media m
+                on m.id::text = a.photo_identifier
+                and m.account_id = a.id
+                and m.deleted_at is null
+            where p.id = $1 and p.deleted_at is null and a.deleted_at is null
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(card)
+    }
+
+    #[tracing::instrument(skip_all)]
     pub async fn create_post(
         &self,
         account_id: sqlx::types::Uuid,
         params: CreatePostParams,
+        rendered_contents: &str,
+        media_ids: &[sqlx::types::Uuid],
     ) -> Result<Post, Error> {
+        let mut tx = self.pool.begin().await?;
+
         let post = sqlx::query_as!(
             Post,
             r#"
             with inserted_post as (
-                insert into posts (account_id, contents)
-                values ($1, $2)
-                returning id, account_id, contents
+                insert into posts (account_id, contents, rendered_contents)
+                values ($1, $2, $3)
+                returning id, account_id, contents, rendered_contents
             )
-            select p.id, p.account_id, a.account_name, p.contents
+            select p.id, p.account_id, a.account_name, p.contents, p.rendered_contents
             from inserted_post p
             join accounts a on p.account_id = a.id
             "#,
             account_id,
             params.content,
+            rendered_contents,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await
-        .map_err(|err| Error::from(err))?;
+        .map_err(Error::from)?;
+
+        super::media::attach_media(&mut tx, account_id, post.id, media_ids).await?;
+
+        tx.commit().await?;
 
         Ok(post)
     }
 
+    /// Updates a post written by `account_id`, or any post when the account is
+    /// an admin.
+    #[tracing::instrument(skip_all)]
     pub async fn update_post(
         &self,
         account_id: sqlx::types::Uuid,
         id: sqlx::types::Uuid,
         params: UpdatePostParams,
+        rendered_contents: &str,
+        media_ids: &[sqlx::types::Uuid],
     ) -> Result<Post, Error> {
+        let mut tx = self.pool.begin().await?;
+
         let post = sqlx::query_as!(
             Post,
             r#"
             with updated_post as (
                 update posts
-                set updated_at = now(), contents = $1
-                where deleted_at is null and account_id = $2 and id = $3
-                returning id, account_id, contents
+                set updated_at = now(), contents = $1, rendered_contents = $2
+                where deleted_at is null
+                    and id = $4
+                    and (account_id = $3 or exists (
+                        select 1 from accounts where id = $3 and role = 'admin'
+                    ))
+                returning id, account_id, contents, rendered_contents
             )
-            select p.id, p.account_id, a.account_name, p.contents
+            select p.id, p.account_id, a.account_name, p.contents, p.rendered_contents
             from updated_post p
             join accounts a on p.account_id = a.id
             "#,
             params.contents,
+            rendered_contents,
             account_id,
             id,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await
-        .map_err(|err| Error::from(err))?;
+        .map_err(Error::from)?;
+
+        // An admin's edit still only attaches the author's uploads
+        super::media::attach_media(&mut tx, post.account_id, post.id, media_ids).await?;
+
+        tx.commit().await?;
 
         Ok(post)
     }
 
+    /// Deletes a post written by `account_id`, or any post when the account is
+    /// an admin.
+    #[tracing::instrument(skip_all)]
     pub async fn delete_post(
         &self,
         account_id: sqlx::types::Uuid,
@@ -223,7 +323,10 @@ impl Queries {
             r#"
             update posts
             set deleted_at = now()
-            where account_id = $1 and id = $2
+            where id = $2
+                and (account_id = $1 or exists (
+                    select 1 from accounts where id = $1 and role = 'admin'
+                ))
             "#,
             account_id,
             id,
@@ -237,4 +340,53 @@ impl Queries {
 
         Ok(())
     }
+
+    /// Every live post, oldest first, for maintenance tasks.
+    #[tracing::instrument(skip_all)]
+    pub async fn all_posts(&self) -> Result<Vec<Post>, Error> {
+        let posts = sqlx::query_as!(
+            Post,
+            r#"
+            select p.id, p.account_id, a.account_name, p.contents, p.rendered_contents
+            from posts p
+            join accounts a on p.account_id = a.id
+            where p.deleted_at is null
+            order by p.created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(posts)
+    }
+
+    /// Stores freshly rendered HTML and media links for a post, without
+    /// counting as an edit.
+    #[tracing::instrument(skip_all)]
+    pub async fn reindex_post(
+        &self,
+        post: &Post,
+        rendered_contents: &str,
+        media_ids: &[sqlx::types::Uuid],
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            update posts
+            set rendered_contents = $1
+            where id = $2
+            "#,
+            rendered_contents,
+            post.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        super::media::attach_media(&mut tx, post.account_id, post.id, media_ids).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,47 @@
+use super::{Error, Queries};
+
+impl Queries {
+    /// Counts a request against `key` in the current fixed window, returning
+    /// the count so far and the seconds until the window ends.
+    #[tracing::instrument(skip_all)]
+    pub async fn hit_rate_limit(&self, key: &str, window_secs: f64) -> Result<(i64, f64), Error> {
+        let hit = sqlx::query!(
+            r#"
+            insert into rate_limits (key, window_start, window_secs, count)
+            values ($1, to_timestamp(floor(extract(epoch from now())::float8 / $2::float8) * $2::float8), $2::float8, 1)
+            on conflict (key) do update
+            set count = case
+                    when rate_limits.window_start = excluded.window_start
+                        and rate_limits.window_secs = excluded.window_secs
+                    then rate_limits.count + 1
+                    else 1
+                end,
+                window_start = excluded.window_start,
+                window_secs = excluded.window_secs
+            returning
+                count,
+                extract(epoch from (window_start + make_interval(secs => window_secs) - now()))::float8 as "reset_after_secs!"
+            "#,
+            key,
+            window_secs,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((hit.count, hit.reset_after_secs))
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn purge_rate_limits(&self) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            delete from rate_limits
+            where window_start + make_interval(secs => window_secs) < now()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
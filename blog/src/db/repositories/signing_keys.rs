@@ -0,0 +1,155 @@
+use super::{Error, Queries};
+
+/// A key tokens are signed or checked with. `public_jwk` is the JSON web key
+/// without its `kid`, which is the id.
+pub struct SigningKey {
+    pub id: sqlx::types::Uuid,
+    pub activated_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub retired_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub algorithm: String,
+    pub private_key: Vec<u8>,
+    pub public_jwk: String,
+}
+
+pub struct SigningKeySummary {
+    pub id: sqlx::types::Uuid,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub activated_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub retired_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub algorithm: String,
+}
+
+impl Queries {
+    /// Keys that tokens may still be signed with: the active one, pending ones
+    /// and retired ones whose tokens haven't all expired.
+    #[tracing::instrument(skip_all)]
+    pub async fn verification_keys(&self) -> Result<Vec<SigningKey>, Error> {
+        let keys = sqlx::query_as!(
+            SigningKey,
+            r#"
+            select id, activated_at, retired_at, algorithm, private_key, public_jwk
+            from signing_keys
+            where expires_at is null or expires_at > now()
+            order by created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list_signing_keys(&self) -> Result<Vec<SigningKeySummary>, Error> {
+        let keys = sqlx::query_as!(
+            SigningKeySummary,
+            r#"
+            select id, created_at, activated_at, retired_at, expires_at, algorithm
+            from signing_keys
+            order by created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    /// Stores a pending key. It is published for verification right away but
+    /// only signs once activated.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_signing_key(
+        &self,
+        algorithm: &str,
+        private_key: &[u8],
+        public_jwk: &str,
+    ) -> Result<sqlx::types::Uuid, Error> {
+        let id = sqlx::query_scalar!(
+            r#"
+            insert into signing_keys (algorithm, private_key, public_jwk)
+            values ($1, $2, $3)
+            returning id
+            "#,
+            algorithm,
+            private_key,
+            public_jwk,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// The newest key that was generated but never activated.
+    #[tracing::instrument(skip_all)]
+    pub async fn pending_signing_key(&self) -> Result<Option<SigningKeySummary>, Error> {
+        let key = sqlx::query_as!(
+            SigningKeySummary,
+            r#"
+            select id, created_at, activated_at, retired_at, expires_at, algorithm
+            from signing_keys
+            where activated_at is null and expires_at is null
+            order by created_at desc
+            limit 1
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    /// Makes a pending key the one that signs. The previous one is retired
+    /// and keeps verifying for `grace_secs`, so the tokens it signed run out
+    /// first. Keys past their grace period are deleted. Returns false when
+    /// the key isn't pending.
+    #[tracing::instrument(skip_all)]
+    pub async fn activate_signing_key(
+        &self,
+        id: sqlx::types::Uuid,
+        grace_secs: i64,
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            update signing_keys
+            set retired_at = now(), expires_at = now() + make_interval(secs => $1)
+            where activated_at is not null and retired_at is null
+            "#,
+            grace_secs as f64,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let activated = sqlx::query!(
+            r#"
+            update signing_keys
+            set activated_at = now()
+            where id = $1 and activated_at is null and expires_at is null
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if activated.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            delete from signing_keys
+            where expires_at < now()
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Error, Queries};
+
+#[derive(sqlx::FromRow)]
+pub struct SitemapEntry {
+    pub kind: String,
+    pub id: sqlx::types::Uuid,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Queries {
+    #[tracing::instrument(skip_all)]
+    pub async fn sitemap_total(&self) -> Result<i64, Error> {
+        let total = sqlx::query_scalar!(
+            r#"
+            select
+                (select count(p.id)
+                 from posts p
+                 join accounts a on p.account_id = a.id
+                 where p.deleted_at is null and a.deleted_at is null)
+                +
+                (select count(distinct a.id)
+                 from accounts a
+                 join posts p on p.account_id = a.id
+                 where p.deleted_at is null and a.deleted_at is null)
+                as "total!"
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(total)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn sitemap_entries(
+        &self,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<SitemapEntry>, Error> {
+        let entries = sqlx::query_as!(
+            SitemapEntry,
+            r#"
+            select kind as "kind!", id as "id!", updated_at as "updated_at!"
+            from (
+                select 'post' as kind, p.id, p.updated_at
+                from posts p
+                join accounts a on p.account_id = a.id
+                where p.deleted_at is null and a.deleted_at is null
+                union all
+                select 'author' as kind, a.id, greatest(a.updated_at, max(p.updated_at)) as updated_at
+                from accounts a
+                join posts p on p.account_id = a.id
+                where p.deleted_at is null and a.deleted_at is null
+                group by a.id
+            ) entries
+            order by kind desc, id
+            limit $1
+            offset $2
+            "#,
+            limit,
+            offset,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,183 @@
+use super::{Error, Queries};
+
+pub struct TotpState {
+    pub secret: Option<String>,
+    pub enabled: bool,
+    pub last_step: Option<i64>,
+}
+
+impl Queries {
+    #[tracing::instrument(skip_all)]
+    pub async fn totp_state(&self, account_id: sqlx::types::Uuid) -> Result<TotpState, Error> {
+        let state = sqlx::query_as!(
+            TotpState,
+            r#"
+            select totp_secret as secret,
+                totp_enabled_at is not null as "enabled!",
+                totp_last_step as last_step
+            from accounts
+            where deleted_at is null and disabled_at is null and id = $1
+            "#,
+            account_id,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(state)
+    }
+
+    /// Stores a secret awaiting confirmation. Returns false when 2FA is
+    /// already enabled, the secret of an enabled account is never replaced.
+    #[tracing::instrument(skip_all)]
+    pub async fn start_totp_enrollment(
+        &self,
+        account_id: sqlx::types::Uuid,
+        secret: &str,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            update accounts
+            set updated_at = now(), totp_secret = $1, totp_last_step = null
+            where deleted_at is null and totp_enabled_at is null and id = $2
+            "#,
+            secret,
+            account_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Turns on 2FA for the pending secret and replaces the recovery codes.
+    /// Returns false when there was nothing pending.
+    #[tracing::instrument(skip_all)]
+    pub async fn enable_totp(
+        &self,
+        account_id: sqlx::types::Uuid,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            update accounts
+            set updated_at = now(), totp_enabled_at = now(), totp_last_step = $1
+            where deleted_at is null
+                and totp_enabled_at is null
+                and totp_secret is not null
+                and id = $2
+            "#,
+            step,
+            account_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            delete from recovery_codes
+            where account_id = $1
+            "#,
+            account_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            insert into recovery_codes (account_id, code_hash)
+            select $1, unnest($2::text[])
+            "#,
+            account_id,
+            recovery_code_hashes,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Marks `step` as used. Returns false when it or a later step was used
+    /// already, so concurrent logins can't share a code.
+    #[tracing::instrument(skip_all)]
+    pub async fn use_totp_step(
+        &self,
+        account_id: sqlx::types::Uuid,
+        step: i64,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            update accounts
+            set totp_last_step = $1
+            where id = $2 and (totp_last_step is null or totp_last_step < $1)
+            "#,
+            step,
+            account_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Spends a recovery code. Returns false when it doesn't exist or was
+    /// used already.
+    #[tracing::instrument(skip_all)]
+    pub async fn use_recovery_code(
+        &self,
+        account_id: sqlx::types::Uuid,
+        code_hash: &str,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            update recovery_codes
+            set used_at = now()
+            where account_id = $1 and code_hash = $2 and used_at is null
+            "#,
+            account_id,
+            code_hash,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn disable_totp(&self, account_id: sqlx::types::Uuid) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            update accounts
+            set updated_at = now(), totp_secret = null, totp_enabled_at = null, totp_last_step = null
+            where id = $1
+            "#,
+            account_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            delete from recovery_codes
+            where account_id = $1
+            "#,
+            account_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+import { Alert, Button, Stack, TextInput } from "@mantine/core";
+import { useForm } from "@mantine/form";
+import { IconAlertCircle, IconShieldLock } from "@tabler/icons-react";
+import { useNavigate } from "@tanstack/react-router";
+import { useMutation } from "@tanstack/react-query";
+import useApi from "@/hooks/useApi";
+import { useAuth, type Session } from "@/store/auth";
+
+// Accounts with 2FA get a challenge instead of a token
+export interface MfaChallenge {
+  mfa_required: true;
+  mfa_token: string;
+}
+
+export type LoginResult = Session | MfaChallenge;
+
+interface MfaValues {
+  code: string;
+}
+
+interface MfaFormProps {
+  mfaToken: string;
+  onCancel: () => void;
+}
+
+// Second login step, trades the challenge and a code for a session
+export function MfaForm({ mfaToken, onCancel }: MfaFormProps) {
+  const api = useApi();
+  const auth = useAuth();
+  const navigate = useNavigate();
+
+  const mfaMutation = useMutation<Session, Error, MfaValues>({
+    mutationFn: async (values: MfaValues) => {
+      let res = await api.post<Session>("/api/login/mfa", {
+        mfa_token: mfaToken,
+        code: values.code,
+      });
+      return res;
+    },
+    onSuccess: (data) => {
+      auth.login(data);
+      navigate({ to: "/" });
+    },
+  });
+
+  const form = useForm<MfaValues>({
+    initialValues: {
+      code: "",
+    },
+    validate: {
+      code: (value) => (value.trim() ? null : "Code is required"),
+    },
+  });
+
+  return (
+    <>
+      {mfaMutation.error && (
+        <Alert
+          icon={<IconAlertCircle size="1rem" />}
+          title="Login Error"
+          color="red"
+          variant="light"
+        >
+          {mfaMutation.error instanceof Error
+            ? mfaMutation.error.message
+            : String(mfaMutation.error)}
+        </Alert>
+      )}
+
+      <form
+        onSubmit={form.onSubmit((values) => mfaMutation.mutateAsync(values))}
+      >
+        <Stack gap="md">
+          <TextInput
+            label="Authentication code"
+            description="The 6 digit code from your authenticator app, or a recovery code"
+            placeholder="123456"
+            leftSection={<IconShieldLock size={16} />}
+            autoComplete="one-time-code"
+            autoFocus
+            required
+            {...form.getInputProps("code")}
+          />
+
+          <Button
+            type="submit"
+            fullWidth
+            mt="md"
+            disabled={mfaMutation.isPending}
+          >
+            {mfaMutation.isPending ? "Verifying..." : "Verify"}
+          </Button>
+
+          <Button variant="subtle" onClick={onCancel}>
+            Back
+          </Button>
+        </Stack>
+      </form>
+    </>
+  );
+}
+
+export default MfaForm;
@@ -14,7 +14,10 @@ import { Route as MainIndexRouteImport } from './routes/_main.index'
 import { Route as MainRegisterRouteImport } from './routes/_main.register'
 import { Route as MainLoginRouteImport } from './routes/_main.login'
 import { Route as MainCreateRouteImport } from './routes/_main.create'
-import { Route as MainPostIdEditRouteImport } from './routes/_main.$postId.edit'
+import { Route as MainLoginMagicRouteImport } from './routes/_main.login_.magic'
+import { Route as MainAuthorsAccountIdRouteImport } from './routes/_main.authors.$accountId'
+import { Route as MainPostsPostIdIndexRouteImport } from './routes/_main.posts.$postId.index'
+import { Route as MainPostsPostIdEditRouteImport } from './routes/_main.posts.$postId.edit'
 
 const MainRoute = MainRouteImport.update({
   id: '/_main',
@@ -40,9 +43,24 @@ const MainCreateRoute = MainCreateRouteImport.update({
   path: '/create',
   getParentRoute: () => MainRoute,
 } as any)
-const MainPostIdEditRoute = MainPostIdEditRouteImport.update({
-  id: '/$postId/edit',
-  path: '/$postId/edit',
+const MainLoginMagicRoute = MainLoginMagicRouteImport.update({
+  id: '/login_/magic',
+  path: '/login/magic',
+  getParentRoute: () => MainRoute,
+} as any)
+const MainAuthorsAccountIdRoute = MainAuthorsAccountIdRouteImport.update({
+  id: '/authors/$accountId',
+  path: '/authors/$accountId',
+  getParentRoute: () => MainRoute,
+} as any)
+const MainPostsPostIdIndexRoute = MainPostsPostIdIndexRouteImport.update({
+  id: '/posts/$postId/',
+  path: '/posts/$postId/',
+  getParentRoute: () => MainRoute,
+} as any)
+const MainPostsPostIdEditRoute = MainPostsPostIdEditRouteImport.update({
+  id: '/posts/$postId/edit',
+  path: '/posts/$postId/edit',
   getParentRoute: () => MainRoute,
 } as any)
 
@@ -51,14 +69,20 @@ export interface FileRoutesByFullPath {
   '/login': typeof MainLoginRoute
   '/register': typeof MainRegisterRoute
   '/': typeof MainIndexRoute
-  '/$postId/edit': typeof MainPostIdEditRoute
+  '/authors/$accountId': typeof MainAuthorsAccountIdRoute
+  '/login/magic': typeof MainLoginMagicRoute
+  '/posts/$postId/edit': typeof MainPostsPostIdEditRoute
+  '/posts/$postId': typeof MainPostsPostIdIndexRoute
 }
 export interface FileRoutesByTo {
   '/create': typeof MainCreateRoute
   '/login': typeof MainLoginRoute
   '/register': typeof MainRegisterRoute
   '/': typeof MainIndexRoute
-  '/$postId/edit': typeof MainPostIdEditRoute
+  '/authors/$accountId': typeof MainAuthorsAccountIdRoute
+  '/login/magic': typeof MainLoginMagicRoute
+  '/posts/$postId/edit': typeof MainPostsPostIdEditRoute
+  '/posts/$postId': typeof MainPostsPostIdIndexRoute
 }
 export interface FileRoutesById {
   __root__: typeof rootRouteImport
@@ -67,13 +91,32 @@ export interface FileRoutesById {
   '/_main/login': typeof MainLoginRoute
   '/_main/register': typeof MainRegisterRoute
   '/_main/': typeof MainIndexRoute
-  '/_main/$postId/edit': typeof MainPostIdEditRoute
+  '/_main/authors/$accountId': typeof MainAuthorsAccountIdRoute
+  '/_main/login_/magic': typeof MainLoginMagicRoute
+  '/_main/posts/$postId/edit': typeof MainPostsPostIdEditRoute
+  '/_main/posts/$postId/': typeof MainPostsPostIdIndexRoute
 }
 export interface FileRouteTypes {
   fileRoutesByFullPath: FileRoutesByFullPath
-  fullPaths: '/create' | '/login' | '/register' | '/' | '/$postId/edit'
+  fullPaths:
+    | '/create'
+    | '/login'
+    | '/register'
+    | '/'
+    | '/authors/$accountId'
+    | '/login/magic'
+    | '/posts/$postId/edit'
+    | '/posts/$postId'
   fileRoutesByTo: FileRoutesByTo
-  to: '/create' | '/login' | '/register' | '/' | '/$postId/edit'
+  to:
+    | '/create'
+    | '/login'
+    | '/register'
+    | '/'
+    | '/authors/$accountId'
+    | '/login/magic'
+    | '/posts/$postId/edit'
+    | '/posts/$postId'
   id:
     | '__root__'
     | '/_main'
@@ -81,7 +124,10 @@ export interface FileRouteTypes {
     | '/_main/login'
     | '/_main/register'
     | '/_main/'
-    | '/_main/$postId/edit'
+    | '/_main/authors/$accountId'
+    | '/_main/login_/magic'
+    | '/_main/posts/$postId/edit'
+    | '/_main/posts/$postId/'
   fileRoutesById: FileRoutesById
 }
 export interface RootRouteChildren {
@@ -125,11 +171,32 @@ declare module '@tanstack/react-router' {
       preLoaderRoute: typeof MainCreateRouteImport
       parentRoute: typeof MainRoute
     }
-    '/_main/$postId/edit': {
-      id: '/_main/$postId/edit'
-      path: '/$postId/edit'
-      fullPath: '/$postId/edit'
-      preLoaderRoute: typeof MainPostIdEditRouteImport
+    '/_main/authors/$accountId': {
+      id: '/_main/authors/$accountId'
+      path: '/authors/$accountId'
+      fullPath: '/authors/$accountId'
+      preLoaderRoute: typeof MainAuthorsAccountIdRouteImport
+      parentRoute: typeof MainRoute
+    }
+    '/_main/posts/$postId/edit': {
+      id: '/_main/posts/$postId/edit'
+      path: '/posts/$postId/edit'
+      fullPath: '/posts/$postId/edit'
+      preLoaderRoute: typeof MainPostsPostIdEditRouteImport
+      parentRoute: typeof MainRoute
+    }
+    '/_main/posts/$postId/': {
+      id: '/_main/posts/$postId/'
+      path: '/posts/$postId'
+      fullPath: '/posts/$postId'
+      preLoaderRoute: typeof MainPostsPostIdIndexRouteImport
+      parentRoute: typeof MainRoute
+    }
+    '/_main/login_/magic': {
+      id: '/_main/login_/magic'
+      path: '/login/magic'
+      fullPath: '/login/magic'
+      preLoaderRoute: typeof MainLoginMagicRouteImport
       parentRoute: typeof MainRoute
     }
   }
@@ -140,7 +207,10 @@ interface MainRouteChildren {
   MainLoginRoute: typeof MainLoginRoute
   MainRegisterRoute: typeof MainRegisterRoute
   MainIndexRoute: typeof MainIndexRoute
-  MainPostIdEditRoute: typeof MainPostIdEditRoute
+  MainAuthorsAccountIdRoute: typeof MainAuthorsAccountIdRoute
+  MainLoginMagicRoute: typeof MainLoginMagicRoute
+  MainPostsPostIdEditRoute: typeof MainPostsPostIdEditRoute
+  MainPostsPostIdIndexRoute: typeof MainPostsPostIdIndexRoute
 }
 
 const MainRouteChildren: MainRouteChildren = {
@@ -148,7 +218,10 @@ const MainRouteChildren: MainRouteChildren = {
   MainLoginRoute: MainLoginRoute,
   MainRegisterRoute: MainRegisterRoute,
   MainIndexRoute: MainIndexRoute,
-  MainPostIdEditRoute: MainPostIdEditRoute,
+  MainAuthorsAccountIdRoute: MainAuthorsAccountIdRoute,
+  MainLoginMagicRoute: MainLoginMagicRoute,
+  MainPostsPostIdEditRoute: MainPostsPostIdEditRoute,
+  MainPostsPostIdIndexRoute: MainPostsPostIdIndexRoute,
 }
 
 const MainRouteWithChildren = MainRoute._addFileChildren(MainRouteChildren)
@@ -0,0 +1,135 @@
+import useApi from "@/hooks/useApi";
+import {
+  Alert,
+  Anchor,
+  Container,
+  LoadingOverlay,
+  Pagination,
+  Paper,
+  Stack,
+  Text,
+  Title,
+} from "@mantine/core";
+import { IconAlertCircle } from "@tabler/icons-react";
+import { useQuery } from "@tanstack/react-query";
+import {
+  createFileRoute,
+  useNavigate,
+  useSearch,
+} from "@tanstack/react-router";
+
+interface PostData {
+  id: string;
+  account_id: string;
+  account_name: string;
+  contents: string;
+  rendered_contents: string;
+}
+
+interface PostListData {
+  data: PostData[];
+  page: number;
+  page_size: number;
+  page_total: number;
+  total: number;
+}
+
+export const Route = createFileRoute("/_main/authors/$accountId")({
+  component: AuthorPosts,
+  validateSearch: (search: Record<string, unknown>): { page?: number } => {
+    return {
+      page: Number(search?.page) || 1,
+    };
+  },
+});
+
+// An author's page, listed in the sitemap
+function AuthorPosts() {
+  const { accountId } = Route.useParams();
+  const search = useSearch({ from: "/_main/authors/$accountId" });
+  const navigate = useNavigate();
+  const api = useApi();
+  const currentPage = search.page || 1;
+
+  const {
+    data: posts,
+    isLoading: loading,
+    error,
+  } = useQuery<PostListData>({
+    queryKey: ["posts", "author", accountId, currentPage],
+    queryFn: async () => {
+      const params = new URLSearchParams();
+      params.append("page", currentPage.toString());
+      params.append("filters[account_id]", accountId);
+
+      return await api.get<PostListData>(`/api/posts?${params.toString()}`);
+    },
+  });
+
+  const authorName = posts?.data[0]?.account_name;
+
+  return (
+    <Container size="md" py="xl">
+      <Title order={1} mb="md">
+        {authorName ? `Posts by ${authorName}` : "Posts"}
+      </Title>
+
+      {loading && <LoadingOverlay visible />}
+
+      {error && (
+        <Alert
+          icon={<IconAlertCircle size="1rem" />}
+          title="Error"
+          color="red"
+          variant="light"
+          mb="md"
+        >
+          {error.message}
+        </Alert>
+      )}
+
+      {!loading && !error && posts?.data.length === 0 && (
+        <Paper shadow="xs" p="xl" radius="md">
+          <Text ta="center" c="dimmed">
+            No posts available
+          </Text>
+        </Paper>
+      )}
+
+      <Stack gap="md">
+        {posts?.data.map((post) => (
+          <Paper key={post.id} shadow="xs" p="lg" radius="md">
+            <div
+              dangerouslySetInnerHTML={{
+                __html: post.rendered_contents || "",
+              }}
+            />
+            <Anchor component="a" href={`/posts/${post.id}`} size="sm">
+              Read post
+            </Anchor>
+          </Paper>
+        ))}
+      </Stack>
+
+      <div
+        style={{
+          display: "flex",
+          justifyContent: "center",
+          marginTop: "1rem",
+        }}
+      >
+        <Pagination
+          total={posts?.page_total || 0}
+          value={currentPage}
+          onChange={(page) =>
+            navigate({
+              to: "/authors/$accountId",
+              params: { accountId },
+              search: { page },
+            })
+          }
+        />
+      </div>
+    </Container>
+  );
+}
@@ -10,6 +10,7 @@ import {
   Group,
   Stack,
   Alert,
+  Anchor,
   Pagination,
   Input,
   Button,
@@ -35,6 +36,7 @@ interface PostData {
   account_id: string;
   account_name: string;
   contents: string;
+  rendered_contents: string;
 }
 
 interface PostListData {
@@ -190,7 +192,13 @@ function PostList() {
               >
                 <Group>
                   <Avatar size="sm" />
-                  <Text fw={500}>{post.account_name}</Text>
+                  <Anchor
+                    component="a"
+                    href={`/authors/${post.account_id}`}
+                    fw={500}
+                  >
+                    {post.account_name}
+                  </Anchor>
                 </Group>
 
                 {auth && auth.accountId === post.account_id && (
@@ -233,9 +241,12 @@ function PostList() {
               </div>
               <div
                 dangerouslySetInnerHTML={{
-                  __html: post.contents || "",
+                  __html: post.rendered_contents || "",
                 }}
               />
+              <Anchor component="a" href={`/posts/${post.id}`} size="sm">
+                Read post
+              </Anchor>
             </Paper>
           ))}
       </Stack>
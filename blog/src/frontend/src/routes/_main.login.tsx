@@ -17,10 +17,8 @@ import { IconMail, IconLock, IconAlertCircle } from "@tabler/icons-react";
 import useApi from "@/hooks/useApi";
 import { useAuth } from "@/store/auth";
 import { useMutation } from "@tanstack/react-query";
-
-interface Token {
-  token: string;
-}
+import { useState } from "react";
+import { MfaForm, type LoginResult } from "@/components/MfaForm";
 
 export const Route = createFileRoute("/_main/login")({
   component: LoginPage,
@@ -35,18 +33,28 @@ function LoginPage() {
   const navigate = useNavigate();
   const api = useApi();
   const auth = useAuth();
+  const [mfaToken, setMfaToken] = useState<string | null>(null);
 
-  const loginMutation = useMutation<Token, Error, LoginForm>({
+  const loginMutation = useMutation<LoginResult, Error, LoginForm>({
     mutationFn: async (values: LoginForm) => {
-      let res = await api.post<Token>("/api/login", values);
+      let res = await api.post<LoginResult>("/api/login", values);
       return res;
     },
     onSuccess: (data) => {
-      auth.login(data.token);
+      if ("mfa_required" in data) {
+        setMfaToken(data.mfa_token);
+        return;
+      }
+      auth.login(data);
       navigate({ to: "/" });
     },
   });
 
+  const cancelMfa = () => {
+    setMfaToken(null);
+    loginMutation.reset();
+  };
+
   const form = useForm<LoginForm>({
     initialValues: {
       email: "",
@@ -79,51 +87,57 @@ function LoginPage() {
             <p>Sign in to your account</p>
           </div>
 
-          {loginMutation.error && (
-            <Alert
-              icon={<IconAlertCircle size="1rem" />}
-              title="Login Error"
-              color="red"
-              variant="light"
-            >
-              {loginMutation.error instanceof Error
-                ? loginMutation.error.message
-                : String(loginMutation.error)}
-            </Alert>
-          )}
+          {mfaToken ? (
+            <MfaForm mfaToken={mfaToken} onCancel={cancelMfa} />
+          ) : (
+            <>
+              {loginMutation.error && (
+                <Alert
+                  icon={<IconAlertCircle size="1rem" />}
+                  title="Login Error"
+                  color="red"
+                  variant="light"
+                >
+                  {loginMutation.error instanceof Error
+                    ? loginMutation.error.message
+                    : String(loginMutation.error)}
+                </Alert>
+              )}
 
-          <form
-            onSubmit={form.onSubmit((values) =>
-              loginMutation.mutateAsync(values),
-            )}
-          >
-            <Stack gap="md">
-              <TextInput
-                label="Email"
-                placeholder="your@email.com"
-                leftSection={<IconMail size={16} />}
-                required
-                {...form.getInputProps("email")}
-              />
+              <form
+                onSubmit={form.onSubmit((values) =>
+                  loginMutation.mutateAsync(values),
+                )}
+              >
+                <Stack gap="md">
+                  <TextInput
+                    label="Email"
+                    placeholder="your@email.com"
+                    leftSection={<IconMail size={16} />}
+                    required
+                    {...form.getInputProps("email")}
+                  />
 
-              <PasswordInput
-                label="Password"
-                placeholder="Your password"
-                leftSection={<IconLock size={16} />}
-                required
-                {...form.getInputProps("password")}
-              />
+                  <PasswordInput
+                    label="Password"
+                    placeholder="Your password"
+                    leftSection={<IconLock size={16} />}
+                    required
+                    {...form.getInputProps("password")}
+                  />
 
-              <Button
-                type="submit"
-                fullWidth
-                mt="md"
-                disabled={loginMutation.isPending}
-              >
-                {loginMutation.isPending ? "Signing in..." : "Sign In"}
-              </Button>
-            </Stack>
-          </form>
+                  <Button
+                    type="submit"
+                    fullWidth
+                    mt="md"
+                    disabled={loginMutation.isPending}
+                  >
+                    {loginMutation.isPending ? "Signing in..." : "Sign In"}
+                  </Button>
+                </Stack>
+              </form>
+            </>
+          )}
 
           <Group justify="center" gap="xs">
             <span>Don't have an account?</span>
@@ -0,0 +1,110 @@
+import {
+  createFileRoute,
+  useNavigate,
+  useSearch,
+} from "@tanstack/react-router";
+import {
+  Container,
+  Paper,
+  Title,
+  Stack,
+  Group,
+  Anchor,
+  Alert,
+  Loader,
+} from "@mantine/core";
+import { IconAlertCircle } from "@tabler/icons-react";
+import useApi from "@/hooks/useApi";
+import { useAuth } from "@/store/auth";
+import { useMutation } from "@tanstack/react-query";
+import { useEffect, useRef, useState } from "react";
+import { MfaForm, type LoginResult } from "@/components/MfaForm";
+
+export const Route = createFileRoute("/_main/login_/magic")({
+  component: MagicLinkPage,
+  validateSearch: (search: Record<string, unknown>): { token?: string } => {
+    return {
+      token: typeof search?.token === "string" ? search.token : undefined,
+    };
+  },
+});
+
+// Opened from the emailed sign-in link. The token is posted rather than
+// spent by the GET that loads this page, so mail scanners can't use it up.
+function MagicLinkPage() {
+  const search = useSearch({ from: "/_main/login_/magic" });
+  const navigate = useNavigate();
+  const api = useApi();
+  const auth = useAuth();
+  const [mfaToken, setMfaToken] = useState<string | null>(null);
+  // Links work once, so the request mustn't be repeated on a re-render
+  const sent = useRef(false);
+
+  const verifyMutation = useMutation<LoginResult, Error, string>({
+    mutationFn: async (token: string) => {
+      let res = await api.post<LoginResult>("/api/login/magic/verify", {
+        token,
+      });
+      return res;
+    },
+    onSuccess: (data) => {
+      if ("mfa_required" in data) {
+        setMfaToken(data.mfa_token);
+        return;
+      }
+      auth.login(data);
+      navigate({ to: "/" });
+    },
+  });
+
+  useEffect(() => {
+    if (sent.current || !search.token) {
+      return;
+    }
+    sent.current = true;
+    verifyMutation.mutate(search.token);
+  }, [search.token]);
+
+  const failed = !search.token || verifyMutation.isError;
+
+  return (
+    <Container size="sm" py="xl">
+      <Paper shadow="md" p="xl" radius="md">
+        <Stack gap="lg">
+          <div style={{ textAlign: "center" }}>
+            <Title order={2} mb="sm">
+              Signing In
+            </Title>
+          </div>
+
+          {mfaToken ? (
+            <MfaForm
+              mfaToken={mfaToken}
+              onCancel={() => navigate({ to: "/login" })}
+            />
+          ) : failed ? (
+            <Alert
+              icon={<IconAlertCircle size="1rem" />}
+              title="Login Error"
+              color="red"
+              variant="light"
+            >
+              This sign-in link is invalid, has already been used or has
+              expired.
+            </Alert>
+          ) : (
+            <Group justify="center">
+              <Loader />
+            </Group>
+          )}
+
+          <Group justify="center" gap="xs">
+            <Anchor component="a" href="/login" size="sm">
+              Back to sign in
+            </Anchor>
+          </Group>
+        </Stack>
+      </Paper>
+    </Container>
+  );
+}
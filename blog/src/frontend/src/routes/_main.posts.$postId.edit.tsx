@@ -14,22 +14,22 @@ import {
   IconDeviceFloppy,
 } from "@tabler/icons-react";
 import { useEffect, useState } from "react";
+import useApi from "@/hooks/useApi";
 
-export const Route = createFileRoute("/_main/$postId/edit")({
+export const Route = createFileRoute("/_main/posts/$postId/edit")({
   component: PostEdit,
 });
 
 function PostEditor({
   content,
   postId,
-  token,
   setError,
 }: {
   content: string;
   postId: string;
-  token: string;
   setError: (error: string | null) => void;
 }) {
+  const api = useApi();
   const navigate = useNavigate();
   const [editedContent, setEditedContent] = useState<string>(content);
   const editor = useEditor({
@@ -50,18 +50,7 @@ function PostEditor({
 
   const onSave = async () => {
     try {
-      const response = await fetch(`/api/posts/${postId}`, {
-        method: "PUT",
-        headers: {
-          "Content-Type": "application/json",
-          Authorization: `Bearer ${token}`,
-        },
-        body: JSON.stringify({ contents: editedContent }),
-      });
-
-      if (!response.ok) {
-        throw new Error("Failed to save post");
-      }
+      await api.put(`/api/posts/${postId}`, { contents: editedContent });
 
       navigate({ to: "/" });
     } catch (error) {
@@ -134,6 +123,7 @@ function PostEditor({
 }
 
 function PostEdit() {
+  const api = useApi();
   const { postId } = Route.useParams();
   const navigate = useNavigate();
   const [content, setContent] = useState<string | null>(null);
@@ -141,24 +131,14 @@ function PostEdit() {
   const [error, setError] = useState<string | null>(null);
 
   const fetchPost = async (postId: string) => {
-    const token = localStorage.getItem("auth_token");
     try {
-      const response = await fetch(`/api/posts/${postId}`, {
-        headers: {
-          Authorization: `Bearer ${token}`,
-        },
-      });
-      if (!response.ok) throw new Error("Failed to fetch post");
-      const data = await response.json();
-      console.log("Fetched post data:", data.contents);
+      const data = await api.get(`/api/posts/${postId}`);
       setContent(data.data.contents);
     } catch (error) {
       setError("Failed to fetch post");
     }
   };
 
-  const token = localStorage.getItem("auth_token");
-
   useEffect(() => {
     fetchPost(postId);
   }, []);
@@ -196,11 +176,10 @@ function PostEdit() {
         </Alert>
       )}
 
-      {token && content != null && (
+      {content != null && (
         <PostEditor
           content={content}
           postId={postId}
-          token={token}
           setError={setError}
         />
       )}
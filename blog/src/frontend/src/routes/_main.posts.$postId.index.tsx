@@ -0,0 +1,92 @@
+import useApi from "@/hooks/useApi";
+import {
+  Alert,
+  Anchor,
+  Avatar,
+  Button,
+  Container,
+  Group,
+  LoadingOverlay,
+  Paper,
+} from "@mantine/core";
+import { IconAlertCircle, IconArrowLeft } from "@tabler/icons-react";
+import { useQuery } from "@tanstack/react-query";
+import { createFileRoute, useNavigate } from "@tanstack/react-router";
+
+interface PostData {
+  id: string;
+  account_id: string;
+  account_name: string;
+  contents: string;
+  rendered_contents: string;
+}
+
+export const Route = createFileRoute("/_main/posts/$postId/")({
+  component: PostView,
+});
+
+// The page the sitemap and the canonical link point at
+function PostView() {
+  const { postId } = Route.useParams();
+  const navigate = useNavigate();
+  const api = useApi();
+
+  const {
+    data: post,
+    isLoading: loading,
+    error,
+  } = useQuery<PostData>({
+    queryKey: ["post", postId],
+    queryFn: async () => {
+      const res = await api.get<{ data: PostData }>(`/api/posts/${postId}`);
+      return res.data;
+    },
+  });
+
+  return (
+    <Container size="md" py="xl">
+      <Button
+        onClick={() => navigate({ to: "/" })}
+        leftSection={<IconArrowLeft size={20} />}
+        variant="subtle"
+        mb="md"
+      >
+        Back
+      </Button>
+
+      {loading && <LoadingOverlay visible />}
+
+      {error && (
+        <Alert
+          icon={<IconAlertCircle size="1rem" />}
+          title="Error"
+          color="red"
+          variant="light"
+          mb="md"
+        >
+          {error.message}
+        </Alert>
+      )}
+
+      {post && (
+        <Paper shadow="xs" p="lg" radius="md">
+          <Group mb="md">
+            <Avatar size="sm" />
+            <Anchor
+              component="a"
+              href={`/authors/${post.account_id}`}
+              fw={500}
+            >
+              {post.account_name}
+            </Anchor>
+          </Group>
+          <div
+            dangerouslySetInnerHTML={{
+              __html: post.rendered_contents || "",
+            }}
+          />
+        </Paper>
+      )}
+    </Container>
+  );
+}
@@ -21,17 +21,13 @@ import {
   IconUser,
 } from "@tabler/icons-react";
 import useApi from "@/hooks/useApi";
-import { useAuth } from "@/store/auth";
+import { useAuth, type Session } from "@/store/auth";
 import { useMutation } from "@tanstack/react-query";
 
 export const Route = createFileRoute("/_main/register")({
   component: Register,
 });
 
-interface Token {
-  token: string;
-}
-
 interface RegisterForm {
   name: string;
   email: string;
@@ -44,9 +40,9 @@ function Register() {
   const api = useApi();
   const auth = useAuth();
 
-  const registerMutation = useMutation<Token, Error, RegisterForm>({
+  const registerMutation = useMutation<Session, Error, RegisterForm>({
     mutationFn: async (form: RegisterForm) => {
-      let res = await api.post<Token>("/api/register", {
+      let res = await api.post<Session>("/api/register", {
         account_name: form.name,
         email: form.email,
         password: form.password,
@@ -54,7 +50,7 @@ function Register() {
       return res;
     },
     onSuccess: (data) => {
-      auth.login(data.token);
+      auth.login(data);
       navigate({
         to: "/",
       });
@@ -28,11 +28,11 @@ function RouteComponent() {
   const navigate = useNavigate();
 
   const { data: me } = useQuery<User>({
-    queryKey: ["user", auth.token],
+    queryKey: ["user", auth.token, auth.isAuthenticated],
     queryFn: async () => {
       return await api.request<User>("/api/me");
     },
-    enabled: !!auth.token,
+    enabled: auth.isAuthenticated,
   });
 
   return (
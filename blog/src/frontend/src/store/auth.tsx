@@ -5,15 +5,25 @@ import React, {
   useEffect,
   type ReactNode,
 } from "react";
+import { getCookie } from "@/utils/cookies";
 
 const AUTH_STORAGE_KEY = "auth_token";
+// Set next to the HttpOnly session cookie when the server uses cookie sessions
+export const CSRF_COOKIE = "csrf_token";
 
 // extract account_id from token
 const extractAccountId = (token: string): string | null => {
   const decoded = JSON.parse(atob(token.split(".")[1]));
-  return decoded?.account_id ?? null;
+  return decoded?.sub ?? null;
 };
 
+// What a login returns: a token for the Authorization header, a CSRF token
+// for a cookie session, or both
+export interface Session {
+  token?: string;
+  csrf_token?: string;
+}
+
 export interface AuthState {
   token: string | null;
   accountId: string | null;
@@ -22,7 +32,7 @@ export interface AuthState {
 }
 
 export interface AuthContextType extends AuthState {
-  login: (token: string) => void;
+  login: (session: Session) => void;
   logout: () => void;
 }
 
@@ -58,17 +68,49 @@ interface AuthProviderProps {
   children: ReactNode;
 }
 
+// The session cookie is out of reach of scripts, so the account comes from
+// the server. Null when the session is gone.
+const fetchCookieAccountId = async (): Promise<string | null> => {
+  try {
+    const response = await fetch("/api/me", { credentials: "same-origin" });
+    if (!response.ok) {
+      return null;
+    }
+    const body = await response.json();
+    return body?.data?.id ?? null;
+  } catch (error) {
+    console.error("Error loading the cookie session:", error);
+    return null;
+  }
+};
+
 // Provider component
 export const AuthProvider: React.FC<AuthProviderProps> = ({ children }) => {
   const [token, setTokenState] = useState<string | null>(null);
+  const [cookieSession, setCookieSession] = useState(false);
   const [accountId, setAccountIdState] = useState<string | null>(null);
 
   const [isLoading, setIsLoading] = useState(true);
 
-  // Load auth state from localStorage on mount
+  const startCookieSession = async () => {
+    setCookieSession(true);
+    const id = await fetchCookieAccountId();
+    if (id) {
+      setAccountIdState(id);
+    } else {
+      setCookieSession(false);
+    }
+  };
+
+  // Load auth state from the cookies or localStorage on mount
   useEffect(() => {
     const storedToken = getStoredToken();
 
+    if (getCookie(CSRF_COOKIE)) {
+      startCookieSession().finally(() => setIsLoading(false));
+      return;
+    }
+
     if (storedToken) {
       setTokenState(storedToken);
       setAccountIdState(extractAccountId(storedToken));
@@ -77,21 +119,39 @@ export const AuthProvider: React.FC<AuthProviderProps> = ({ children }) => {
     setIsLoading(false);
   }, []);
 
-  const login = (newToken: string) => {
-    setTokenState(newToken);
-    setStoredToken(newToken);
-    setAccountIdState(extractAccountId(newToken));
+  const login = (session: Session) => {
+    // A cookie session is preferred when the server offers one, and then the
+    // token isn't kept where scripts can read it
+    if (session.csrf_token) {
+      clearStoredAuth();
+      setTokenState(null);
+      startCookieSession();
+      return;
+    }
+
+    if (session.token) {
+      setTokenState(session.token);
+      setStoredToken(session.token);
+      setAccountIdState(extractAccountId(session.token));
+    }
   };
 
   const logout = () => {
+    if (cookieSession || getCookie(CSRF_COOKIE)) {
+      // Only the server can clear the HttpOnly session cookie
+      fetch("/api/logout", { method: "POST", credentials: "same-origin" }).catch(
+        (error) => console.error("Error ending the cookie session:", error),
+      );
+    }
     setTokenState(null);
+    setCookieSession(false);
     setAccountIdState(null);
     clearStoredAuth();
   };
 
   const value: AuthContextType = {
     token,
-    isAuthenticated: !!token,
+    isAuthenticated: !!token || cookieSession,
     isLoading,
     login,
     logout,
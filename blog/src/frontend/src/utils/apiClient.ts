@@ -1,4 +1,5 @@
-import type { AuthContextType } from "../store/auth";
+import { CSRF_COOKIE, type AuthContextType } from "../store/auth";
+import { getCookie } from "./cookies";
 
 const API_BASE_URL = import.meta.env.VITE_API_URL || "";
 
@@ -57,10 +58,18 @@ export function createApiClient(auth: AuthContextType) {
       requestHeaders["Authorization"] = `Bearer ${token}`;
     }
 
+    // Cookie sessions need the CSRF token echoed on requests that change
+    // something
+    const csrfToken = getCookie(CSRF_COOKIE);
+    if (!skipAuth && csrfToken) {
+      requestHeaders["X-CSRF-Token"] = csrfToken;
+    }
+
     try {
       const response = await fetch(url, {
         ...restConfig,
         headers: requestHeaders,
+        credentials: "same-origin",
       });
 
       if (response.status === 401) {
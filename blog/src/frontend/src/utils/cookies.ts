@@ -0,0 +1,8 @@
+// Reads a cookie that isn't HttpOnly, like the CSRF token in cookie sessions
+export function getCookie(name: string): string | null {
+  const prefix = `${name}=`;
+  const cookie = document.cookie
+    .split("; ")
+    .find((part) => part.startsWith(prefix));
+  return cookie ? decodeURIComponent(cookie.slice(prefix.length)) : null;
+}
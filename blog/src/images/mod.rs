@@ -0,0 +1,322 @@
+use image::ImageDecoder;
+
+const JPEG_QUALITY: u8 = 82;
+// Quality used when an upload has to be re-encoded to apply its orientation
+const ORIGINAL_JPEG_QUALITY: u8 = 92;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VariantFormat {
+    WebP,
+    Jpeg,
+}
+
+impl VariantFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "webp" => Some(Self::WebP),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::WebP => "webp",
+            Self::Jpeg => "jpg",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::WebP => "image/webp",
+            Self::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// Removes EXIF, XMP and text metadata (camera details, GPS coordinates, ...)
+/// from an uploaded image. Images carrying a non-default orientation are
+/// re-encoded with the orientation applied so they still display upright.
+pub fn strip_metadata(data: &[u8], format: image::ImageFormat) -> Result<Vec<u8>, String> {
+    let orientation = image::ImageReader::with_format(std::io::Cursor::new(data), format)
+        .into_decoder()
+        .and_then(|mut decoder| decoder.orientation())
+        .map_err(|err| err.to_string())?;
+
+    if orientation != image::metadata::Orientation::NoTransforms {
+        let mut decoded =
+            image::load_from_memory_with_format(data, format).map_err(|err| err.to_string())?;
+        decoded.apply_orientation(orientation);
+
+        return match format {
+            image::ImageFormat::Jpeg => encode_jpeg(&decoded, ORIGINAL_JPEG_QUALITY),
+            _ => encode(&decoded, format),
+        };
+    }
+
+    match format {
+        image::ImageFormat::Jpeg => strip_jpeg(data),
+        image::ImageFormat::Png => strip_png(data),
+        image::ImageFormat::WebP => strip_webp(data),
+        _ => Ok(data.to_vec()),
+    }
+}
+
+/// Scales an image down to `width` (never up) and encodes it as `format`.
+/// Re-encoding drops any metadata left in the source.
+pub fn resize(data: &[u8], width: u32, format: VariantFormat) -> Result<Vec<u8>, String> {
+    let decoded = image::load_from_memory(data).map_err(|err| err.to_string())?;
+
+    let resized = if decoded.width() > width {
+        let height = (decoded.height() as u64 * width as u64 / decoded.width() as u64).max(1);
+        decoded.resize_exact(width, height as u32, image::imageops::FilterType::Lanczos3)
+    } else {
+        decoded
+    };
+
+    match format {
+        VariantFormat::WebP => encode(&resized, image::ImageFormat::WebP),
+        VariantFormat::Jpeg => encode_jpeg(&resized, JPEG_QUALITY),
+    }
+}
+
+fn encode(image: &image::DynamicImage, format: image::ImageFormat) -> Result<Vec<u8>, String> {
+    // The WebP encoder only handles 8-bit RGB(A)
+    let image = match format {
+        image::ImageFormat::WebP => image::DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => image.clone(),
+    };
+
+    let mut encoded = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut encoded), format)
+        .map_err(|err| err.to_string())?;
+
+    Ok(encoded)
+}
+
+fn encode_jpeg(image: &image::DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, quality);
+    // JPEG has no alpha channel
+    image::DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(encoder)
+        .map_err(|err| err.to_string())?;
+
+    Ok(encoded)
+}
+
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.get(..2) != Some(&[0xFF, 0xD8]) {
+        return Err("invalid jpeg".to_string());
+    }
+
+    let mut stripped = vec![0xFF, 0xD8];
+    let mut position = 2;
+
+    while position + 4 <= data.len() {
+        if data[position] != 0xFF {
+            return Err("invalid jpeg segment".to_string());
+        }
+
+        let marker = data[position + 1];
+        // Start of scan: the entropy-coded image data follows until the end
+        if marker == 0xDA {
+            stripped.extend_from_slice(&data[position..]);
+            return Ok(stripped);
+        }
+
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        let end = position + 2 + length;
+        if length < 2 || end > data.len() {
+            return Err("invalid jpeg segment".to_string());
+        }
+
+        // APP1 holds EXIF and XMP, APP13 holds IPTC, 0xFE is a comment.
+        // APP0 (JFIF), APP2 (ICC profile) and APP14 (Adobe) affect decoding.
+        if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+            stripped.extend_from_slice(&data[position..end]);
+        }
+
+        position = end;
+    }
+
+    Err("invalid jpeg".to_string())
+}
+
+fn strip_png(data: &[u8]) -> Result<Vec<u8>, String> {
+    const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    if data.get(..8) != Some(SIGNATURE) {
+        return Err("invalid png".to_string());
+    }
+
+    let mut stripped = SIGNATURE.to_vec();
+    let mut position = 8;
+
+    while position + 12 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let end = position + 12 + length;
+        if end > data.len() {
+            return Err("invalid png chunk".to_string());
+        }
+
+        let chunk_type = &data[position + 4..position + 8];
+        if !matches!(chunk_type, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            stripped.extend_from_slice(&data[position..end]);
+        }
+
+        position = end;
+    }
+
+    Ok(stripped)
+}
+
+fn strip_webp(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err("invalid webp".to_string());
+    }
+
+    let mut chunks = Vec::new();
+    let mut position = 12;
+
+    while position + 8 <= data.len() {
+        let size =
+            u32::from_le_bytes(data[position + 4..position + 8].try_into().unwrap()) as usize;
+        // Chunks are padded to an even size
+        let end = (position + 8 + size + (size & 1)).min(data.len());
+
+        let fourcc = &data[position..position + 4];
+        if fourcc == b"VP8X" && position + 8 < end {
+            let mut chunk = data[position..end].to_vec();
+            // Clear the EXIF (0x08) and XMP (0x04) presence flags
+            chunk[8] &= !0x0C;
+            chunks.extend_from_slice(&chunk);
+        } else if fourcc != b"EXIF" && fourcc != b"XMP " {
+            chunks.extend_from_slice(&data[position..end]);
+        }
+
+        position = end;
+    }
+
+    let mut stripped = Vec::with_capacity(chunks.len() + 12);
+    stripped.extend_from_slice(b"RIFF");
+    stripped.extend_from_slice(&((chunks.len() + 4) as u32).to_le_bytes());
+    stripped.extend_from_slice(b"WEBP");
+    stripped.extend_from_slice(&chunks);
+
+    Ok(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(format: image::ImageFormat) -> Vec<u8> {
+        encode(
+            &image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+                8,
+                8,
+                image::Rgb([200, 100, 50]),
+            )),
+            format,
+        )
+        .unwrap()
+    }
+
+    fn png_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(data);
+        // The CRC isn't checked while stripping
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn riff_chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let chunks = chunks.concat();
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&((chunks.len() + 4) as u32).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&chunks);
+        data
+    }
+
+    #[test]
+    fn strip_jpeg_removes_exif_iptc_and_comments() {
+        let original = encoded(image::ImageFormat::Jpeg);
+        let mut tagged = original[..2].to_vec();
+        for (marker, payload) in [
+            (0xE1, &b"Exif\0\0GPS 51.5N 0.1W"[..]),
+            (0xED, &b"Photoshop 3.0\0IPTC"[..]),
+            (0xFE, &b"taken at home"[..]),
+        ] {
+            tagged.extend_from_slice(&[0xFF, marker]);
+            tagged.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+            tagged.extend_from_slice(payload);
+        }
+        tagged.extend_from_slice(&original[2..]);
+
+        let stripped = strip_jpeg(&tagged).unwrap();
+
+        assert_eq!(stripped, original);
+        image::load_from_memory_with_format(&stripped, image::ImageFormat::Jpeg).unwrap();
+    }
+
+    #[test]
+    fn strip_png_removes_exif_and_text_chunks() {
+        let original = encoded(image::ImageFormat::Png);
+        // The signature and IHDR come first
+        let header = 8 + 12 + 13;
+        let mut tagged = original[..header].to_vec();
+        for (chunk_type, payload) in [
+            (b"eXIf", &b"MM\0*GPS 51.5N 0.1W"[..]),
+            (b"tEXt", &b"Author\0someone"[..]),
+            (b"zTXt", &b"Comment\0\0x"[..]),
+            (b"iTXt", &b"Title\0\0\0\0\0home"[..]),
+            (b"tIME", &[0x07, 0xEA, 10, 19, 12, 0, 0][..]),
+        ] {
+            tagged.extend_from_slice(&png_chunk(chunk_type, payload));
+        }
+        tagged.extend_from_slice(&original[header..]);
+
+        let stripped = strip_png(&tagged).unwrap();
+
+        assert_eq!(stripped, original);
+        image::load_from_memory_with_format(&stripped, image::ImageFormat::Png).unwrap();
+    }
+
+    #[test]
+    fn strip_webp_removes_exif_and_xmp_and_clears_their_flags() {
+        // Alpha (0x10), EXIF (0x08) and XMP (0x04), then a 16x16 canvas
+        let vp8x = |flags: u8| riff_chunk(b"VP8X", &[flags, 0, 0, 0, 15, 0, 0, 15, 0, 0]);
+        let image = riff_chunk(b"VP8L", &[0x2F, 1, 2, 3, 4]);
+
+        let tagged = webp(&[
+            vp8x(0x1C),
+            image.clone(),
+            riff_chunk(b"EXIF", b"MM\0*GPS"),
+            riff_chunk(b"XMP ", b"<x:xmpmeta/>"),
+        ]);
+
+        assert_eq!(strip_webp(&tagged).unwrap(), webp(&[vp8x(0x10), image]));
+    }
+
+    #[test]
+    fn strip_rejects_other_formats() {
+        let png = encoded(image::ImageFormat::Png);
+
+        assert!(strip_jpeg(&png).is_err());
+        assert!(strip_png(&png[1..]).is_err());
+        assert!(strip_webp(&png).is_err());
+    }
+}
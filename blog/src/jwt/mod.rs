@@ -0,0 +1,493 @@
+// Signs and checks the JWTs used for sessions and login challenges.
+//
+// Keys live in the database so every instance signs with the same one, and
+// each instance reloads them every minute so a rotation reaches all of them
+// without a restart. Tokens name their key in the `kid` header and the public
+// halves are published as a JWKS for other services. Until a key has been
+// activated, HS256 with `auth.jwt_secret` is used instead, and tokens without
+// a `kid` are accepted for one token lifetime after that.
+
+use crate::db::repositories::Queries;
+use crate::db::repositories::signing_keys::SigningKey;
+
+pub const REFRESH_INTERVAL_SECS: u64 = 60;
+const RSA_KEY_BITS: usize = 2048;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KeyAlgorithm {
+    EdDsa,
+    Rs256,
+}
+
+impl KeyAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyAlgorithm::EdDsa => "EdDSA",
+            KeyAlgorithm::Rs256 => "RS256",
+        }
+    }
+
+    fn jwt_algorithm(&self) -> jsonwebtoken::Algorithm {
+        match self {
+            KeyAlgorithm::EdDsa => jsonwebtoken::Algorithm::EdDSA,
+            KeyAlgorithm::Rs256 => jsonwebtoken::Algorithm::RS256,
+        }
+    }
+
+    fn encoding_key(&self, private_key: &[u8]) -> jsonwebtoken::EncodingKey {
+        match self {
+            KeyAlgorithm::EdDsa => jsonwebtoken::EncodingKey::from_ed_der(private_key),
+            KeyAlgorithm::Rs256 => jsonwebtoken::EncodingKey::from_rsa_der(private_key),
+        }
+    }
+}
+
+impl std::str::FromStr for KeyAlgorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "EdDSA" => Ok(KeyAlgorithm::EdDsa),
+            "RS256" => Ok(KeyAlgorithm::Rs256),
+            other => Err(format!("unknown signing key algorithm {}", other)),
+        }
+    }
+}
+
+/// A new key pair as it is stored: the private key as DER (PKCS#8 for
+/// Ed25519, PKCS#1 for RSA) and the public key as a JWK without `kid`.
+pub struct GeneratedKey {
+    pub private_key: Vec<u8>,
+    pub public_jwk: String,
+}
+
+pub fn generate(algorithm: KeyAlgorithm) -> Result<GeneratedKey, String> {
+    let (private_key, public_jwk) = match algorithm {
+        KeyAlgorithm::EdDsa => {
+            use ring::signature::KeyPair;
+
+            let pkcs8 =
+                ring::signature::Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                    .map_err(|_| "can't generate Ed25519 key")?;
+            let pair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+                .map_err(|_| "can't generate Ed25519 key")?;
+
+            let jwk = serde_json::json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": encode(pair.public_key().as_ref()),
+                "alg": algorithm.as_str(),
+                "use": "sig",
+            });
+            (pkcs8.as_ref().to_vec(), jwk)
+        }
+        KeyAlgorithm::Rs256 => {
+            use rsa::pkcs1::EncodeRsaPrivateKey;
+            use rsa::traits::PublicKeyParts;
+
+            let key = rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, RSA_KEY_BITS)
+                .map_err(|err| format!("can't generate RSA key: {}", err))?;
+            let der = key
+                .to_pkcs1_der()
+                .map_err(|err| format!("can't encode RSA key: {}", err))?;
+
+            let jwk = serde_json::json!({
+                "kty": "RSA",
+                "n": encode(&key.n().to_bytes_be()),
+                "e": encode(&key.e().to_bytes_be()),
+                "alg": algorithm.as_str(),
+                "use": "sig",
+            });
+            (der.as_bytes().to_vec(), jwk)
+        }
+    };
+
+    Ok(GeneratedKey {
+        private_key,
+        public_jwk: public_jwk.to_string(),
+    })
+}
+
+struct Signer {
+    kid: String,
+    algorithm: jsonwebtoken::Algorithm,
+    key: jsonwebtoken::EncodingKey,
+}
+
+struct Verifier {
+    algorithm: jsonwebtoken::Algorithm,
+    key: jsonwebtoken::DecodingKey,
+}
+
+#[derive(Default)]
+struct KeySet {
+    signer: Option<Signer>,
+    verifiers: std::collections::HashMap<String, Verifier>,
+    jwks: Vec<jsonwebtoken::jwk::Jwk>,
+    /// When tokens without a `kid` stop being accepted, None until a key has
+    /// been activated.
+    hmac_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub struct Keys {
+    hmac_encoding: jsonwebtoken::EncodingKey,
+    hmac_decoding: jsonwebtoken::DecodingKey,
+    token_lifetime: chrono::Duration,
+    set: std::sync::RwLock<KeySet>,
+}
+
+impl Keys {
+    /// Starts out with only the shared secret, `refresh` loads the rest.
+    /// `token_lifetime` is how long shared secret tokens are still accepted
+    /// once a key signs instead.
+    pub fn new(hmac_secret: &str, token_lifetime: chrono::Duration) -> Self {
+        Keys {
+            hmac_encoding: jsonwebtoken::EncodingKey::from_secret(hmac_secret.as_bytes()),
+            hmac_decoding: jsonwebtoken::DecodingKey::from_secret(hmac_secret.as_bytes()),
+            token_lifetime,
+            set: std::sync::RwLock::new(KeySet::default()),
+        }
+    }
+
+    /// Reloads the keys from the database. On error the previous ones stay.
+    pub async fn refresh(&self, queries: &Queries) -> Result<(), String> {
+        let stored = queries
+            .verification_keys()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.load(stored)
+    }
+
+    /// Replaces the keys with `stored`, as returned by `verification_keys`.
+    fn load(&self, stored: Vec<SigningKey>) -> Result<(), String> {
+        let mut set = KeySet::default();
+        for key in stored {
+            let kid = key.id.to_string();
+            let algorithm: KeyAlgorithm = key.algorithm.parse()?;
+
+            let mut jwk: jsonwebtoken::jwk::Jwk = serde_json::from_str(&key.public_jwk)
+                .map_err(|err| format!("invalid public key {}: {}", kid, err))?;
+            jwk.common.key_id = Some(kid.clone());
+            let decoding = jsonwebtoken::DecodingKey::from_jwk(&jwk)
+                .map_err(|err| format!("invalid public key {}: {}", kid, err))?;
+
+            // A key is only deleted once the one after it has been active for
+            // a token lifetime, so the oldest activation left is never later
+            // than the end of the shared secret's last tokens
+            if let Some(activated_at) = key.activated_at {
+                let until = activated_at + self.token_lifetime;
+                set.hmac_until = Some(set.hmac_until.map_or(until, |current| current.min(until)));
+            }
+
+            if key.activated_at.is_some() && key.retired_at.is_none() {
+                set.signer = Some(Signer {
+                    kid: kid.clone(),
+                    algorithm: algorithm.jwt_algorithm(),
+                    key: algorithm.encoding_key(&key.private_key),
+                });
+            }
+            set.verifiers.insert(
+                kid,
+                Verifier {
+                    algorithm: algorithm.jwt_algorithm(),
+                    key: decoding,
+                },
+            );
+            set.jwks.push(jwk);
+        }
+
+        *self.set.write().expect("key set lock is poisoned") = set;
+        Ok(())
+    }
+
+    /// Signs with the active key, or the shared secret when there is none.
+    pub fn encode<T: serde::Serialize>(
+        &self,
+        claims: &T,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let set = self.set.read().expect("key set lock is poisoned");
+        match &set.signer {
+            Some(signer) => {
+                let mut header = jsonwebtoken::Header::new(signer.algorithm);
+                header.kid = Some(signer.kid.clone());
+                jsonwebtoken::encode(&header, claims, &signer.key)
+            }
+            None => jsonwebtoken::encode(
+                &jsonwebtoken::Header::default(),
+                claims,
+                &self.hmac_encoding,
+            ),
+        }
+    }
+
+    /// Checks a token with the key its `kid` names, or the shared secret
+    /// without one while that's still accepted. Without an `audience`,
+    /// tokens that carry one are rejected.
+    pub fn decode<T: serde::de::DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: Option<&str>,
+    ) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let set = self.set.read().expect("key set lock is poisoned");
+
+        let (algorithm, key) = match &header.kid {
+            Some(kid) => match set.verifiers.get(kid) {
+                Some(verifier) => (verifier.algorithm, &verifier.key),
+                None => return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into()),
+            },
+            None => match set.hmac_until {
+                Some(until) if until <= chrono::Utc::now() => {
+                    return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+                }
+                _ => (jsonwebtoken::Algorithm::HS256, &self.hmac_decoding),
+            },
+        };
+
+        let mut validation = jsonwebtoken::Validation::new(algorithm);
+        if let Some(audience) = audience {
+            validation.set_audience(&[audience]);
+        }
+
+        Ok(jsonwebtoken::decode::<T>(token, key, &validation)?.claims)
+    }
+
+    /// The public keys tokens may be signed with, active one included.
+    pub fn jwks(&self) -> jsonwebtoken::jwk::JwkSet {
+        let set = self.set.read().expect("key set lock is poisoned");
+        jsonwebtoken::jwk::JwkSet {
+            keys: set.jwks.clone(),
+        }
+    }
+}
+
+/// Picks up keys generated or rotated from other processes.
+pub fn spawn_refresher(keys: std::sync::Arc<Keys>, queries: Queries) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(REFRESH_INTERVAL_SECS));
+        // The first tick is immediate and the keys were just loaded
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = keys.refresh(&queries).await {
+                tracing::warn!("can't reload signing keys: {}", err);
+            }
+        }
+    });
+}
+
+fn encode(bytes: &[u8]) -> String {
+    data_encoding::BASE64URL_NOPAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lifetime() -> chrono::Duration {
+        chrono::Duration::hours(1)
+    }
+
+    fn claims() -> serde_json::Value {
+        serde_json::json!({
+            "sub": "someone",
+            "exp": (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp(),
+        })
+    }
+
+    fn key(
+        activated_at: Option<chrono::DateTime<chrono::Utc>>,
+        retired_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> SigningKey {
+        let algorithm = KeyAlgorithm::EdDsa;
+        let generated = generate(algorithm).unwrap();
+        SigningKey {
+            id: uuid::Uuid::new_v4(),
+            activated_at,
+            retired_at,
+            algorithm: algorithm.as_str().to_string(),
+            private_key: generated.private_key,
+            public_jwk: generated.public_jwk,
+        }
+    }
+
+    /// Loads `key` again, as `refresh` would after the row changed.
+    fn copy(key: &SigningKey) -> SigningKey {
+        SigningKey {
+            id: key.id,
+            activated_at: key.activated_at,
+            retired_at: key.retired_at,
+            algorithm: key.algorithm.clone(),
+            private_key: key.private_key.clone(),
+            public_jwk: key.public_jwk.clone(),
+        }
+    }
+
+    fn kid(token: &str) -> Option<String> {
+        jsonwebtoken::decode_header(token).unwrap().kid
+    }
+
+    #[test]
+    fn signs_with_the_shared_secret_until_a_key_is_active() {
+        let keys = Keys::new("secret", lifetime());
+        let token = keys.encode(&claims()).unwrap();
+        assert_eq!(kid(&token), None);
+        assert!(keys.decode::<serde_json::Value>(&token, None).is_ok());
+
+        // A pending key is published but doesn't sign
+        let pending = key(None, None);
+        keys.load(vec![copy(&pending)]).unwrap();
+        assert_eq!(kid(&keys.encode(&claims()).unwrap()), None);
+        assert_eq!(keys.jwks().keys.len(), 1);
+
+        keys.load(vec![key(Some(chrono::Utc::now()), None)])
+            .unwrap();
+        let signed = keys.encode(&claims()).unwrap();
+        assert!(kid(&signed).is_some());
+        assert_eq!(
+            keys.decode::<serde_json::Value>(&signed, None).unwrap()["sub"],
+            "someone"
+        );
+        assert!(
+            Keys::new("other", lifetime())
+                .decode::<serde_json::Value>(&token, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn checks_the_audience() {
+        let keys = Keys::new("secret", lifetime());
+        let mut claims = claims();
+        claims["aud"] = "mfa".into();
+        let token = keys.encode(&claims).unwrap();
+
+        assert!(
+            keys.decode::<serde_json::Value>(&token, Some("mfa"))
+                .is_ok()
+        );
+        assert!(
+            keys.decode::<serde_json::Value>(&token, Some("other"))
+                .is_err()
+        );
+        assert!(keys.decode::<serde_json::Value>(&token, None).is_err());
+    }
+
+    #[test]
+    fn shared_secret_tokens_last_one_lifetime_after_activation() {
+        let keys = Keys::new("secret", lifetime());
+        let token = keys.encode(&claims()).unwrap();
+
+        let now = chrono::Utc::now();
+        keys.load(vec![key(
+            Some(now - lifetime() + chrono::Duration::minutes(1)),
+            None,
+        )])
+        .unwrap();
+        assert!(keys.decode::<serde_json::Value>(&token, None).is_ok());
+
+        keys.load(vec![key(Some(now - lifetime()), None)]).unwrap();
+        assert!(keys.decode::<serde_json::Value>(&token, None).is_err());
+
+        // The oldest activation left counts, not the active key's
+        keys.load(vec![
+            key(Some(now - lifetime() * 2), Some(now)),
+            key(Some(now), None),
+        ])
+        .unwrap();
+        assert!(keys.decode::<serde_json::Value>(&token, None).is_err());
+    }
+
+    #[test]
+    fn retired_keys_verify_until_their_grace_period_ends() {
+        let keys = Keys::new("secret", lifetime());
+        let now = chrono::Utc::now();
+        let mut first = key(Some(now), None);
+        keys.load(vec![copy(&first)]).unwrap();
+        let old_token = keys.encode(&claims()).unwrap();
+        assert_eq!(kid(&old_token), Some(first.id.to_string()));
+
+        // Rotation: the first key is retired, the second one signs
+        first.retired_at = Some(now);
+        let second = key(Some(now), None);
+        keys.load(vec![copy(&first), copy(&second)]).unwrap();
+        let new_token = keys.encode(&claims()).unwrap();
+        assert_eq!(kid(&new_token), Some(second.id.to_string()));
+        assert!(keys.decode::<serde_json::Value>(&old_token, None).is_ok());
+        assert!(keys.decode::<serde_json::Value>(&new_token, None).is_ok());
+
+        // Past its grace period `verification_keys` no longer returns it
+        keys.load(vec![copy(&second)]).unwrap();
+        assert!(keys.decode::<serde_json::Value>(&old_token, None).is_err());
+        assert!(keys.decode::<serde_json::Value>(&new_token, None).is_ok());
+        assert_eq!(keys.jwks().keys.len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_key_ids() {
+        let keys = Keys::new("secret", lifetime());
+        keys.load(vec![key(Some(chrono::Utc::now()), None)])
+            .unwrap();
+        let token = keys.encode(&claims()).unwrap();
+
+        keys.load(vec![key(Some(chrono::Utc::now()), None)])
+            .unwrap();
+        assert!(keys.decode::<serde_json::Value>(&token, None).is_err());
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn rotation_keeps_the_retired_key_for_the_grace_period(pool: sqlx::PgPool) {
+        let queries = Queries::new(pool);
+        let keys = Keys::new("secret", lifetime());
+        let create = || async {
+            let generated = generate(KeyAlgorithm::EdDsa).unwrap();
+            queries
+                .create_signing_key(
+                    KeyAlgorithm::EdDsa.as_str(),
+                    &generated.private_key,
+                    &generated.public_jwk,
+                )
+                .await
+                .map_err(|err| err.to_string())
+                .unwrap()
+        };
+
+        let first = create().await;
+        assert!(
+            queries
+                .activate_signing_key(first, 1)
+                .await
+                .is_ok_and(|ok| ok)
+        );
+        keys.refresh(&queries).await.unwrap();
+        let old_token = keys.encode(&claims()).unwrap();
+
+        let second = create().await;
+        assert!(
+            queries
+                .activate_signing_key(second, 1)
+                .await
+                .is_ok_and(|ok| ok)
+        );
+        // Only pending keys can be activated
+        assert!(
+            queries
+                .activate_signing_key(first, 1)
+                .await
+                .is_ok_and(|ok| !ok)
+        );
+        keys.refresh(&queries).await.unwrap();
+        assert_eq!(
+            kid(&keys.encode(&claims()).unwrap()),
+            Some(second.to_string())
+        );
+        assert!(keys.decode::<serde_json::Value>(&old_token, None).is_ok());
+
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        keys.refresh(&queries).await.unwrap();
+        assert!(keys.decode::<serde_json::Value>(&old_token, None).is_err());
+    }
+}
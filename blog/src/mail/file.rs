@@ -0,0 +1,36 @@
+use lettre::AsyncTransport;
+
+/// Writes each email to an `.eml` file in a directory instead of sending it,
+/// for development.
+pub struct FileMailer {
+    from: lettre::message::Mailbox,
+    dir: std::path::PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(from: lettre::message::Mailbox, dir: std::path::PathBuf) -> Self {
+        Self { from, dir }
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Mailer for FileMailer {
+    async fn send(&self, message: super::Message) -> Result<(), String> {
+        let email = super::build(&self.from, message)?;
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let id = lettre::AsyncFileTransport::<lettre::Tokio1Executor>::new(&self.dir)
+            .send(email)
+            .await
+            .map_err(|err| err.to_string())?;
+        tracing::info!(
+            "wrote email to {}",
+            self.dir.join(format!("{}.eml", id)).display()
+        );
+
+        Ok(())
+    }
+}
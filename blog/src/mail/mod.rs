@@ -0,0 +1,51 @@
+pub mod file;
+pub mod smtp;
+
+/// A plain text email to one recipient.
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers outgoing email.
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: Message) -> Result<(), String>;
+}
+
+/// Builds the backend selected by `mail.backend`.
+pub fn from_config(
+    config: &crate::config::MailConfig,
+) -> Result<std::sync::Arc<dyn Mailer>, String> {
+    let from: lettre::message::Mailbox = config
+        .from
+        .parse()
+        .map_err(|err| format!("invalid mail.from: {}", err))?;
+
+    match config.backend {
+        crate::config::MailBackend::Smtp => Ok(std::sync::Arc::new(smtp::SmtpMailer::new(
+            from,
+            &config.smtp,
+        )?)),
+        crate::config::MailBackend::File => Ok(std::sync::Arc::new(file::FileMailer::new(
+            from,
+            config.dir.clone(),
+        ))),
+    }
+}
+
+fn build(from: &lettre::message::Mailbox, message: Message) -> Result<lettre::Message, String> {
+    let to: lettre::message::Mailbox = message
+        .to
+        .parse()
+        .map_err(|err| format!("invalid recipient {}: {}", message.to, err))?;
+
+    lettre::Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(message.subject)
+        .header(lettre::message::header::ContentType::TEXT_PLAIN)
+        .body(message.body)
+        .map_err(|err| err.to_string())
+}
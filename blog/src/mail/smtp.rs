@@ -0,0 +1,56 @@
+use lettre::AsyncTransport;
+
+pub struct SmtpMailer {
+    from: lettre::message::Mailbox,
+    transport: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        from: lettre::message::Mailbox,
+        config: &crate::config::SmtpConfig,
+    ) -> Result<Self, String> {
+        let host = config.host.as_deref().ok_or("mail.smtp.host is not set")?;
+
+        let mut builder = match config.tls {
+            crate::config::SmtpTls::Tls => {
+                lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::relay(host)
+            }
+            crate::config::SmtpTls::Starttls => {
+                lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::starttls_relay(host)
+            }
+            crate::config::SmtpTls::None => {
+                Ok(lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::builder_dangerous(host))
+            }
+        }
+        .map_err(|err| err.to_string())?
+        .port(config.port);
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder =
+                builder.credentials(lettre::transport::smtp::authentication::Credentials::new(
+                    username.clone(),
+                    password.clone(),
+                ));
+        }
+
+        Ok(Self {
+            from,
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Mailer for SmtpMailer {
+    async fn send(&self, message: super::Message) -> Result<(), String> {
+        let email = super::build(&self.from, message)?;
+
+        self.transport
+            .send(email)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(())
+    }
+}
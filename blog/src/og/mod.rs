@@ -0,0 +1,330 @@
+use ab_glyph::{Font, ScaleFont};
+
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 630;
+
+// Bump when the card layout changes so cached images are regenerated
+pub const CARD_VERSION: u32 = 2;
+
+const PADDING: f32 = 80.0;
+const FOOTER_PADDING: u32 = 64;
+const AVATAR_SIZE: u32 = 72;
+
+const BACKGROUND: [u8; 3] = [17, 24, 39];
+const ACCENT: [u8; 3] = [56, 189, 248];
+const TITLE: [u8; 3] = [249, 250, 251];
+const TEXT: [u8; 3] = [156, 163, 175];
+
+static FONT_REGULAR: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+static FONT_BOLD: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+
+// A render holds a full-size canvas and keeps a core busy for a while, so
+// only a few run at once and the rest wait for a permit.
+static RENDER_PERMITS: std::sync::LazyLock<tokio::sync::Semaphore> =
+    std::sync::LazyLock::new(|| {
+        let permits = std::thread::available_parallelism()
+            .map(|parallelism| parallelism.get())
+            .unwrap_or(2);
+        tokio::sync::Semaphore::new(permits)
+    });
+
+pub struct Card {
+    pub title: String,
+    pub excerpt: String,
+    pub author: String,
+    /// The author's photo, drawn in place of their initial when it decodes.
+    pub photo: Option<Vec<u8>>,
+    pub site_name: String,
+    pub site_host: String,
+}
+
+/// Renders the card as a PNG on the blocking pool.
+pub async fn render(card: Card) -> Result<Vec<u8>, String> {
+    let _permit = RENDER_PERMITS
+        .acquire()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    tokio::task::spawn_blocking(move || render_blocking(&card))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn render_blocking(card: &Card) -> Result<Vec<u8>, String> {
+    let regular = ab_glyph::FontRef::try_from_slice(FONT_REGULAR).map_err(|err| err.to_string())?;
+    let bold = ab_glyph::FontRef::try_from_slice(FONT_BOLD).map_err(|err| err.to_string())?;
+
+    let mut image = image::RgbImage::from_pixel(WIDTH, HEIGHT, image::Rgb(BACKGROUND));
+    let content_width = WIDTH as f32 - PADDING * 2.0;
+
+    fill_rect(&mut image, 0, 0, WIDTH, 12, ACCENT);
+    draw_text(
+        &mut image,
+        &bold,
+        32.0,
+        PADDING,
+        PADDING - 16.0,
+        &card.site_name,
+        ACCENT,
+    );
+
+    let mut y = 140.0;
+    for line in wrap(&bold, 56.0, &card.title, content_width, 3) {
+        draw_text(&mut image, &bold, 56.0, PADDING, y, &line, TITLE);
+        y += 68.0;
+    }
+
+    y += 20.0;
+    for line in wrap(&regular, 32.0, &card.excerpt, content_width, 2) {
+        draw_text(&mut image, &regular, 32.0, PADDING, y, &line, TEXT);
+        y += 44.0;
+    }
+
+    let avatar_x = PADDING as u32;
+    let avatar_y = HEIGHT - FOOTER_PADDING - AVATAR_SIZE;
+    let photo = card.photo.as_deref().and_then(|photo| {
+        image::load_from_memory(photo)
+            .inspect_err(|err| tracing::warn!("can't decode author photo: {}", err))
+            .ok()
+    });
+    match photo {
+        Some(photo) => draw_photo(&mut image, &photo, avatar_x, avatar_y),
+        None => draw_avatar(&mut image, &bold, avatar_x, avatar_y, &card.author),
+    }
+
+    let footer_middle = (avatar_y + AVATAR_SIZE / 2) as f32;
+
+    let author_x = (avatar_x + AVATAR_SIZE) as f32 + 24.0;
+    let author = fit(&bold, 32.0, &card.author, content_width / 2.0);
+    let author_y = centered_top(&bold, 32.0, footer_middle);
+    draw_text(&mut image, &bold, 32.0, author_x, author_y, &author, TITLE);
+
+    let host_x = WIDTH as f32 - PADDING - text_width(&regular, 28.0, &card.site_host);
+    let host_y = centered_top(&regular, 28.0, footer_middle);
+    draw_text(
+        &mut image,
+        &regular,
+        28.0,
+        host_x,
+        host_y,
+        &card.site_host,
+        TEXT,
+    );
+
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|err| err.to_string())?;
+
+    Ok(png)
+}
+
+/// Draws the photo cropped to a square and masked to a circle.
+fn draw_photo(image: &mut image::RgbImage, photo: &image::DynamicImage, x: u32, y: u32) {
+    let side = photo.width().min(photo.height());
+    let photo = photo
+        .crop_imm(
+            (photo.width() - side) / 2,
+            (photo.height() - side) / 2,
+            side,
+            side,
+        )
+        .resize_exact(
+            AVATAR_SIZE,
+            AVATAR_SIZE,
+            image::imageops::FilterType::Lanczos3,
+        )
+        .to_rgb8();
+
+    let radius = AVATAR_SIZE as f32 / 2.0;
+    for (px, py, pixel) in photo.enumerate_pixels() {
+        let dx = px as f32 + 0.5 - radius;
+        let dy = py as f32 + 0.5 - radius;
+        let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+        blend(image, (x + px) as i32, (y + py) as i32, pixel.0, coverage);
+    }
+}
+
+fn draw_avatar(
+    image: &mut image::RgbImage,
+    font: &ab_glyph::FontRef,
+    x: u32,
+    y: u32,
+    author: &str,
+) {
+    let radius = AVATAR_SIZE as f32 / 2.0;
+    let center_x = x as f32 + radius;
+    let center_y = y as f32 + radius;
+
+    for py in y..y + AVATAR_SIZE {
+        for px in x..x + AVATAR_SIZE {
+            let dx = px as f32 + 0.5 - center_x;
+            let dy = py as f32 + 0.5 - center_y;
+            let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            blend(image, px as i32, py as i32, ACCENT, coverage);
+        }
+    }
+
+    let initial = author
+        .chars()
+        .find(|c| c.is_alphanumeric())
+        .unwrap_or('?')
+        .to_uppercase()
+        .to_string();
+    let initial_x = center_x - text_width(font, 36.0, &initial) / 2.0;
+    let initial_y = centered_top(font, 36.0, center_y);
+    draw_text(
+        image, font, 36.0, initial_x, initial_y, &initial, BACKGROUND,
+    );
+}
+
+/// Top edge for a line of text that is vertically centered on `middle`.
+fn centered_top(font: &ab_glyph::FontRef, size: f32, middle: f32) -> f32 {
+    let scaled = font.as_scaled(size);
+    middle - (scaled.ascent() - scaled.descent()) / 2.0
+}
+
+/// Greedily wraps `text` into at most `max_lines` lines, ending the last line
+/// with an ellipsis when the text does not fit.
+fn wrap(
+    font: &ab_glyph::FontRef,
+    size: f32,
+    text: &str,
+    max_width: f32,
+    max_lines: usize,
+) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut index = 0;
+
+    while index < words.len() {
+        if lines.len() + 1 == max_lines {
+            let rest = std::iter::once(current.as_str())
+                .filter(|current| !current.is_empty())
+                .chain(words[index..].iter().copied())
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push(fit(font, size, &rest, max_width));
+            return lines;
+        }
+
+        let candidate = if current.is_empty() {
+            words[index].to_string()
+        } else {
+            format!("{} {}", current, words[index])
+        };
+
+        if text_width(font, size, &candidate) <= max_width {
+            current = candidate;
+            index += 1;
+        } else if current.is_empty() {
+            // A single word wider than the line is cut at the character level
+            lines.push(fit(font, size, words[index], max_width));
+            index += 1;
+        } else {
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+/// Truncates `text` with an ellipsis so that it fits in `max_width`.
+fn fit(font: &ab_glyph::FontRef, size: f32, text: &str, max_width: f32) -> String {
+    if text_width(font, size, text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut fitted = String::new();
+    for c in text.chars() {
+        let candidate = format!("{}{}…", fitted, c);
+        if text_width(font, size, &candidate) > max_width {
+            break;
+        }
+        fitted.push(c);
+    }
+
+    format!("{}…", fitted.trim_end())
+}
+
+fn text_width(font: &ab_glyph::FontRef, size: f32, text: &str) -> f32 {
+    let scaled = font.as_scaled(size);
+    let mut width = 0.0;
+    let mut previous: Option<ab_glyph::GlyphId> = None;
+
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    width
+}
+
+/// Draws a single line of text with its top edge at `y`.
+fn draw_text(
+    image: &mut image::RgbImage,
+    font: &ab_glyph::FontRef,
+    size: f32,
+    x: f32,
+    y: f32,
+    text: &str,
+    color: [u8; 3],
+) {
+    let scaled = font.as_scaled(size);
+    let baseline = y + scaled.ascent();
+    let mut caret = x;
+    let mut previous: Option<ab_glyph::GlyphId> = None;
+
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+
+        let glyph = id.with_scale_and_position(size, ab_glyph::point(caret, baseline));
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                blend(
+                    image,
+                    bounds.min.x as i32 + gx as i32,
+                    bounds.min.y as i32 + gy as i32,
+                    color,
+                    coverage,
+                );
+            });
+        }
+
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+}
+
+fn fill_rect(image: &mut image::RgbImage, x: u32, y: u32, width: u32, height: u32, color: [u8; 3]) {
+    for py in y..(y + height).min(HEIGHT) {
+        for px in x..(x + width).min(WIDTH) {
+            image.put_pixel(px, py, image::Rgb(color));
+        }
+    }
+}
+
+fn blend(image: &mut image::RgbImage, x: i32, y: i32, color: [u8; 3], coverage: f32) {
+    if x < 0 || y < 0 || x >= WIDTH as i32 || y >= HEIGHT as i32 || coverage <= 0.0 {
+        return;
+    }
+
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let coverage = coverage.min(1.0);
+    for (channel, target) in pixel.0.iter_mut().zip(color) {
+        *channel = (*channel as f32 * (1.0 - coverage) + target as f32 * coverage).round() as u8;
+    }
+}
@@ -0,0 +1,155 @@
+use argon2::{PasswordHasher, PasswordVerifier};
+
+// Each hash takes tens of milliseconds of CPU and ~19 MiB of memory, so only
+// a few run at once; further logins wait for a permit instead of piling onto
+// the blocking pool.
+static HASHING_PERMITS: std::sync::LazyLock<tokio::sync::Semaphore> =
+    std::sync::LazyLock::new(|| {
+        let permits = std::thread::available_parallelism()
+            .map(|parallelism| parallelism.get())
+            .unwrap_or(2);
+        tokio::sync::Semaphore::new(permits)
+    });
+
+// Verified against when an account doesn't exist, with the current parameters
+static DUMMY_HASH: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
+    hash_blocking(&uuid::Uuid::new_v4().to_string()).expect("can't create dummy password hash")
+});
+
+pub struct Verification {
+    pub valid: bool,
+    /// The hash uses an older algorithm or parameters and should be replaced.
+    pub needs_rehash: bool,
+}
+
+/// Hashes a password with Argon2id using the current parameters.
+pub async fn hash(password: String) -> Result<String, String> {
+    run_blocking(move || hash_blocking(&password)).await
+}
+
+/// Verifies a password against an Argon2 or legacy bcrypt hash.
+pub async fn verify(password: String, hash: String) -> Result<Verification, String> {
+    run_blocking(move || verify_blocking(&password, &hash)).await
+}
+
+/// Spends the same time as verifying a real password, for unknown accounts.
+pub async fn verify_dummy(password: String) -> Result<(), String> {
+    run_blocking(move || verify_blocking(&password, &DUMMY_HASH).map(|_| ())).await
+}
+
+async fn run_blocking<T, F>(work: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    let _permit = HASHING_PERMITS
+        .acquire()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn argon2() -> argon2::Argon2<'static> {
+    argon2::Argon2::new(
+        argon2::Algorithm::Argon2id,
+        argon2::Version::V0x13,
+        argon2::Params::default(),
+    )
+}
+
+fn hash_blocking(password: &str) -> Result<String, String> {
+    let salt =
+        argon2::password_hash::SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+
+    argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| err.to_string())
+}
+
+fn verify_blocking(password: &str, hash: &str) -> Result<Verification, String> {
+    // Accounts created before Argon2 was introduced carry bcrypt hashes
+    if hash.starts_with("$2") {
+        let valid = bcrypt::verify(password, hash).map_err(|err| err.to_string())?;
+        return Ok(Verification {
+            valid,
+            needs_rehash: true,
+        });
+    }
+
+    let parsed = argon2::PasswordHash::new(hash).map_err(|err| err.to_string())?;
+    let valid = match argon2().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => true,
+        Err(argon2::password_hash::Error::Password) => false,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let current = argon2::Params::default();
+    let needs_rehash = parsed.algorithm != argon2::Algorithm::Argon2id.ident()
+        || parsed.version != Some(argon2::Version::V0x13.into())
+        || argon2::Params::try_from(&parsed).is_ok_and(|params| {
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        });
+
+    Ok(Verification {
+        valid,
+        needs_rehash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery";
+
+    fn argon2_hash(algorithm: argon2::Algorithm, params: argon2::Params) -> String {
+        let salt = argon2::password_hash::SaltString::generate(
+            &mut argon2::password_hash::rand_core::OsRng,
+        );
+        argon2::Argon2::new(algorithm, argon2::Version::V0x13, params)
+            .hash_password(PASSWORD.as_bytes(), &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn needs_rehash_flags_outdated_hashes() {
+        let current = argon2::Params::default();
+        let cases = [
+            (hash_blocking(PASSWORD).unwrap(), false),
+            (
+                argon2_hash(
+                    argon2::Algorithm::Argon2id,
+                    argon2::Params::new(current.m_cost() / 2, current.t_cost(), 1, None).unwrap(),
+                ),
+                true,
+            ),
+            (
+                argon2_hash(
+                    argon2::Algorithm::Argon2id,
+                    argon2::Params::new(current.m_cost(), current.t_cost() + 1, 1, None).unwrap(),
+                ),
+                true,
+            ),
+            (
+                argon2_hash(argon2::Algorithm::Argon2i, argon2::Params::default()),
+                true,
+            ),
+            (bcrypt::hash(PASSWORD, 4).unwrap(), true),
+        ];
+
+        for (hash, needs_rehash) in cases {
+            let verification = verify_blocking(PASSWORD, &hash).unwrap();
+            assert!(verification.valid, "{}", hash);
+            assert_eq!(verification.needs_rehash, needs_rehash, "{}", hash);
+
+            assert!(!verify_blocking("wrong password", &hash).unwrap().valid);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+/// Per-process counters. Each replica counts on its own, so use the Postgres
+/// store when running more than one.
+#[derive(Default)]
+pub struct MemoryStore {
+    windows: std::sync::Mutex<std::collections::HashMap<String, Window>>,
+}
+
+struct Window {
+    started_at: std::time::Instant,
+    length: std::time::Duration,
+    count: u64,
+}
+
+#[async_trait::async_trait]
+impl super::RateLimitStore for MemoryStore {
+    async fn hit(&self, key: &str, window: std::time::Duration) -> Result<super::Hit, String> {
+        let now = std::time::Instant::now();
+        let mut windows = self.windows.lock().map_err(|err| err.to_string())?;
+
+        let entry = windows.entry(key.to_string()).or_insert(Window {
+            started_at: now,
+            length: window,
+            count: 0,
+        });
+        if now.duration_since(entry.started_at) >= entry.length {
+            entry.started_at = now;
+            entry.length = window;
+            entry.count = 0;
+        }
+        entry.count += 1;
+
+        Ok(super::Hit {
+            count: entry.count,
+            reset_after: entry
+                .length
+                .saturating_sub(now.duration_since(entry.started_at)),
+        })
+    }
+
+    async fn purge(&self) -> Result<(), String> {
+        let now = std::time::Instant::now();
+        let mut windows = self.windows.lock().map_err(|err| err.to_string())?;
+        windows.retain(|_, window| now.duration_since(window.started_at) < window.length);
+
+        Ok(())
+    }
+}
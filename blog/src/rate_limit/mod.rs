@@ -0,0 +1,218 @@
+pub mod memory;
+pub mod postgres;
+
+use axum::response::IntoResponse;
+
+/// Counts requests per key in fixed windows.
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Records a request for `key` and returns the count in the current window.
+    async fn hit(&self, key: &str, window: std::time::Duration) -> Result<Hit, String>;
+    /// Drops windows that have ended.
+    async fn purge(&self) -> Result<(), String>;
+}
+
+pub struct Hit {
+    pub count: u64,
+    pub reset_after: std::time::Duration,
+}
+
+/// Builds the backend selected by `rate_limit.backend`.
+pub fn from_config(
+    config: &crate::config::RateLimitConfig,
+    pool: sqlx::PgPool,
+) -> std::sync::Arc<dyn RateLimitStore> {
+    match config.backend {
+        crate::config::RateLimitBackend::Memory => {
+            std::sync::Arc::new(memory::MemoryStore::default())
+        }
+        crate::config::RateLimitBackend::Postgres => std::sync::Arc::new(
+            postgres::PostgresStore::new(crate::db::repositories::Queries::new(pool)),
+        ),
+    }
+}
+
+/// Periodically removes ended windows so the store doesn't grow unbounded.
+pub fn spawn_purger(store: std::sync::Arc<dyn RateLimitStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
+        loop {
+            interval.tick().await;
+            if let Err(err) = store.purge().await {
+                tracing::warn!("can't purge rate limits: {}", err);
+            }
+        }
+    });
+}
+
+/// Everything the middleware needs to limit one route group.
+#[derive(Clone)]
+pub struct RateLimit {
+    pub group: &'static str,
+    pub policy: crate::config::RateLimitPolicy,
+    pub store: std::sync::Arc<dyn RateLimitStore>,
+    pub trusted_proxies: std::sync::Arc<[ipnet::IpNet]>,
+}
+
+/// Limits requests per client IP or, for authenticated routes keyed by
+/// account, per account id. Must run after `auth_middleware` for the latter.
+pub async fn rate_limit_middleware(
+    axum::extract::State(limit): axum::extract::State<RateLimit>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let account = match limit.policy.key {
+        crate::config::RateLimitKey::Account => request
+            .extensions()
+            .get::<crate::routes::accounts::Claims>()
+            .map(|claims| claims.sub),
+        crate::config::RateLimitKey::Ip => None,
+    };
+
+    let key = match account {
+        Some(account_id) => format!("{}:account:{}", limit.group, account_id),
+        None => format!(
+            "{}:ip:{}",
+            limit.group,
+            crate::routes::client_ip(request.headers(), peer, &limit.trusted_proxies)
+        ),
+    };
+
+    let window = std::time::Duration::from_secs(limit.policy.window_secs);
+    let hit = match limit.store.hit(&key, window).await {
+        Ok(hit) => hit,
+        Err(err) => {
+            // Fail open, an unavailable store shouldn't take the site down
+            tracing::warn!("can't check rate limit: {}", err);
+            return next.run(request).await;
+        }
+    };
+
+    let reset_secs = hit.reset_after.as_secs_f64().ceil().max(1.0) as u64;
+    let remaining = limit.policy.requests.saturating_sub(hit.count);
+
+    let mut response = if hit.count > limit.policy.requests {
+        metrics::counter!("rate_limited_requests_total", "group" => limit.group).increment(1);
+        (
+            axum::http::StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, reset_secs.to_string())],
+            axum::response::Json(serde_json::json!({ "error": "too many requests" })),
+        )
+            .into_response()
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.insert("ratelimit-limit", limit.policy.requests.into());
+    headers.insert("ratelimit-remaining", remaining.into());
+    headers.insert("ratelimit-reset", reset_secs.into());
+    if let Ok(value) = axum::http::HeaderValue::from_str(&format!(
+        "{};w={}",
+        limit.policy.requests, limit.policy.window_secs
+    )) {
+        headers.insert("ratelimit-policy", value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app_with_rate_limit, lazy_pool, request};
+    use axum::http::{Method, StatusCode};
+
+    struct UnavailableStore;
+
+    #[async_trait::async_trait]
+    impl RateLimitStore for UnavailableStore {
+        async fn hit(&self, _key: &str, _window: std::time::Duration) -> Result<Hit, String> {
+            Err("connection refused".to_string())
+        }
+
+        async fn purge(&self) -> Result<(), String> {
+            Err("connection refused".to_string())
+        }
+    }
+
+    /// Logs in without credentials, which fails validation before touching
+    /// the database, from `ip`.
+    async fn login(app: &axum::Router, ip: [u8; 4]) -> axum::response::Response {
+        let mut request = request(
+            Method::POST,
+            "/api/login",
+            None,
+            Some(serde_json::json!({})),
+        );
+        request
+            .extensions_mut()
+            .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                ip, 443,
+            ))));
+
+        tower::ServiceExt::oneshot(app.clone(), request)
+            .await
+            .unwrap()
+    }
+
+    fn header<'a>(response: &'a axum::response::Response, name: &str) -> Option<&'a str> {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    fn config(requests: u64) -> crate::config::Config {
+        let mut config = crate::config::Config::default();
+        config.rate_limit.auth.requests = requests;
+        config.rate_limit.auth.window_secs = 60;
+        config
+    }
+
+    #[tokio::test]
+    async fn requests_past_the_budget_are_turned_away() {
+        let app = app_with_rate_limit(
+            lazy_pool(),
+            &config(2),
+            Some(std::sync::Arc::new(memory::MemoryStore::default())),
+        );
+
+        for remaining in ["1", "0"] {
+            let response = login(&app, [203, 0, 113, 1]).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(header(&response, "ratelimit-limit"), Some("2"));
+            assert_eq!(header(&response, "ratelimit-remaining"), Some(remaining));
+            assert_eq!(header(&response, "ratelimit-policy"), Some("2;w=60"));
+        }
+
+        let response = login(&app, [203, 0, 113, 1]).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "ratelimit-remaining"), Some("0"));
+        let retry_after: u64 = header(&response, "retry-after").unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after), "{}", retry_after);
+        assert_eq!(
+            header(&response, "ratelimit-reset"),
+            Some(retry_after.to_string().as_str())
+        );
+
+        // Other addresses have budgets of their own
+        let response = login(&app, [203, 0, 113, 2]).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(header(&response, "ratelimit-remaining"), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn an_unavailable_store_lets_requests_through() {
+        let app = app_with_rate_limit(
+            lazy_pool(),
+            &config(0),
+            Some(std::sync::Arc::new(UnavailableStore)),
+        );
+
+        let response = login(&app, [203, 0, 113, 1]).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(header(&response, "ratelimit-limit"), None);
+    }
+}
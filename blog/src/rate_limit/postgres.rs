@@ -0,0 +1,96 @@
+use crate::db::repositories::Queries;
+
+/// Counters shared by every replica through the `rate_limits` table.
+pub struct PostgresStore {
+    queries: Queries,
+}
+
+impl PostgresStore {
+    pub fn new(queries: Queries) -> Self {
+        Self { queries }
+    }
+}
+
+#[async_trait::async_trait]
+impl super::RateLimitStore for PostgresStore {
+    async fn hit(&self, key: &str, window: std::time::Duration) -> Result<super::Hit, String> {
+        let (count, reset_after_secs) = self
+            .queries
+            .hit_rate_limit(key, window.as_secs_f64())
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(super::Hit {
+            count: count.max(0) as u64,
+            reset_after: std::time::Duration::from_secs_f64(reset_after_secs.max(0.0)),
+        })
+    }
+
+    async fn purge(&self) -> Result<(), String> {
+        self.queries
+            .purge_rate_limits()
+            .await
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::RateLimitStore;
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn counts_hits_per_key_and_window(pool: sqlx::PgPool) {
+        let store = PostgresStore::new(Queries::new(pool.clone()));
+        let window = std::time::Duration::from_secs(3600);
+
+        for expected in 1..=3 {
+            let hit = store.hit("auth:ip:203.0.113.1", window).await.unwrap();
+            assert_eq!(hit.count, expected);
+            assert!(hit.reset_after <= window, "{:?}", hit.reset_after);
+        }
+        let hit = store.hit("auth:ip:203.0.113.2", window).await.unwrap();
+        assert_eq!(hit.count, 1);
+
+        // A changed policy starts a new window
+        let hit = store
+            .hit("auth:ip:203.0.113.1", std::time::Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(hit.count, 1);
+        assert!(hit.reset_after <= std::time::Duration::from_secs(60));
+
+        // As does the end of the old one
+        sqlx::query("update rate_limits set window_start = window_start - interval '2 hours'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let hit = store.hit("auth:ip:203.0.113.2", window).await.unwrap();
+        assert_eq!(hit.count, 1);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn purge_drops_ended_windows(pool: sqlx::PgPool) {
+        let store = PostgresStore::new(Queries::new(pool.clone()));
+        let window = std::time::Duration::from_secs(60);
+        store.hit("ended", window).await.unwrap();
+        store.hit("current", window).await.unwrap();
+        sqlx::query(
+            "update rate_limits set window_start = window_start - interval '2 minutes'
+            where key = 'ended'",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        store.purge().await.unwrap();
+
+        let keys: Vec<String> = sqlx::query_scalar("select key from rate_limits")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(keys, ["current"]);
+    }
+}
@@ -0,0 +1,440 @@
+use super::AppState;
+use crate::db::repositories::access_tokens::CreateAccessTokenParams;
+use axum::response::IntoResponse;
+
+/// Marks personal access tokens so `auth_middleware` can tell them from JWTs
+/// and secret scanners can find leaked ones.
+pub const TOKEN_PREFIX: &str = "lpat_";
+const TOKEN_BYTES: usize = 32;
+// How much of the token is kept in the clear for listing
+const DISPLAY_PREFIX_LEN: usize = TOKEN_PREFIX.len() + 6;
+const MAX_NAME_LEN: usize = 100;
+
+/// What a personal access token may do. Account management (passkeys, 2FA,
+/// other tokens) isn't a scope, it always needs a login session.
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum Scope {
+    #[serde(rename = "account:read")]
+    AccountRead,
+    #[serde(rename = "posts:write")]
+    PostsWrite,
+    #[serde(rename = "comments:write")]
+    CommentsWrite,
+    #[serde(rename = "media:write")]
+    MediaWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::AccountRead => "account:read",
+            Scope::PostsWrite => "posts:write",
+            Scope::CommentsWrite => "comments:write",
+            Scope::MediaWrite => "media:write",
+        }
+    }
+}
+
+/// Added to requests authenticated with a personal access token, next to the
+/// `Claims` of its account.
+#[derive(Clone)]
+pub struct TokenScopes(pub Vec<String>);
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct CreateTokenParams {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Days until the token expires, it never does when left out
+    pub expires_in_days: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me/tokens",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    responses((status = 200, body = super::openapi::Data<Vec<crate::db::repositories::access_tokens::AccessToken>>))
+)]
+pub async fn list_tokens(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+) -> impl axum::response::IntoResponse {
+    match state.queries.list_access_tokens(claims.sub).await {
+        Ok(tokens) => (
+            axum::http::StatusCode::OK,
+            axum::response::Json(serde_json::json!({"data": tokens})),
+        ),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        ),
+    }
+}
+
+/// Creates a token. The response is the only time the token itself is shown,
+/// only its hash is stored.
+#[utoipa::path(
+    post,
+    path = "/api/me/tokens",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    request_body = CreateTokenParams,
+    responses(
+        (status = 201, body = super::openapi::CreatedToken),
+        (status = 400, body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn create_token(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::Json(params): axum::Json<CreateTokenParams>,
+) -> impl axum::response::IntoResponse {
+    let name = params.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::response::Json(serde_json::json!({
+                "error": format!("name must be 1 to {} characters", MAX_NAME_LEN)
+            })),
+        );
+    }
+    if params.scopes.is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::response::Json(serde_json::json!({"error": "at least one scope is required"})),
+        );
+    }
+
+    let expires_at = match params.expires_in_days {
+        None => None,
+        Some(days) if (1..=3650).contains(&days) => {
+            Some(chrono::Utc::now() + chrono::Duration::days(days))
+        }
+        Some(_) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(
+                    serde_json::json!({"error": "expires_in_days must be between 1 and 3650"}),
+                ),
+            );
+        }
+    };
+
+    let mut scopes: Vec<String> = params
+        .scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+
+    let token = generate_token();
+    let created = state
+        .queries
+        .create_access_token(
+            claims.sub,
+            &CreateAccessTokenParams {
+                name,
+                token_prefix: &token[..DISPLAY_PREFIX_LEN],
+                token_hash: &hash_token(&token),
+                scopes: &scopes,
+                expires_at,
+            },
+        )
+        .await;
+
+    match created {
+        Ok(created) => (
+            axum::http::StatusCode::CREATED,
+            axum::response::Json(serde_json::json!({"data": created, "token": token})),
+        ),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        ),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/me/tokens/{token_id}",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    params(("token_id" = uuid::Uuid, Path)),
+    responses(
+        (status = 204, description = "The token was revoked"),
+        (status = 404, body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn revoke_token(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::extract::Path(token_id): axum::extract::Path<uuid::Uuid>,
+) -> axum::response::Response {
+    match state
+        .queries
+        .revoke_access_token(claims.sub, token_id)
+        .await
+    {
+        Ok(true) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::response::Json(serde_json::json!({"error": "token not found"})),
+        )
+            .into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
+
+/// Resolves a personal access token to the claims of its account and its
+/// scopes. None when the token is unknown, expired or revoked.
+pub async fn authenticate(
+    state: &AppState,
+    token: &str,
+) -> Result<Option<(super::accounts::Claims, TokenScopes)>, crate::db::repositories::Error> {
+    let Some(grant) = state.queries.find_access_token(&hash_token(token)).await? else {
+        return Ok(None);
+    };
+
+    if let Err(err) = state.queries.touch_access_token(grant.id).await {
+        tracing::warn!("can't record access token use: {}", err);
+    }
+
+    let claims = super::accounts::Claims {
+        sub: grant.account_id,
+        exp: grant
+            .expires_at
+            .map_or(i64::MAX, |expires_at| expires_at.timestamp()),
+        email: grant.email,
+        account_name: grant.account_name,
+        csrf: None,
+    };
+
+    Ok(Some((claims, TokenScopes(grant.scopes))))
+}
+
+/// Lets personal access tokens through only when they carry `scope`.
+/// Sessions may do everything.
+pub async fn require_scope(
+    axum::extract::State(scope): axum::extract::State<Scope>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if let Some(TokenScopes(scopes)) = request.extensions().get::<TokenScopes>()
+        && !scopes.iter().any(|granted| granted == scope.as_str())
+    {
+        return (
+            axum::http::StatusCode::FORBIDDEN,
+            axum::response::Json(serde_json::json!({
+                "error": format!("token is missing the {} scope", scope.as_str())
+            })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Keeps personal access tokens away from account management, so a leaked
+/// token can't mint more tokens or change how the account logs in.
+pub async fn session_only(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if request.extensions().get::<TokenScopes>().is_some() {
+        return (
+            axum::http::StatusCode::FORBIDDEN,
+            axum::response::Json(
+                serde_json::json!({"error": "personal access tokens can't manage the account"}),
+            ),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+fn generate_token() -> String {
+    use rand::Rng;
+
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::rng().fill(&mut bytes);
+    format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        data_encoding::BASE64URL_NOPAD.encode(&bytes)
+    )
+}
+
+fn hash_token(token: &str) -> String {
+    use sha2::Digest;
+
+    hex::encode(sha2::Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::tests::{app, request, send};
+    use axum::http::{Method, StatusCode};
+
+    async fn register(app: &axum::Router) -> String {
+        let (status, body) = send(
+            app,
+            request(
+                Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": "someone",
+                    "email": "someone@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        body["token"].as_str().unwrap().to_string()
+    }
+
+    /// Creates a personal access token with `scopes` from a login session,
+    /// returning its id and the token.
+    async fn create_token(app: &axum::Router, session: &str, scopes: &[&str]) -> (String, String) {
+        let (status, body) = send(
+            app,
+            request(
+                Method::POST,
+                "/api/me/tokens",
+                Some(session),
+                Some(serde_json::json!({"name": "ci", "scopes": scopes})),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        (
+            body["data"]["id"].as_str().unwrap().to_string(),
+            body["token"].as_str().unwrap().to_string(),
+        )
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn tokens_only_reach_the_routes_of_their_scopes(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        let session = register(&app).await;
+        let (_, token) = create_token(&app, &session, &["posts:write"]).await;
+
+        let (status, body) = send(
+            &app,
+            request(
+                Method::POST,
+                "/api/posts",
+                Some(&token),
+                Some(serde_json::json!({"content": "hello"})),
+            ),
+        )
+        .await;
+        assert!(status.is_success(), "{} {}", status, body);
+
+        let (status, body) = send(
+            &app,
+            request(Method::POST, "/api/media", Some(&token), None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "token is missing the media:write scope");
+
+        let (status, _) = send(&app, request(Method::GET, "/api/me", Some(&token), None)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Sessions aren't limited by scopes
+        let (status, _) = send(&app, request(Method::GET, "/api/me", Some(&session), None)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn expired_and_revoked_tokens_are_rejected(pool: sqlx::PgPool) {
+        let app = app(pool.clone(), &crate::config::Config::default());
+        let session = register(&app).await;
+        let (expired_id, expired) = create_token(&app, &session, &["account:read"]).await;
+        let (revoked_id, revoked) = create_token(&app, &session, &["account:read"]).await;
+
+        for token in [&expired, &revoked] {
+            let (status, _) = send(&app, request(Method::GET, "/api/me", Some(token), None)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        sqlx::query(
+            "update access_tokens set expires_at = now() - interval '1 second' where id = $1",
+        )
+        .bind(uuid::Uuid::parse_str(&expired_id).unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+        let (status, _) = send(
+            &app,
+            request(
+                Method::DELETE,
+                &format!("/api/me/tokens/{}", revoked_id),
+                Some(&session),
+                None,
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        for token in [&expired, &revoked] {
+            let (status, body) =
+                send(&app, request(Method::GET, "/api/me", Some(token), None)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["error"], "invalid token");
+        }
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn tokens_cannot_manage_the_account(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        let session = register(&app).await;
+        let (id, token) = create_token(
+            &app,
+            &session,
+            &[
+                "account:read",
+                "posts:write",
+                "comments:write",
+                "media:write",
+            ],
+        )
+        .await;
+
+        let token_path = format!("/api/me/tokens/{}", id);
+        let cases = [
+            (Method::GET, "/api/me/tokens", None),
+            (
+                Method::POST,
+                "/api/me/tokens",
+                Some(serde_json::json!({"name": "more", "scopes": ["posts:write"]})),
+            ),
+            (Method::DELETE, token_path.as_str(), None),
+            (Method::GET, "/api/me/passkeys", None),
+            (Method::POST, "/api/me/totp", None),
+        ];
+
+        for (method, uri, body) in cases {
+            let (status, response) =
+                send(&app, request(method.clone(), uri, Some(&token), body)).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+            assert_eq!(
+                response["error"],
+                "personal access tokens can't manage the account"
+            );
+        }
+    }
+}
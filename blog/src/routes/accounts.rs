@@ -1,22 +1,88 @@
 use super::AppState;
-use crate::db::repositories::{accounts::CreateAccountParams, accounts::LoginParams};
+use crate::db::repositories::accounts::{Account, CreateAccountParams, LoginParams};
+use crate::db::repositories::login_attempts::{LoginFailures, LoginReservation};
+use axum::response::IntoResponse;
 
+// Failed logins older than this are forgotten
+pub const LOGIN_WINDOW_SECS: i64 = 15 * 60;
+const ACCOUNT_FREE_ATTEMPTS: i64 = 3;
+const IP_FREE_ATTEMPTS: i64 = 20;
+const LOGIN_BACKOFF_BASE_SECS: i64 = 1;
+const LOGIN_LOCKOUT_SECS: i64 = 15 * 60;
+
+const MFA_AUDIENCE: &str = "mfa";
+// Time allowed between the password and the second factor
+const MFA_CHALLENGE_SECS: i64 = 5 * 60;
+
+#[utoipa::path(
+    post,
+    path = "/api/register",
+    tag = "auth",
+    request_body = CreateAccountParams,
+    responses(
+        (status = 200, description = "The account was created and is logged in", body = super::openapi::Session),
+        (status = 400, body = super::openapi::ErrorBody),
+        (status = 403, description = "Registration is disabled", body = super::openapi::ErrorBody),
+    )
+)]
 pub async fn register(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::Json(params): axum::Json<CreateAccountParams>,
-) -> impl axum::response::IntoResponse {
-    let account = match state.queries.create_account(&params).await {
-        Ok(account) => account,
-        Err(err) => {
-            return (
-                axum::http::StatusCode::BAD_REQUEST,
-                axum::response::Json(serde_json::json!({"error": err})),
-            );
-        }
-    };
+) -> axum::response::Response {
+    if !state.features.registration {
+        return (
+            axum::http::StatusCode::FORBIDDEN,
+            axum::response::Json(serde_json::json!({"error": "registration is disabled"})),
+        )
+            .into_response();
+    }
+
+    match state.queries.create_account(&params).await {
+        Ok(account) => session_response(&state, account),
+        Err(err) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Claims {
+    pub sub: uuid::Uuid,
+    pub exp: i64,
+    pub email: String,
+    pub account_name: String,
+    /// Set for cookie sessions, unsafe requests must echo it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf: Option<String>,
+}
+
+/// Proves the password step of a login when the account has 2FA. The `aud`
+/// claim keeps it from passing as a session token: `auth_middleware` doesn't
+/// name an audience, so it rejects every token that carries one.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MfaClaims {
+    pub sub: uuid::Uuid,
+    pub exp: i64,
+    pub aud: String,
+    pub email: String,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct MfaParams {
+    pub mfa_token: String,
+    /// A code from the authenticator app or a recovery code
+    pub code: String,
+}
+
+fn session_token(
+    state: &AppState,
+    account: Account,
+    csrf: Option<String>,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(state.token_lifetime)
         .unwrap()
         .timestamp();
 
@@ -25,86 +91,319 @@ pub async fn register(
         exp: expiration,
         email: account.email,
         account_name: account.account_name,
+        csrf,
+    };
+
+    state.keys.encode(&claims)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    tag = "auth",
+    request_body = LoginParams,
+    responses(
+        (status = 200, body = super::openapi::LoginResult),
+        (status = 401, description = "Wrong email or password", body = super::openapi::ErrorBody),
+        (status = 429, description = "Too many failed logins", body = super::openapi::ErrorBody,
+            headers(("Retry-After" = i64, description = "Seconds until the next attempt"))),
+    )
+)]
+pub async fn login(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    headers: axum::http::HeaderMap,
+    axum::Json(params): axum::Json<LoginParams>,
+) -> axum::response::Response {
+    let email = params.email.trim().to_lowercase();
+    let ip_address = super::client_ip(&headers, peer, &state.trusted_proxies).to_string();
+
+    let attempt = match start_login_attempt(&state, Some(&email), &ip_address).await {
+        Ok(attempt) => attempt,
+        Err(response) => return response,
+    };
+
+    let account = match state.queries.login(params).await {
+        // The login only counts as successful once the second factor is in
+        Ok(account) if account.totp_enabled => {
+            cancel_login_attempt(&state, attempt).await;
+            return mfa_challenge(&state, account);
+        }
+        Ok(account) => {
+            settle_login_attempt(&state, attempt, "success").await;
+            account
+        }
+        Err(crate::db::repositories::Error::LoginError(err)) => {
+            settle_login_attempt(&state, attempt, "failure").await;
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            cancel_login_attempt(&state, attempt).await;
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
     };
 
-    let token_res = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(state.jwt_secret.as_ref()),
-    );
+    session_response(&state, account)
+}
 
-    let token = match token_res {
+/// Hands out a session the way `auth.session_mode` asks for: the token in
+/// the body, as a cookie with a CSRF token, or both.
+pub fn session_response(state: &AppState, account: Account) -> axum::response::Response {
+    let csrf_token = state
+        .session_mode
+        .allows_cookie()
+        .then(super::sessions::generate_csrf_token);
+
+    let token = match session_token(state, account, csrf_token.clone()) {
         Ok(token) => token,
         Err(err) => {
             return (
                 axum::http::StatusCode::UNAUTHORIZED,
                 axum::response::Json(serde_json::json!({"error": err.to_string()})),
-            );
+            )
+                .into_response();
         }
     };
 
-    (
-        axum::http::StatusCode::OK,
-        axum::response::Json(serde_json::json!({"token": token})),
-    )
+    let Some(csrf_token) = csrf_token else {
+        return (
+            axum::http::StatusCode::OK,
+            axum::response::Json(serde_json::json!({"token": token})),
+        )
+            .into_response();
+    };
+
+    let body = if state.session_mode.allows_bearer() {
+        serde_json::json!({"token": token, "csrf_token": csrf_token})
+    } else {
+        serde_json::json!({"csrf_token": csrf_token})
+    };
+    let mut response = (axum::http::StatusCode::OK, axum::response::Json(body)).into_response();
+    super::sessions::set_cookies(state, &mut response, &token, &csrf_token);
+
+    response
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct Claims {
-    pub sub: uuid::Uuid,
-    pub exp: i64,
-    pub email: String,
-    pub account_name: String,
+pub fn mfa_challenge(state: &AppState, account: Account) -> axum::response::Response {
+    let claims = MfaClaims {
+        sub: account.id,
+        exp: (chrono::Utc::now() + chrono::Duration::seconds(MFA_CHALLENGE_SECS)).timestamp(),
+        aud: MFA_AUDIENCE.to_string(),
+        email: account.email,
+    };
+
+    match state.keys.encode(&claims) {
+        Ok(token) => (
+            axum::http::StatusCode::OK,
+            axum::response::Json(serde_json::json!({"mfa_required": true, "mfa_token": token})),
+        )
+            .into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err.to_string()})),
+        )
+            .into_response(),
+    }
 }
 
-pub async fn login(
+/// Second step of a login for accounts with 2FA: trades the challenge token
+/// from `login` and a code for a session token. Wrong codes count as failed
+/// logins for the account and IP address.
+#[utoipa::path(
+    post,
+    path = "/api/login/mfa",
+    tag = "auth",
+    request_body = MfaParams,
+    responses(
+        (status = 200, body = super::openapi::Session),
+        (status = 401, description = "Wrong code or expired challenge", body = super::openapi::ErrorBody),
+        (status = 429, description = "Too many failed logins", body = super::openapi::ErrorBody,
+            headers(("Retry-After" = i64, description = "Seconds until the next attempt"))),
+    )
+)]
+pub async fn login_mfa(
     axum::extract::State(state): axum::extract::State<AppState>,
-    axum::Json(params): axum::Json<LoginParams>,
-) -> impl axum::response::IntoResponse {
-    let account = match state.queries.login(params).await {
-        Ok(account) => account,
-        Err(err) => {
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    headers: axum::http::HeaderMap,
+    axum::Json(params): axum::Json<MfaParams>,
+) -> axum::response::Response {
+    let claims = match state
+        .keys
+        .decode::<MfaClaims>(&params.mfa_token, Some(MFA_AUDIENCE))
+    {
+        Ok(claims) => claims,
+        Err(_) => {
             return (
                 axum::http::StatusCode::UNAUTHORIZED,
-                axum::response::Json(serde_json::json!({"error": err})),
-            );
+                axum::response::Json(
+                    serde_json::json!({"error": "invalid or expired challenge, log in again"}),
+                ),
+            )
+                .into_response();
         }
     };
 
-    let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
-        .unwrap()
-        .timestamp();
+    let email = claims.email.trim().to_lowercase();
+    let ip_address = super::client_ip(&headers, peer, &state.trusted_proxies).to_string();
 
-    let claims = Claims {
-        sub: account.id,
-        exp: expiration,
-        email: account.email,
-        account_name: account.account_name,
+    let attempt = match start_login_attempt(&state, Some(&email), &ip_address).await {
+        Ok(attempt) => attempt,
+        Err(response) => return response,
     };
 
-    let token_res = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(state.jwt_secret.as_ref()),
-    );
+    let verified = match state.queries.totp_state(claims.sub).await {
+        Ok(totp) => super::totp::check_code(&state, claims.sub, &totp, &params.code).await,
+        Err(err) => Err(err),
+    };
 
-    let token = match token_res {
-        Ok(token) => token,
-        Err(err) => {
+    match verified {
+        Ok(true) => settle_login_attempt(&state, attempt, "success").await,
+        Ok(false) => {
+            settle_login_attempt(&state, attempt, "failure").await;
             return (
                 axum::http::StatusCode::UNAUTHORIZED,
-                axum::response::Json(serde_json::json!({"error": err.to_string()})),
-            );
+                axum::response::Json(serde_json::json!({"error": "invalid code"})),
+            )
+                .into_response();
         }
-    };
+        Err(err) => {
+            cancel_login_attempt(&state, attempt).await;
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    }
 
-    (
-        axum::http::StatusCode::OK,
-        axum::response::Json(serde_json::json!({"token": token})),
-    )
+    match state.queries.me(claims.sub).await {
+        Ok(account) => session_response(&state, account),
+        Err(err) => (
+            axum::http::StatusCode::UNAUTHORIZED,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
+
+/// Reserves a login attempt for the email and IP address, or answers 429
+/// when either has failed too often recently. The attempt counts as a
+/// failure until it's settled. Without an email only the IP address counts.
+pub async fn start_login_attempt(
+    state: &AppState,
+    email: Option<&str>,
+    ip_address: &str,
+) -> Result<uuid::Uuid, axum::response::Response> {
+    let reservation = state
+        .queries
+        .reserve_login_attempt(email, ip_address, LOGIN_WINDOW_SECS, |account, ip| {
+            retry_after(account, ACCOUNT_FREE_ATTEMPTS).max(retry_after(ip, IP_FREE_ATTEMPTS))
+        })
+        .await;
+
+    match reservation {
+        Ok(LoginReservation::Reserved(attempt)) => Ok(attempt),
+        Ok(LoginReservation::Throttled(retry_after)) => {
+            metrics::counter!("login_attempts_total", "result" => "throttled").increment(1);
+            Err((
+                axum::http::StatusCode::TOO_MANY_REQUESTS,
+                [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+                axum::response::Json(
+                    serde_json::json!({"error": "too many login attempts, try again later"}),
+                ),
+            )
+                .into_response())
+        }
+        Err(err) => Err((
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response()),
+    }
+}
+
+/// Records a reserved attempt as a `success` or `failure`. Best effort, a
+/// failed write doesn't fail the login.
+pub async fn settle_login_attempt(state: &AppState, attempt: uuid::Uuid, result: &'static str) {
+    metrics::counter!("login_attempts_total", "result" => result).increment(1);
+
+    if let Err(err) = state.queries.settle_login_attempt(attempt, result).await {
+        tracing::warn!("can't record login attempt: {}", err);
+    }
+}
+
+/// Forgets a reserved attempt that neither succeeded nor failed.
+pub async fn cancel_login_attempt(state: &AppState, attempt: uuid::Uuid) {
+    if let Err(err) = state.queries.cancel_login_attempt(attempt).await {
+        tracing::warn!("can't cancel login attempt: {}", err);
+    }
+}
+
+/// Periodically removes attempts that have left the throttling window, and
+/// failures once they're older than `audit_retention`.
+pub fn spawn_login_attempts_purger(
+    queries: crate::db::repositories::Queries,
+    audit_retention: chrono::Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
+        loop {
+            interval.tick().await;
+            if let Err(err) = queries
+                .purge_login_attempts(LOGIN_WINDOW_SECS, audit_retention.num_seconds())
+                .await
+            {
+                tracing::warn!("can't purge login attempts: {}", err);
+            }
+        }
+    });
+}
+
+/// After `free_attempts` failures each further attempt waits twice as long
+/// as the previous one, up to a lockout that lasts until failures leave the
+/// window.
+fn retry_after(failures: &LoginFailures, free_attempts: i64) -> Option<i64> {
+    let excess = failures.failures - free_attempts;
+    if excess < 0 {
+        return None;
+    }
+
+    let delay = (LOGIN_BACKOFF_BASE_SECS << excess.min(32)).min(LOGIN_LOCKOUT_SECS);
+    let allowed_at = failures.last_failure? + chrono::Duration::seconds(delay);
+    let remaining = (allowed_at - chrono::Utc::now()).num_milliseconds();
+
+    (remaining > 0).then(|| (remaining + 999) / 1000)
 }
 
+/// Audit entries are best effort, a failed write doesn't fail the login.
+pub async fn record_login_attempt(state: &AppState, email: &str, ip_address: &str, result: &str) {
+    if let Err(err) = state
+        .queries
+        .record_login_attempt(email, ip_address, result)
+        .await
+    {
+        tracing::warn!("can't record login attempt: {}", err);
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me",
+    tag = "account",
+    security(("bearer" = ["account:read"]), ("cookie" = [])),
+    responses(
+        (status = 200, body = super::openapi::Data<Account>),
+        (status = 401, body = super::openapi::ErrorBody),
+    )
+)]
 pub async fn me(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Extension(claims): axum::extract::Extension<crate::routes::accounts::Claims>,
@@ -124,3 +423,213 @@ pub async fn me(
         axum::response::Json(serde_json::json!({"data": account})),
     )
 }
+
+/// The public keys session tokens are signed with, for other services to
+/// verify them. Kept cacheable only for as long as instances take to pick
+/// up a new key.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "auth",
+    responses((status = 200, description = "A JWK set", body = serde_json::Value))
+)]
+pub async fn jwks(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl axum::response::IntoResponse {
+    (
+        axum::http::StatusCode::OK,
+        [(axum::http::header::CACHE_CONTROL, "public, max-age=60")],
+        axum::response::Json(state.keys.jwks()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app, request, send};
+    use axum::http::{Method, StatusCode};
+
+    const PASSWORD: &str = "correct horse battery";
+
+    async fn register(app: &axum::Router, email: &str) {
+        let (status, body) = send(
+            app,
+            request(
+                Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": email.split('@').next().unwrap(),
+                    "email": email,
+                    "password": PASSWORD,
+                })),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    /// Logs in from `ip`, returning the status, body and `Retry-After`.
+    async fn login(
+        app: &axum::Router,
+        email: &str,
+        password: &str,
+        ip: [u8; 4],
+    ) -> (StatusCode, serde_json::Value, Option<i64>) {
+        let mut request = request(
+            Method::POST,
+            "/api/login",
+            None,
+            Some(serde_json::json!({"email": email, "password": password})),
+        );
+        request
+            .extensions_mut()
+            .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                ip, 443,
+            ))));
+
+        let response = tower::ServiceExt::oneshot(app.clone(), request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(axum::http::header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().parse().unwrap());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap(), retry_after)
+    }
+
+    async fn wait_secs(secs: i64) {
+        tokio::time::sleep(std::time::Duration::from_millis(secs as u64 * 1000 + 100)).await;
+    }
+
+    #[test]
+    fn retry_after_doubles_past_the_free_attempts() {
+        let failures = |failures| LoginFailures {
+            failures,
+            last_failure: Some(chrono::Utc::now()),
+        };
+
+        assert_eq!(retry_after(&failures(2), 3), None);
+        assert_eq!(retry_after(&failures(3), 3), Some(1));
+        assert_eq!(retry_after(&failures(4), 3), Some(2));
+        assert_eq!(retry_after(&failures(6), 3), Some(8));
+        assert_eq!(retry_after(&failures(100), 3), Some(LOGIN_LOCKOUT_SECS));
+        assert_eq!(
+            retry_after(
+                &LoginFailures {
+                    failures: 4,
+                    last_failure: Some(chrono::Utc::now() - chrono::Duration::seconds(3)),
+                },
+                3
+            ),
+            None
+        );
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn unknown_emails_and_wrong_passwords_look_the_same(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        register(&app, "known@example.com").await;
+
+        let wrong_password = login(&app, "known@example.com", "wrong", [203, 0, 113, 1]).await;
+        let unknown_email = login(&app, "unknown@example.com", "wrong", [203, 0, 113, 1]).await;
+
+        assert_eq!(wrong_password.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong_password, unknown_email);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn accounts_back_off_after_their_free_attempts(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        register(&app, "locked@example.com").await;
+
+        // Each from its own address, so only the account's count matters
+        for attempt in 0..ACCOUNT_FREE_ATTEMPTS {
+            let (status, _, _) = login(
+                &app,
+                "locked@example.com",
+                "wrong",
+                [198, 51, 100, attempt as u8],
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        // Even the right password waits, from any address
+        let (status, body, retry_after) =
+            login(&app, "locked@example.com", PASSWORD, [198, 51, 100, 99]).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "too many login attempts, try again later"})
+        );
+        assert_eq!(retry_after, Some(LOGIN_BACKOFF_BASE_SECS));
+
+        // Other accounts aren't affected
+        register(&app, "other@example.com").await;
+        let (status, _, _) = login(&app, "other@example.com", PASSWORD, [198, 51, 100, 99]).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Each further failure doubles the wait
+        wait_secs(LOGIN_BACKOFF_BASE_SECS).await;
+        let (status, _, _) = login(&app, "locked@example.com", "wrong", [198, 51, 100, 99]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _, retry_after) =
+            login(&app, "locked@example.com", PASSWORD, [198, 51, 100, 99]).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after, Some(LOGIN_BACKOFF_BASE_SECS * 2));
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn a_success_resets_the_backoff(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        register(&app, "forgetful@example.com").await;
+
+        for _ in 0..ACCOUNT_FREE_ATTEMPTS {
+            login(&app, "forgetful@example.com", "wrong", [203, 0, 113, 1]).await;
+        }
+        wait_secs(LOGIN_BACKOFF_BASE_SECS).await;
+        let (status, _, _) = login(&app, "forgetful@example.com", PASSWORD, [203, 0, 113, 1]).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // The free attempts are back
+        for _ in 0..ACCOUNT_FREE_ATTEMPTS {
+            let (status, _, _) =
+                login(&app, "forgetful@example.com", "wrong", [203, 0, 113, 1]).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let (status, _, _) = login(&app, "forgetful@example.com", "wrong", [203, 0, 113, 1]).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn addresses_are_throttled_after_their_free_attempts(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        register(&app, "victim@example.com").await;
+
+        // A different account each time, so only the address's count matters
+        for attempt in 0..IP_FREE_ATTEMPTS {
+            let email = format!("guess{}@example.com", attempt);
+            let (status, _, _) = login(&app, &email, "wrong", [203, 0, 113, 1]).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        let (status, _, retry_after) =
+            login(&app, "victim@example.com", PASSWORD, [203, 0, 113, 1]).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after, Some(LOGIN_BACKOFF_BASE_SECS));
+
+        // The account itself can still log in from elsewhere
+        let (status, _, _) = login(&app, "victim@example.com", PASSWORD, [203, 0, 113, 2]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
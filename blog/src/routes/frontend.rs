@@ -0,0 +1,646 @@
+use super::AppState;
+
+// Vite names bundled files `assets/<name>-<hash>.<ext>` with an 8 character
+// base64url hash, a new build never reuses one of those names
+const FINGERPRINT_DIR: &str = "assets/";
+const FINGERPRINT_LEN: usize = 8;
+const FINGERPRINTED_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+const ASSET_CACHE_CONTROL: &str = "public, max-age=3600";
+
+// Content codings with precompressed variants, most preferred first, and the
+// suffix `build.rs` gives their files
+const PRECOMPRESSED: &[(&str, &str)] = &[("br", "br"), ("gzip", "gz")];
+
+#[derive(rust_embed::RustEmbed)]
+#[folder = "src/frontend/dist"]
+pub struct ClientAssets;
+
+/// Brotli and gzip versions of `ClientAssets`, written by `build.rs`.
+#[derive(rust_embed::RustEmbed)]
+#[folder = "$OUT_DIR/frontend"]
+struct CompressedAssets;
+
+/// The fallback that serves the SPA, from where `[frontend]` says.
+pub fn service(
+    config: &crate::config::FrontendConfig,
+    state: AppState,
+) -> axum::routing::MethodRouter {
+    match config.source {
+        crate::config::FrontendSource::Embedded => {
+            axum::routing::get(serve_embedded).with_state(state)
+        }
+        crate::config::FrontendSource::Disk => {
+            axum::routing::get(serve_disk).with_state(DiskFrontend {
+                state,
+                dir: config.dir.clone(),
+            })
+        }
+        crate::config::FrontendSource::Vite => {
+            axum::routing::get(proxy_vite).with_state(ViteProxy::new(&config.vite_url))
+        }
+    }
+}
+
+async fn serve_embedded(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    request: axum::extract::Request,
+) -> axum::response::Response {
+    let path = asset_path(&request);
+
+    // The index is rendered for every request, it carries the CSP nonce
+    if path != "index.html"
+        && let Some(asset) = ClientAssets::get(path)
+    {
+        return asset_response(path, asset, request.headers());
+    }
+
+    // Fallback to index.html for SPA routing
+    match ClientAssets::get("index.html") {
+        Some(index) => {
+            index_response(
+                &state,
+                path,
+                nonce(&request),
+                String::from_utf8_lossy(&index.data),
+            )
+            .await
+        }
+        None => not_found(),
+    }
+}
+
+#[derive(Clone)]
+struct DiskFrontend {
+    state: AppState,
+    dir: std::path::PathBuf,
+}
+
+/// Serves a frontend build from disk, so `vite build --watch` output shows
+/// up without rebuilding the server.
+async fn serve_disk(
+    axum::extract::State(frontend): axum::extract::State<DiskFrontend>,
+    request: axum::extract::Request,
+) -> axum::response::Response {
+    let path = asset_path(&request);
+
+    // Only plain relative paths, nothing that could leave the directory
+    let is_plain = std::path::Path::new(path)
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)));
+
+    if path != "index.html"
+        && is_plain
+        && let Ok(contents) = tokio::fs::read(frontend.dir.join(path)).await
+    {
+        let mime_type = mime_guess::from_path(path).first_or_octet_stream();
+
+        return axum::response::Response::builder()
+            .header(axum::http::header::CONTENT_TYPE, mime_type.as_ref())
+            .header(axum::http::header::CACHE_CONTROL, "no-cache")
+            .status(axum::http::StatusCode::OK)
+            .body(axum::body::Body::from(contents))
+            .unwrap();
+    }
+
+    match tokio::fs::read_to_string(frontend.dir.join("index.html")).await {
+        Ok(index) => index_response(&frontend.state, path, nonce(&request), index.into()).await,
+        Err(err) => {
+            tracing::warn!(
+                "can't read frontend index from {}: {}",
+                frontend.dir.display(),
+                err
+            );
+            not_found()
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ViteProxy {
+    client: hyper_util::client::legacy::Client<
+        hyper_util::client::legacy::connect::HttpConnector,
+        axum::body::Body,
+    >,
+    url: String,
+}
+
+impl ViteProxy {
+    fn new(url: &str) -> Self {
+        ViteProxy {
+            client: hyper_util::client::legacy::Client::builder(
+                hyper_util::rt::TokioExecutor::new(),
+            )
+            .build_http(),
+            url: url.to_string(),
+        }
+    }
+}
+
+/// Passes requests to the Vite dev server. WebSocket upgrades are tunnelled
+/// too, which is how hot module replacement reaches the browser.
+async fn proxy_vite(
+    axum::extract::State(proxy): axum::extract::State<ViteProxy>,
+    mut request: axum::extract::Request,
+) -> axum::response::Response {
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    let uri: axum::http::Uri = match format!("{}{}", proxy.url, path_and_query).parse() {
+        Ok(uri) => uri,
+        Err(_) => return not_found(),
+    };
+
+    let nonce = nonce(&request);
+    let client_upgrade = request
+        .headers()
+        .contains_key(axum::http::header::UPGRADE)
+        .then(|| hyper::upgrade::on(&mut request));
+
+    let (mut parts, body) = request.into_parts();
+    if let Some(authority) = uri.authority()
+        && let Ok(host) = axum::http::HeaderValue::from_str(authority.as_str())
+    {
+        parts.headers.insert(axum::http::header::HOST, host);
+    }
+    parts.uri = uri;
+
+    let mut response = match proxy
+        .client
+        .request(axum::http::Request::from_parts(parts, body))
+        .await
+    {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::warn!("can't reach the vite dev server at {}: {}", proxy.url, err);
+            return axum::response::Response::builder()
+                .status(axum::http::StatusCode::BAD_GATEWAY)
+                .body(axum::body::Body::from("vite dev server is not running"))
+                .unwrap();
+        }
+    };
+
+    if response.status() == axum::http::StatusCode::SWITCHING_PROTOCOLS
+        && let Some(client_upgrade) = client_upgrade
+    {
+        let server_upgrade = hyper::upgrade::on(&mut response);
+        tokio::spawn(async move {
+            let (Ok(client), Ok(server)) = tokio::join!(client_upgrade, server_upgrade) else {
+                return;
+            };
+            let mut client = hyper_util::rt::TokioIo::new(client);
+            let mut server = hyper_util::rt::TokioIo::new(server);
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+        });
+    }
+
+    let is_html = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+
+    // Vite's index has inline scripts, they need the nonce to run
+    match nonce {
+        Some(nonce) if is_html => {
+            let (mut parts, body) = response.into_parts();
+            let html = match axum::body::to_bytes(axum::body::Body::new(body), usize::MAX).await {
+                Ok(html) => html,
+                Err(_) => return not_found(),
+            };
+            let html = super::security::add_script_nonce(&String::from_utf8_lossy(&html), &nonce.0);
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+
+            axum::response::Response::from_parts(parts, axum::body::Body::from(html))
+        }
+        _ => response.map(axum::body::Body::new),
+    }
+}
+
+fn asset_path(request: &axum::extract::Request) -> &str {
+    let path = request.uri().path().trim_start_matches('/');
+    if path.is_empty() { "index.html" } else { path }
+}
+
+async fn index_response(
+    state: &AppState,
+    path: &str,
+    nonce: Option<super::security::CspNonce>,
+    index: std::borrow::Cow<'_, str>,
+) -> axum::response::Response {
+    let (status, html) = super::seo::render_index(state, path, index).await;
+    let html = match nonce {
+        Some(nonce) => super::security::add_script_nonce(&html, &nonce.0),
+        None => html,
+    };
+
+    axum::response::Response::builder()
+        .header(axum::http::header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(axum::http::header::CACHE_CONTROL, "no-cache")
+        .status(status)
+        .body(axum::body::Body::from(html))
+        .unwrap()
+}
+
+fn nonce(request: &axum::extract::Request) -> Option<super::security::CspNonce> {
+    request.extensions().get().cloned()
+}
+
+fn not_found() -> axum::response::Response {
+    axum::response::Response::builder()
+        .status(axum::http::StatusCode::NOT_FOUND)
+        .body(axum::body::Body::from("404 Not Found"))
+        .unwrap()
+}
+
+/// Sends the best precompressed variant the client accepts, or a 304 when
+/// it already has it.
+fn asset_response(
+    path: &str,
+    asset: rust_embed::EmbeddedFile,
+    headers: &axum::http::HeaderMap,
+) -> axum::response::Response {
+    let mime_type = mime_guess::from_path(path).first_or_octet_stream();
+    let cache_control = if is_fingerprinted(path) {
+        FINGERPRINTED_CACHE_CONTROL
+    } else {
+        ASSET_CACHE_CONTROL
+    };
+
+    let (body, encoding) = PRECOMPRESSED
+        .iter()
+        .filter(|(coding, _)| accepts_encoding(headers, coding))
+        .find_map(|(coding, suffix)| {
+            CompressedAssets::get(&format!("{}.{}", path, suffix))
+                .map(|file| (file.data, Some(*coding)))
+        })
+        .unwrap_or((asset.data, None));
+
+    // Each encoding is a different representation and needs its own tag
+    let hash = hex::encode(asset.metadata.sha256_hash());
+    let etag = match encoding {
+        Some(coding) => format!("\"{}-{}\"", hash, coding),
+        None => format!("\"{}\"", hash),
+    };
+
+    let builder = axum::response::Response::builder()
+        .header(axum::http::header::ETAG, &etag)
+        .header(axum::http::header::CACHE_CONTROL, cache_control)
+        .header(axum::http::header::VARY, "accept-encoding");
+
+    if matches_etag(headers, &etag) {
+        return builder
+            .status(axum::http::StatusCode::NOT_MODIFIED)
+            .body(axum::body::Body::empty())
+            .unwrap();
+    }
+
+    let builder = match encoding {
+        Some(coding) => builder.header(axum::http::header::CONTENT_ENCODING, coding),
+        None => builder,
+    };
+
+    builder
+        .header(axum::http::header::CONTENT_TYPE, mime_type.as_ref())
+        .status(axum::http::StatusCode::OK)
+        .body(axum::body::Body::from(body))
+        .unwrap()
+}
+
+/// Whether `Accept-Encoding` allows `coding`, directly or through `*`.
+fn accepts_encoding(headers: &axum::http::HeaderMap, coding: &str) -> bool {
+    let mut wildcard = false;
+
+    for item in headers
+        .get_all(axum::http::header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|quality| quality.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if name.eq_ignore_ascii_case(coding) {
+            return quality > 0.0;
+        }
+        if name == "*" {
+            wildcard = quality > 0.0;
+        }
+    }
+
+    wildcard
+}
+
+/// `If-None-Match` uses the weak comparison, so `W/` tags match too.
+fn matches_etag(headers: &axum::http::HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(axum::http::header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn is_fingerprinted(path: &str) -> bool {
+    if !path.starts_with(FINGERPRINT_DIR) {
+        return false;
+    }
+
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let stem = file.split('.').next().unwrap_or(file);
+    let Some(separator) = stem.len().checked_sub(FINGERPRINT_LEN + 1) else {
+        return false;
+    };
+
+    let (name, hash) = stem.as_bytes().split_at(separator);
+    !name.is_empty()
+        && hash[0] == b'-'
+        && hash[1..]
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b'-' || *byte == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: axum::http::HeaderName, values: &[&str]) -> axum::http::HeaderMap {
+        let mut headers = axum::http::HeaderMap::new();
+        for value in values {
+            headers.append(&name, axum::http::HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn accepts_encoding_follows_quality_values() {
+        let cases: &[(&[&str], &str, bool)] = &[
+            (&[], "gzip", false),
+            (&["gzip"], "gzip", true),
+            (&["gzip"], "br", false),
+            (&["gzip, deflate, br"], "br", true),
+            (&["GZIP"], "gzip", true),
+            (&["gzip;q=0.5"], "gzip", true),
+            (&["br;q=0"], "br", false),
+            (&["br; q=0.0, gzip"], "br", false),
+            (&["br; q=0.0, gzip"], "gzip", true),
+            (&["identity"], "gzip", false),
+            (&["*"], "br", true),
+            (&["*;q=0"], "br", false),
+            (&["br;q=0, *"], "br", false),
+            (&["*, br;q=0"], "br", false),
+            (&["gzip, *;q=0"], "gzip", true),
+            (&["gzip, *;q=0"], "br", false),
+            (&["gzip", "br"], "br", true),
+        ];
+
+        for (values, coding, expected) in cases {
+            let headers = headers(axum::http::header::ACCEPT_ENCODING, values);
+            assert_eq!(
+                accepts_encoding(&headers, coding),
+                *expected,
+                "{:?} accepting {}",
+                values,
+                coding
+            );
+        }
+    }
+
+    #[test]
+    fn matches_etag_uses_the_weak_comparison() {
+        let etag = "\"abc\"";
+        let cases: &[(&[&str], bool)] = &[
+            (&[], false),
+            (&["\"abc\""], true),
+            (&["W/\"abc\""], true),
+            (&["\"xyz\", \"abc\""], true),
+            (&["\"xyz\"", "W/\"abc\""], true),
+            (&["\"xyz\""], false),
+            (&["\"abc-br\""], false),
+            (&["abc"], false),
+            (&["*"], true),
+        ];
+
+        for (values, expected) in cases {
+            let headers = headers(axum::http::header::IF_NONE_MATCH, values);
+            assert_eq!(matches_etag(&headers, etag), *expected, "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn is_fingerprinted_only_takes_hashed_asset_names() {
+        let cases = [
+            ("assets/index-BdP3kT0a.js", true),
+            ("assets/index-Bd_3k-0a.css", true),
+            ("assets/post-editor-BdP3kT0a.js", true),
+            ("assets/fonts/inter-BdP3kT0a.woff2", true),
+            ("index.html", false),
+            ("favicon-BdP3kT0a.ico", false),
+            ("assets/logo.svg", false),
+            ("assets/index.js", false),
+            ("assets/-BdP3kT0a.js", false),
+            ("assets/index_BdP3kT0a.js", false),
+            ("assets/index-BdP3kT0.js", false),
+            ("assets/index-BdP3kT!a.js", false),
+            ("assets/robots-long-name.txt", false),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(is_fingerprinted(path), expected, "{}", path);
+        }
+    }
+
+    async fn get(app: &axum::Router, uri: &str) -> (axum::http::StatusCode, String) {
+        let request = axum::http::Request::builder()
+            .uri(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(app.clone(), request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn disk_serves_the_build_and_nothing_outside_it() {
+        let root = std::env::temp_dir().join(format!("landing-test-{}", uuid::Uuid::new_v4()));
+        let dir = root.join("dist");
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("index.html"), "<script src=\"/app.js\"></script>").unwrap();
+        std::fs::write(dir.join("assets/app.js"), "console.log(1)").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+
+        let mut config = crate::config::Config::default();
+        config.frontend.source = crate::config::FrontendSource::Disk;
+        config.frontend.dir = dir;
+        let app = crate::routes::tests::app(crate::routes::tests::lazy_pool(), &config);
+
+        let (status, body) = get(&app, "/assets/app.js").await;
+        assert_eq!(
+            (status, body.as_str()),
+            (axum::http::StatusCode::OK, "console.log(1)")
+        );
+
+        for uri in [
+            "/",
+            "/posts",
+            "/../secret.txt",
+            "/assets/../../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/assets/%2e%2e/%2e%2e/secret.txt",
+        ] {
+            let (status, body) = get(&app, uri).await;
+            assert_eq!(status, axum::http::StatusCode::OK, "{}", uri);
+            assert!(body.starts_with("<script nonce="), "{} {}", uri, body);
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    /// A stand-in for the Vite dev server. Pages echo the path they were
+    /// asked for, `/` is an index with an inline script, and upgrades are
+    /// accepted and echo whatever is sent.
+    async fn vite_stub() -> std::net::SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0; 1];
+                        if stream.read(&mut byte).await.unwrap() == 0 {
+                            return;
+                        }
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8_lossy(&head).to_lowercase();
+                    let path = head.split(' ').nth(1).unwrap_or("/").to_string();
+
+                    if head.contains("upgrade: websocket") {
+                        stream
+                            .write_all(
+                                b"HTTP/1.1 101 Switching Protocols\r\n\
+                                  upgrade: websocket\r\nconnection: upgrade\r\n\r\n",
+                            )
+                            .await
+                            .unwrap();
+                        let mut buffer = [0; 64];
+                        while let Ok(read @ 1..) = stream.read(&mut buffer).await {
+                            stream.write_all(&buffer[..read]).await.unwrap();
+                        }
+                        return;
+                    }
+
+                    let (content_type, body) = if path == "/" {
+                        (
+                            "text/html",
+                            "<script type=\"module\">import \"/@vite/client\"</script>".to_string(),
+                        )
+                    } else {
+                        ("text/javascript", format!("served {}", path))
+                    };
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\n\
+                         connection: close\r\n\r\n{}",
+                        content_type,
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        addr
+    }
+
+    fn vite_config(addr: std::net::SocketAddr) -> crate::config::Config {
+        let mut config = crate::config::Config::default();
+        config.frontend.source = crate::config::FrontendSource::Vite;
+        config.frontend.vite_url = format!("http://{}", addr);
+        config
+    }
+
+    #[tokio::test]
+    async fn vite_pages_are_proxied_with_the_nonce() {
+        let app = crate::routes::tests::app(
+            crate::routes::tests::lazy_pool(),
+            &vite_config(vite_stub().await),
+        );
+
+        let (status, body) = get(&app, "/src/main.tsx?t=1").await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body, "served /src/main.tsx?t=1");
+
+        let (status, body) = get(&app, "/").await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert!(body.starts_with("<script nonce=\""), "{}", body);
+
+        // Nothing listens on a port that was just freed
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let app = crate::routes::tests::app(crate::routes::tests::lazy_pool(), &vite_config(addr));
+
+        let (status, _) = get(&app, "/").await;
+        assert_eq!(status, axum::http::StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn vite_websocket_upgrades_are_tunnelled() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let app = crate::routes::tests::app(
+            crate::routes::tests::lazy_pool(),
+            &vite_config(vite_stub().await),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /?token=hmr HTTP/1.1\r\nhost: localhost\r\n\
+                  upgrade: websocket\r\nconnection: upgrade\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            assert_eq!(stream.read(&mut byte).await.unwrap(), 1);
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8_lossy(&head);
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
+}
@@ -0,0 +1,111 @@
+use crate::db::repositories::Queries;
+
+// Probes must answer quickly even when the database hangs
+const CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Flipped once shutdown starts so `/readyz` fails and traffic is drained
+/// away from this instance before it stops accepting connections.
+#[derive(Clone, Default)]
+pub struct Readiness(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl Readiness {
+    pub fn mark_shutting_down(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone)]
+struct HealthState {
+    queries: Queries,
+    readiness: Readiness,
+}
+
+pub fn router(pool: sqlx::PgPool, readiness: Readiness) -> axum::Router {
+    axum::Router::new()
+        .route("/healthz", axum::routing::get(healthz))
+        .route("/readyz", axum::routing::get(readyz))
+        .with_state(HealthState {
+            queries: Queries::new(pool),
+            readiness,
+        })
+}
+
+async fn healthz() -> impl axum::response::IntoResponse {
+    (
+        axum::http::StatusCode::OK,
+        axum::response::Json(serde_json::json!({ "status": "ok" })),
+    )
+}
+
+async fn readyz(
+    axum::extract::State(state): axum::extract::State<HealthState>,
+) -> impl axum::response::IntoResponse {
+    let shutdown = if state.readiness.is_shutting_down() {
+        serde_json::json!({ "status": "shutting_down" })
+    } else {
+        serde_json::json!({ "status": "ok" })
+    };
+
+    let start = std::time::Instant::now();
+    let database = match tokio::time::timeout(CHECK_TIMEOUT, state.queries.ping()).await {
+        Ok(Ok(())) => serde_json::json!({
+            "status": "ok",
+            "latency_ms": start.elapsed().as_secs_f64() * 1000.0,
+        }),
+        Ok(Err(err)) => {
+            tracing::warn!("readiness database check failed: {}", err);
+            serde_json::json!({ "status": "error" })
+        }
+        Err(_) => {
+            tracing::warn!("readiness database check timed out");
+            serde_json::json!({ "status": "error" })
+        }
+    };
+
+    let migrations =
+        match tokio::time::timeout(CHECK_TIMEOUT, state.queries.applied_migrations()).await {
+            Ok(Ok(applied)) => {
+                let pending = crate::db::pending_migrations(&applied);
+
+                if pending.is_empty() {
+                    serde_json::json!({ "status": "ok" })
+                } else {
+                    serde_json::json!({ "status": "pending", "pending": pending })
+                }
+            }
+            Ok(Err(err)) => {
+                tracing::warn!("readiness migrations check failed: {}", err);
+                serde_json::json!({ "status": "error" })
+            }
+            Err(_) => {
+                tracing::warn!("readiness migrations check timed out");
+                serde_json::json!({ "status": "error" })
+            }
+        };
+
+    let ready = [&shutdown, &database, &migrations]
+        .iter()
+        .all(|check| check["status"] == "ok");
+
+    let status = if ready {
+        axum::http::StatusCode::OK
+    } else {
+        axum::http::StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        axum::response::Json(serde_json::json!({
+            "status": if ready { "ok" } else { "unavailable" },
+            "checks": {
+                "shutdown": shutdown,
+                "database": database,
+                "migrations": migrations,
+            },
+        })),
+    )
+}
@@ -0,0 +1,316 @@
+use axum::response::IntoResponse;
+
+/// Ends requests that run longer than the configured timeout. A client
+/// still sending its body is the slow party and gets a 408, otherwise the
+/// server is and it's a 503.
+pub async fn request_timeout(
+    axum::extract::State(timeout): axum::extract::State<std::time::Duration>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let progress = std::sync::Arc::new(BodyProgress::default());
+    let request = request.map(|body| {
+        axum::body::Body::new(TrackedBody {
+            inner: body,
+            progress: progress.clone(),
+        })
+    });
+
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) if progress.is_receiving() => (
+            axum::http::StatusCode::REQUEST_TIMEOUT,
+            [(axum::http::header::CONNECTION, "close")],
+            axum::response::Json(serde_json::json!({ "error": "request body took too long" })),
+        )
+            .into_response(),
+        Err(_) => (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            axum::response::Json(serde_json::json!({ "error": "request took too long" })),
+        )
+            .into_response(),
+    }
+}
+
+/// Extractors reject bodies over the route's `DefaultBodyLimit` with a plain
+/// text 413, this turns it into the usual JSON error.
+pub async fn payload_too_large(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let response = next.run(request).await;
+
+    let is_json = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    if response.status() != axum::http::StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+
+    (
+        axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+        axum::response::Json(serde_json::json!({ "error": "request body is too large" })),
+    )
+        .into_response()
+}
+
+/// Compresses responses the client accepts gzip, Brotli or zstd for.
+/// Embedded assets already carry their precompressed encoding and images
+/// don't shrink, both are left alone.
+pub fn compression_layer(
+    config: &crate::config::ServerConfig,
+) -> Option<
+    tower_http::compression::CompressionLayer<impl tower_http::compression::Predicate + use<>>,
+> {
+    use tower_http::compression::Predicate;
+    use tower_http::compression::predicate::{NotForContentType, SizeAbove};
+
+    if !config.compression {
+        return None;
+    }
+
+    let predicate = SizeAbove::new(config.compression_min_bytes)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE)
+        // Upgraded connections and bodiless responses have nothing to compress
+        .and(
+            |status: axum::http::StatusCode,
+             _: axum::http::Version,
+             _: &axum::http::HeaderMap,
+             _: &axum::http::Extensions| {
+                status != axum::http::StatusCode::SWITCHING_PROTOCOLS
+                    && status != axum::http::StatusCode::NO_CONTENT
+                    && status != axum::http::StatusCode::NOT_MODIFIED
+            },
+        );
+
+    Some(
+        tower_http::compression::CompressionLayer::new()
+            .gzip(true)
+            .br(true)
+            .zstd(true)
+            .compress_when(predicate),
+    )
+}
+
+#[derive(Default)]
+struct BodyProgress {
+    started: std::sync::atomic::AtomicBool,
+    finished: std::sync::atomic::AtomicBool,
+}
+
+impl BodyProgress {
+    fn is_receiving(&self) -> bool {
+        self.started.load(std::sync::atomic::Ordering::Relaxed)
+            && !self.finished.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// Records whether the handler has started and finished reading the body.
+struct TrackedBody {
+    inner: axum::body::Body,
+    progress: std::sync::Arc<BodyProgress>,
+}
+
+impl http_body::Body for TrackedBody {
+    type Data = axum::body::Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        self.progress
+            .started
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+
+        let poll = std::pin::Pin::new(&mut self.inner).poll_frame(cx);
+        if matches!(poll, std::task::Poll::Ready(None | Some(Err(_)))) || self.inner.is_end_stream()
+        {
+            self.progress
+                .finished
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_millis(50);
+
+    /// Sends the start of a JSON document and then nothing more.
+    #[derive(Default)]
+    pub(crate) struct StalledBody {
+        sent: bool,
+    }
+
+    impl http_body::Body for StalledBody {
+        type Data = axum::body::Bytes;
+        type Error = std::convert::Infallible;
+
+        fn poll_frame(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+            if self.sent {
+                return std::task::Poll::Pending;
+            }
+            self.sent = true;
+            std::task::Poll::Ready(Some(Ok(http_body::Frame::data(
+                axum::body::Bytes::from_static(b"{"),
+            ))))
+        }
+    }
+
+    async fn read_body(body: String) -> String {
+        body
+    }
+
+    async fn read_body_slowly(body: String) -> String {
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+        body
+    }
+
+    fn timed(handler: axum::routing::MethodRouter) -> axum::Router {
+        axum::Router::new()
+            .route("/", handler)
+            .layer(axum::middleware::from_fn_with_state(
+                TIMEOUT,
+                request_timeout,
+            ))
+    }
+
+    /// Layered the way `setup_make_app` does it: a small default limit with
+    /// one route allowing more.
+    fn limited() -> axum::Router {
+        axum::Router::new()
+            .route("/small", axum::routing::post(read_body))
+            .route(
+                "/large",
+                axum::routing::post(read_body).layer(axum::extract::DefaultBodyLimit::max(64)),
+            )
+            .route(
+                "/json",
+                axum::routing::post(|| async {
+                    (
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        axum::response::Json(serde_json::json!({ "error": "quota exceeded" })),
+                    )
+                }),
+            )
+            .layer(axum::extract::DefaultBodyLimit::max(8))
+            .layer(axum::middleware::from_fn(payload_too_large))
+    }
+
+    async fn send(
+        app: axum::Router,
+        uri: &str,
+        body: axum::body::Body,
+    ) -> (StatusCode, axum::http::HeaderMap, String) {
+        let request = axum::http::Request::builder()
+            .method(axum::http::Method::POST)
+            .uri(uri)
+            .body(body)
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(app, request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn a_body_still_arriving_gets_a_408() {
+        let body = axum::body::Body::new(StalledBody::default());
+        let (status, headers, body) = send(timed(axum::routing::post(read_body)), "/", body).await;
+
+        assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(headers[axum::http::header::CONNECTION], "close");
+        assert_eq!(body, r#"{"error":"request body took too long"}"#);
+    }
+
+    #[tokio::test]
+    async fn a_slow_handler_gets_a_503() {
+        for handler in [
+            axum::routing::post(|| async {
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await
+            }),
+            axum::routing::post(read_body_slowly),
+        ] {
+            let (status, headers, body) =
+                send(timed(handler), "/", axum::body::Body::from("{}")).await;
+
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert!(!headers.contains_key(axum::http::header::CONNECTION));
+            assert_eq!(body, r#"{"error":"request took too long"}"#);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_quick_request_is_left_alone() {
+        let (status, _, body) = send(
+            timed(axum::routing::post(read_body)),
+            "/",
+            axum::body::Body::from("{}"),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "{}");
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_get_a_json_413() {
+        let (status, headers, body) =
+            send(limited(), "/small", axum::body::Body::from("0123456789")).await;
+
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            headers[axum::http::header::CONTENT_TYPE],
+            "application/json"
+        );
+        assert_eq!(body, r#"{"error":"request body is too large"}"#);
+    }
+
+    #[tokio::test]
+    async fn json_413s_from_handlers_are_kept() {
+        let (status, _, body) = send(limited(), "/json", axum::body::Body::empty()).await;
+
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body, r#"{"error":"quota exceeded"}"#);
+    }
+
+    #[tokio::test]
+    async fn route_limits_override_the_default() {
+        let (status, _, body) =
+            send(limited(), "/large", axum::body::Body::from("0123456789")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "0123456789");
+
+        let (status, _, _) =
+            send(limited(), "/large", axum::body::Body::from("x".repeat(65))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (status, _, _) = send(limited(), "/small", axum::body::Body::from("01234567")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,315 @@
+use super::AppState;
+use axum::response::IntoResponse;
+
+const LINK_LIFETIME_SECS: i64 = 15 * 60;
+const TOKEN_BYTES: usize = 32;
+// Links that can be asked for one address within the login window
+const LINK_REQUESTS_PER_WINDOW: i64 = 3;
+// Holds the secret a browser-bound link is checked against
+const BROWSER_COOKIE: &str = "magic_link_browser";
+const BROWSER_COOKIE_PATH: &str = "/api/login/magic";
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = MagicLinkParams)]
+pub struct RequestParams {
+    pub email: String,
+    /// Only accept the link in the browser that asked for it
+    #[serde(default)]
+    pub bind_browser: bool,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = MagicLinkVerifyParams)]
+pub struct VerifyParams {
+    pub token: String,
+}
+
+/// Emails a sign-in link. The response is the same whether or not the
+/// account exists, and the lookup and email happen after responding so
+/// timing doesn't tell either. Requests are limited per address, which
+/// doesn't tell either since it applies to unknown addresses too.
+#[utoipa::path(
+    post,
+    path = "/api/login/magic",
+    tag = "auth",
+    request_body = RequestParams,
+    responses(
+        (status = 202, body = super::openapi::Message),
+        (status = 429, description = "Too many links asked for the address", body = super::openapi::ErrorBody,
+            headers(("Retry-After" = i64, description = "Seconds until the next request"))),
+    )
+)]
+pub async fn request_link(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    headers: axum::http::HeaderMap,
+    axum::Json(params): axum::Json<RequestParams>,
+) -> axum::response::Response {
+    let ip_address = super::client_ip(&headers, peer, &state.trusted_proxies).to_string();
+    match state
+        .queries
+        .record_link_request(
+            &params.email.trim().to_lowercase(),
+            &ip_address,
+            super::accounts::LOGIN_WINDOW_SECS,
+            LINK_REQUESTS_PER_WINDOW,
+        )
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(retry_after)) => {
+            return (
+                axum::http::StatusCode::TOO_MANY_REQUESTS,
+                [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+                axum::response::Json(
+                    serde_json::json!({"error": "too many sign-in links requested, try again later"}),
+                ),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    }
+
+    let token = random_token();
+    let browser_secret = params.bind_browser.then(random_token);
+    let browser_hash = browser_secret.as_deref().map(hash_secret);
+    let email = params.email.trim().to_string();
+
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            if let Err(err) = send_link(&state, &email, &token, browser_hash.as_deref()).await {
+                tracing::warn!("can't send sign-in link: {}", err);
+            }
+        }
+    });
+
+    let mut response = (
+        axum::http::StatusCode::ACCEPTED,
+        axum::response::Json(serde_json::json!({
+            "message": "if an account uses that email, a sign-in link is on its way"
+        })),
+    )
+        .into_response();
+
+    if let Some(secret) = browser_secret {
+        set_browser_cookie(&state, &mut response, &secret, LINK_LIFETIME_SECS);
+    }
+
+    response
+}
+
+async fn send_link(
+    state: &AppState,
+    email: &str,
+    token: &str,
+    browser_hash: Option<&str>,
+) -> Result<(), String> {
+    let Some(account) = state
+        .queries
+        .find_account_by_email(email)
+        .await
+        .map_err(|err| err.to_string())?
+    else {
+        return Ok(());
+    };
+    if !state
+        .queries
+        .is_account_active(account.id)
+        .await
+        .map_err(|err| err.to_string())?
+    {
+        return Ok(());
+    }
+
+    state
+        .queries
+        .create_magic_link(
+            account.id,
+            &hash_secret(token),
+            browser_hash,
+            LINK_LIFETIME_SECS,
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+    // The link opens the frontend, which posts the token to `verify_link`.
+    // A plain GET endpoint would be spent by mail scanners following links.
+    let link = format!("{}/login/magic?token={}", state.base_url, token);
+    state
+        .mailer
+        .send(crate::mail::Message {
+            to: account.email,
+            subject: "Your sign-in link".to_string(),
+            body: format!(
+                "Hi {},\n\n\
+                 Use this link to sign in:\n\n\
+                 {}\n\n\
+                 It works once and expires in {} minutes.\n\n\
+                 If you didn't ask to sign in, you can ignore this email.\n",
+                account.account_name,
+                link,
+                LINK_LIFETIME_SECS / 60
+            ),
+        })
+        .await
+}
+
+/// Exchanges a sign-in link for a session, like a password login does.
+#[utoipa::path(
+    post,
+    path = "/api/login/magic/verify",
+    tag = "auth",
+    request_body = VerifyParams,
+    responses(
+        (status = 200, body = super::openapi::LoginResult),
+        (status = 401, description = "The link is invalid, used or expired", body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn verify_link(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    headers: axum::http::HeaderMap,
+    axum::Json(params): axum::Json<VerifyParams>,
+) -> axum::response::Response {
+    let browser_hash = super::cookie(&headers, BROWSER_COOKIE).map(hash_secret);
+
+    let account_id = match state
+        .queries
+        .consume_magic_link(&hash_secret(&params.token), browser_hash.as_deref())
+        .await
+    {
+        Ok(Some(account_id)) => account_id,
+        Ok(None) => {
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                axum::response::Json(
+                    serde_json::json!({"error": "invalid or expired sign-in link"}),
+                ),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let account = match state.queries.me(account_id).await {
+        Ok(account) => account,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let mut response = if account.totp_enabled {
+        super::accounts::mfa_challenge(&state, account)
+    } else {
+        let ip_address = super::client_ip(&headers, peer, &state.trusted_proxies).to_string();
+        metrics::counter!("login_attempts_total", "result" => "success").increment(1);
+        super::accounts::record_login_attempt(&state, &account.email, &ip_address, "success").await;
+        super::accounts::session_response(&state, account)
+    };
+
+    if browser_hash.is_some() {
+        set_browser_cookie(&state, &mut response, "", 0);
+    }
+
+    response
+}
+
+fn random_token() -> String {
+    use rand::Rng;
+
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::rng().fill(&mut bytes);
+    data_encoding::BASE64URL_NOPAD.encode(&bytes)
+}
+
+/// Tokens are random, so a fast hash is enough to keep the stored values
+/// useless to someone reading the database.
+fn hash_secret(secret: &str) -> String {
+    use sha2::Digest;
+
+    hex::encode(sha2::Sha256::digest(secret.as_bytes()))
+}
+
+fn set_browser_cookie(
+    state: &AppState,
+    response: &mut axum::response::Response,
+    value: &str,
+    max_age_secs: i64,
+) {
+    let secure = if state.base_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    let cookie = format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        BROWSER_COOKIE, value, BROWSER_COOKIE_PATH, max_age_secs, secure
+    );
+
+    if let Ok(value) = axum::http::HeaderValue::from_str(&cookie) {
+        response
+            .headers_mut()
+            .append(axum::http::header::SET_COOKIE, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::tests::{app, request, send};
+    use axum::http::{Method, StatusCode};
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn sign_in_links_are_limited_per_address(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        let link_request = |email: &str| {
+            request(
+                Method::POST,
+                "/api/login/magic",
+                None,
+                Some(serde_json::json!({"email": email})),
+            )
+        };
+
+        for email in [
+            "nobody@example.com",
+            "Nobody@Example.com ",
+            "nobody@example.com",
+        ] {
+            let (status, _) = send(&app, link_request(email)).await;
+            assert_eq!(status, StatusCode::ACCEPTED);
+        }
+
+        let response = tower::ServiceExt::oneshot(app.clone(), link_request("NOBODY@example.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: i64 = response.headers()[axum::http::header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=crate::routes::accounts::LOGIN_WINDOW_SECS).contains(&retry_after));
+
+        // Other addresses aren't affected
+        let (status, _) = send(&app, link_request("somebody@example.com")).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+}
@@ -0,0 +1,593 @@
+use super::AppState;
+use crate::db::repositories::media::{CreateMediaParams, CreatedMedia};
+use crate::storage::Storage;
+use axum::response::IntoResponse;
+use sha2::Digest;
+
+#[utoipa::path(
+    post,
+    path = "/api/media",
+    tag = "media",
+    security(("bearer" = ["media:write"]), ("cookie" = [])),
+    request_body(content = super::openapi::UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The same image was uploaded before", body = super::openapi::UploadedMedia),
+        (status = 201, body = super::openapi::UploadedMedia),
+        (status = 403, description = "Uploads are disabled or the quota is used up", body = super::openapi::ErrorBody),
+        (status = 413, description = "Over `media.max_bytes`", body = super::openapi::ErrorBody),
+        (status = 415, description = "Not a PNG, JPEG, GIF or WebP image", body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn upload_media(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<crate::routes::accounts::Claims>,
+    mut multipart: axum::extract::Multipart,
+) -> axum::response::Response {
+    if !state.features.media_uploads {
+        return (
+            axum::http::StatusCode::FORBIDDEN,
+            axum::response::Json(serde_json::json!({ "error": "media uploads are disabled" })),
+        )
+            .into_response();
+    }
+
+    let (file_name, data) = loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    axum::response::Json(serde_json::json!({ "error": "missing file field" })),
+                )
+                    .into_response();
+            }
+            Err(err) => return multipart_error(err),
+        };
+
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let file_name = field.file_name().unwrap_or("upload").to_string();
+        match field.bytes().await {
+            Ok(data) => break (file_name, data),
+            Err(err) => return multipart_error(err),
+        }
+    };
+
+    let size_bytes = data.len() as i64;
+    if size_bytes > state.media_limits.max_bytes {
+        return (
+            axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            axum::response::Json(serde_json::json!({ "error": "file is too large" })),
+        )
+            .into_response();
+    }
+
+    // Trust the file's magic bytes rather than the client supplied content type
+    let (format, content_type) = match image::guess_format(&data) {
+        Ok(image::ImageFormat::Png) => (image::ImageFormat::Png, "image/png"),
+        Ok(image::ImageFormat::Jpeg) => (image::ImageFormat::Jpeg, "image/jpeg"),
+        Ok(image::ImageFormat::Gif) => (image::ImageFormat::Gif, "image/gif"),
+        Ok(image::ImageFormat::WebP) => (image::ImageFormat::WebP, "image/webp"),
+        _ => {
+            return (
+                axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                axum::response::Json(serde_json::json!({ "error": "unsupported file type" })),
+            )
+                .into_response();
+        }
+    };
+
+    let data =
+        match tokio::task::spawn_blocking(move || crate::images::strip_metadata(&data, format))
+            .await
+        {
+            Ok(Ok(data)) => data,
+            Ok(Err(err)) => {
+                return (
+                    axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                    axum::response::Json(serde_json::json!({ "error": err })),
+                )
+                    .into_response();
+            }
+            Err(err) => return internal_error(err.to_string()),
+        };
+    let size_bytes = data.len() as i64;
+
+    let content_hash = hex::encode(sha2::Sha256::digest(&data));
+
+    match state
+        .queries
+        .find_media_by_hash(claims.sub, &content_hash)
+        .await
+    {
+        Ok(Some(media)) => return media_response(axum::http::StatusCode::OK, &state, media),
+        Ok(None) => {}
+        Err(err) => return internal_error(err),
+    }
+
+    // Saves storing a file that can't fit, create_media enforces the quota
+    let usage = match state.queries.media_usage(claims.sub).await {
+        Ok(usage) => usage,
+        Err(err) => return internal_error(err),
+    };
+    if usage + size_bytes > state.media_limits.quota_bytes {
+        return quota_exceeded();
+    }
+
+    // Identical files uploaded by different accounts share one stored object
+    let stored = match state.storage.exists(&content_hash).await {
+        Ok(true) => Ok(()),
+        Ok(false) => state.storage.put(&content_hash, data).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = stored {
+        return internal_error(err);
+    }
+
+    let params = CreateMediaParams {
+        content_hash,
+        content_type: content_type.to_string(),
+        size_bytes,
+        file_name,
+    };
+
+    match state
+        .queries
+        .create_media(claims.sub, params, state.media_limits.quota_bytes)
+        .await
+    {
+        Ok(CreatedMedia::Created(media)) => {
+            media_response(axum::http::StatusCode::CREATED, &state, media)
+        }
+        Ok(CreatedMedia::Existing(media)) => {
+            media_response(axum::http::StatusCode::OK, &state, media)
+        }
+        Ok(CreatedMedia::QuotaExceeded) => quota_exceeded(),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VariantParams {
+    /// Resize to one of `images.widths`, the original is sent without it
+    pub width: Option<u32>,
+    /// Format of the resized image, `webp` (the default) or `jpeg`
+    pub format: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/media/{media_id}",
+    tag = "media",
+    params(("media_id" = uuid::Uuid, Path), VariantParams),
+    responses(
+        (status = 200, description = "The image", content_type = "image/*"),
+        (status = 400, description = "Unsupported width or format", body = super::openapi::ErrorBody),
+        (status = 404, body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn get_media(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(media_id): axum::extract::Path<uuid::Uuid>,
+    axum::extract::Query(params): axum::extract::Query<VariantParams>,
+) -> axum::response::Response {
+    let media = match state.queries.get_media(media_id).await {
+        Ok(Some(media)) => media,
+        Ok(None) => return not_found(),
+        Err(err) => return internal_error(err),
+    };
+
+    // Animated GIFs would lose their animation, so they are always served as is
+    if let Some(width) = params.width
+        && media.content_type != "image/gif"
+    {
+        return get_variant(&state, &media, width, params.format.as_deref()).await;
+    }
+
+    let data = match state.storage.get(&media.content_hash).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return not_found(),
+        Err(err) => return internal_error(err),
+    };
+
+    media_data_response(&media.content_type, data)
+}
+
+/// Serves a resized variant, rendering and caching it on first request.
+async fn get_variant(
+    state: &AppState,
+    media: &crate::db::repositories::media::Media,
+    width: u32,
+    format: Option<&str>,
+) -> axum::response::Response {
+    // Only configured widths are rendered so clients can't fill the cache
+    if !state.image_variants.widths.contains(&width) {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::response::Json(serde_json::json!({ "error": "unsupported width" })),
+        )
+            .into_response();
+    }
+
+    let format = match crate::images::VariantFormat::parse(format.unwrap_or("webp")) {
+        Some(format) => format,
+        None => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(serde_json::json!({ "error": "unsupported format" })),
+            )
+                .into_response();
+        }
+    };
+
+    let cache = crate::storage::local::LocalStorage::new(state.image_variants.cache_dir.clone());
+    let cache_key = format!("{}-{}.{}", media.content_hash, width, format.extension());
+
+    if let Ok(Some(data)) = cache.get(&cache_key).await {
+        return media_data_response(format.content_type(), data);
+    }
+
+    let original = match state.storage.get(&media.content_hash).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return not_found(),
+        Err(err) => return internal_error(err),
+    };
+
+    let data =
+        match tokio::task::spawn_blocking(move || crate::images::resize(&original, width, format))
+            .await
+        {
+            Ok(Ok(data)) => data,
+            Ok(Err(err)) => return internal_error(err),
+            Err(err) => return internal_error(err.to_string()),
+        };
+
+    if let Err(err) = cache.put(&cache_key, data.clone()).await {
+        tracing::warn!("can't write image variant cache: {}", err);
+    }
+
+    media_data_response(format.content_type(), data)
+}
+
+/// Adds `srcset` and `sizes` attributes pointing at the resized variants to
+/// every `<img>` that shows an uploaded image.
+pub fn with_srcset(contents: &str, widths: &[u32]) -> String {
+    if widths.is_empty() {
+        return contents.to_string();
+    }
+
+    let mut rendered = String::with_capacity(contents.len());
+    let mut rest = contents;
+
+    while let Some(start) = rest.find("<img") {
+        let Some(length) = tag_length(&rest[start..]) else {
+            break;
+        };
+        let end = start + length;
+        let tag = &rest[start..end];
+
+        rendered.push_str(&rest[..start]);
+
+        let media_id = referenced_media(tag).into_iter().next();
+        match media_id {
+            Some(media_id) if !tag.contains("srcset") => {
+                let srcset = widths
+                    .iter()
+                    .map(|width| {
+                        format!(
+                            "/api/media/{}?width={}&amp;format=webp {}w",
+                            media_id, width, width
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let self_closing = tag.ends_with('/');
+                rendered.push_str(&format!(
+                    r#"{} srcset="{}" sizes="(max-width: 768px) 100vw, 768px""#,
+                    tag.trim_end_matches('/').trim_end(),
+                    srcset
+                ));
+                if self_closing {
+                    rendered.push_str(" /");
+                }
+            }
+            _ => rendered.push_str(tag),
+        }
+
+        rest = &rest[end..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// The length of the tag at the start of `html` up to its closing `>`,
+/// skipping over any `>` inside quoted attribute values.
+fn tag_length(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(index),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn media_data_response(content_type: &str, data: Vec<u8>) -> axum::response::Response {
+    (
+        axum::http::StatusCode::OK,
+        [
+            (axum::http::header::CONTENT_TYPE, content_type),
+            (
+                axum::http::header::CACHE_CONTROL,
+                "public, max-age=31536000, immutable",
+            ),
+            (axum::http::header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+/// Finds the media ids referenced from post contents through their
+/// `/api/media/{id}` URLs, e.g. `<img src="/api/media/{id}">`.
+pub fn referenced_media(contents: &str) -> Vec<uuid::Uuid> {
+    let mut ids: Vec<uuid::Uuid> = contents
+        .match_indices("/api/media/")
+        .filter_map(|(index, prefix)| {
+            let start = index + prefix.len();
+            contents
+                .get(start..start + 36)
+                .and_then(|id| uuid::Uuid::parse_str(id).ok())
+        })
+        .collect();
+
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn media_response(
+    status: axum::http::StatusCode,
+    state: &AppState,
+    media: crate::db::repositories::media::Media,
+) -> axum::response::Response {
+    let url = format!("/api/media/{}", media.id);
+    // Posts are HTML from the editor, so the snippet is too
+    let html = format!(
+        r#"<img src="{}" alt="{}">"#,
+        url,
+        super::seo::xml_escape(&media.file_name)
+    );
+
+    (
+        status,
+        axum::response::Json(serde_json::json!({
+            "data": media,
+            "url": format!("{}{}", state.base_url, url),
+            "html": html,
+        })),
+    )
+        .into_response()
+}
+
+fn multipart_error(err: axum::extract::multipart::MultipartError) -> axum::response::Response {
+    (
+        err.status(),
+        axum::response::Json(serde_json::json!({ "error": err.body_text() })),
+    )
+        .into_response()
+}
+
+fn not_found() -> axum::response::Response {
+    (
+        axum::http::StatusCode::NOT_FOUND,
+        axum::response::Json(serde_json::json!({ "error": "media not found" })),
+    )
+        .into_response()
+}
+
+fn quota_exceeded() -> axum::response::Response {
+    (
+        axum::http::StatusCode::FORBIDDEN,
+        axum::response::Json(serde_json::json!({ "error": "storage quota exceeded" })),
+    )
+        .into_response()
+}
+
+fn internal_error(err: impl serde::Serialize) -> axum::response::Response {
+    (
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        axum::response::Json(serde_json::json!({ "error": err })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app, request, send};
+    use axum::http::{Method, StatusCode};
+
+    #[test]
+    fn with_srcset_only_touches_uploaded_images() {
+        let id = "0199f2a0-0000-7000-8000-000000000001";
+        let srcset = format!(
+            r#"srcset="/api/media/{id}?width=320&amp;format=webp 320w, /api/media/{id}?width=640&amp;format=webp 640w" sizes="(max-width: 768px) 100vw, 768px""#
+        );
+        let cases: &[(String, String)] = &[
+            (
+                format!(r#"<p><img src="/api/media/{id}" alt="a"></p>"#),
+                format!(r#"<p><img src="/api/media/{id}" alt="a" {srcset}></p>"#),
+            ),
+            (
+                format!(r#"<img src="/api/media/{id}" />"#),
+                format!(r#"<img src="/api/media/{id}" {srcset} />"#),
+            ),
+            (
+                format!(r#"<img src="/api/media/{id}"/>"#),
+                format!(r#"<img src="/api/media/{id}" {srcset} />"#),
+            ),
+            (
+                format!(r#"<img alt="1 > 0" src="/api/media/{id}">"#),
+                format!(r#"<img alt="1 > 0" src="/api/media/{id}" {srcset}>"#),
+            ),
+            (
+                format!(r#"<img alt='a "b" > c' src="/api/media/{id}">"#),
+                format!(r#"<img alt='a "b" > c' src="/api/media/{id}" {srcset}>"#),
+            ),
+            (
+                format!(r#"<img src="/api/media/{id}" srcset="/a.png 2x">"#),
+                format!(r#"<img src="/api/media/{id}" srcset="/a.png 2x">"#),
+            ),
+            (
+                r#"<img src="https://example.com/a.png">"#.to_string(),
+                r#"<img src="https://example.com/a.png">"#.to_string(),
+            ),
+            (
+                format!(r#"<img src="/api/media/{id}">, <img src="/a.png">"#),
+                format!(r#"<img src="/api/media/{id}" {srcset}>, <img src="/a.png">"#),
+            ),
+            (
+                format!(r#"<img src="/api/media/{id}""#),
+                format!(r#"<img src="/api/media/{id}""#),
+            ),
+        ];
+
+        for (contents, expected) in cases {
+            assert_eq!(
+                &with_srcset(contents, &[320, 640]),
+                expected,
+                "{:?}",
+                contents
+            );
+        }
+
+        let contents = format!(r#"<img src="/api/media/{id}">"#);
+        assert_eq!(with_srcset(&contents, &[]), contents);
+    }
+
+    async fn register(app: &axum::Router) -> String {
+        let (status, body) = send(
+            app,
+            request(
+                Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": "someone",
+                    "email": "someone@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        body["token"].as_str().unwrap().to_string()
+    }
+
+    /// Uploads `data` as the `file` field of a multipart form.
+    async fn upload(
+        app: &axum::Router,
+        token: &str,
+        data: &[u8],
+    ) -> (StatusCode, serde_json::Value) {
+        let mut body = b"--boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"image.png\"\r\n\
+            Content-Type: image/png\r\n\r\n"
+            .to_vec();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--boundary--\r\n");
+
+        let mut request = request(Method::POST, "/api/media", Some(token), None);
+        request.headers_mut().insert(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static("multipart/form-data; boundary=boundary"),
+        );
+        *request.body_mut() = axum::body::Body::from(body);
+
+        send(app, request).await
+    }
+
+    /// A PNG filled with `shade`, so different shades hash differently.
+    fn png(shade: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::from_pixel(16, 16, image::Rgb([shade, shade, shade]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut data),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        data
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn the_same_image_is_only_uploaded_once(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        let token = register(&app).await;
+
+        let (status, created) = upload(&app, &token, &png(0)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", created);
+
+        let (status, existing) = upload(&app, &token, &png(0)).await;
+        assert_eq!(status, StatusCode::OK, "{}", existing);
+        assert_eq!(existing["data"]["id"], created["data"]["id"]);
+
+        let (status, other) = upload(&app, &token, &png(255)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", other);
+        assert_ne!(other["data"]["id"], created["data"]["id"]);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn files_that_are_not_images_are_rejected(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+        let token = register(&app).await;
+
+        // Named and typed as a PNG, which the magic bytes don't back up
+        let (status, body) = upload(&app, &token, b"<script>alert(1)</script>").await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{}", body);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn files_over_max_bytes_are_rejected(pool: sqlx::PgPool) {
+        let mut config = crate::config::Config::default();
+        let data = png(0);
+        config.media.max_bytes = data.len() as i64 - 1;
+        let app = app(pool, &config);
+        let token = register(&app).await;
+
+        let (status, body) = upload(&app, &token, &data).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{}", body);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn uploads_past_the_quota_are_rejected(pool: sqlx::PgPool) {
+        let mut config = crate::config::Config::default();
+        config.media.quota_bytes = png(0).len() as i64 + 1;
+        let app = app(pool, &config);
+        let token = register(&app).await;
+
+        let (status, body) = upload(&app, &token, &png(0)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        let (status, body) = upload(&app, &token, &png(255)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+        assert_eq!(body["error"], "storage quota exceeded");
+
+        // Uploading an image again doesn't count against the quota
+        let (status, body) = upload(&app, &token, &png(0)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+}
@@ -1,92 +1,388 @@
-mod accounts;
+mod access_tokens;
+pub mod accounts;
+mod frontend;
+pub mod health;
+mod limits;
+mod magic_links;
+pub mod media;
+mod openapi;
+mod passkeys;
 mod posts;
+mod security;
+mod seo;
+mod sessions;
+mod totp;
 use crate::db::repositories::Queries;
 
+const X_REQUEST_ID: &str = "x-request-id";
+
 #[derive(Clone)]
 pub struct AppState {
     pub queries: Queries,
-    pub jwt_secret: String,
+    pub keys: std::sync::Arc<crate::jwt::Keys>,
+    pub token_lifetime: chrono::Duration,
+    pub session_mode: crate::config::SessionMode,
+    pub base_url: String,
+    pub trusted_proxies: std::sync::Arc<[ipnet::IpNet]>,
+    pub og_cache_dir: std::path::PathBuf,
+    pub storage: std::sync::Arc<dyn crate::storage::Storage>,
+    pub mailer: std::sync::Arc<dyn crate::mail::Mailer>,
+    pub media_limits: MediaLimits,
+    pub image_variants: ImageVariants,
+    pub features: crate::config::FeaturesConfig,
+    pub relying_party: crate::webauthn::RelyingParty,
 }
 
 #[derive(Clone)]
-pub struct JwtSecret(pub String);
+pub struct MediaLimits {
+    pub max_bytes: i64,
+    pub quota_bytes: i64,
+}
+
+#[derive(Clone)]
+pub struct ImageVariants {
+    pub widths: Vec<u32>,
+    pub cache_dir: std::path::PathBuf,
+}
 
-pub fn setup_make_app(pool: sqlx::Pool<sqlx::Postgres>, jwt_secret: JwtSecret) -> axum::Router {
+pub fn setup_make_app(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    config: &crate::config::Config,
+    storage: std::sync::Arc<dyn crate::storage::Storage>,
+    mailer: std::sync::Arc<dyn crate::mail::Mailer>,
+    rate_limit_store: Option<std::sync::Arc<dyn crate::rate_limit::RateLimitStore>>,
+    keys: std::sync::Arc<crate::jwt::Keys>,
+) -> axum::Router {
     let queries = Queries::new(pool);
 
     let state = AppState {
         queries,
-        jwt_secret: jwt_secret.0.clone(),
+        keys,
+        token_lifetime: chrono::Duration::seconds(config.auth.token_lifetime_secs),
+        session_mode: config.auth.session_mode,
+        base_url: config.server.base_url.clone(),
+        trusted_proxies: config.server.trusted_proxies.clone().into(),
+        og_cache_dir: config.og.cache_dir.clone(),
+        storage,
+        mailer,
+        media_limits: MediaLimits {
+            max_bytes: config.media.max_bytes,
+            quota_bytes: config.media.quota_bytes,
+        },
+        image_variants: ImageVariants {
+            widths: config.images.widths.clone(),
+            cache_dir: config.images.cache_dir.clone(),
+        },
+        features: config.features.clone(),
+        relying_party: crate::webauthn::RelyingParty::from_base_url(&config.server.base_url)
+            .expect("base url is validated with the config"),
     };
 
-    let authenticated_routes = axum::Router::new()
-        .route("/api/me", axum::routing::get(accounts::me))
-        .route("/api/posts", axum::routing::post(posts::create_post))
+    // Leave room for the multipart framing around the file itself
+    let media_body_limit = state.media_limits.max_bytes as usize + 64 * 1024;
+
+    let rate_limit = |group: &'static str, policy: &crate::config::RateLimitPolicy| {
+        rate_limit_store
+            .clone()
+            .map(|store| crate::rate_limit::RateLimit {
+                group,
+                policy: policy.clone(),
+                store,
+                trusted_proxies: state.trusted_proxies.clone(),
+            })
+    };
+
+    // Personal access tokens reach these only with the matching scope
+    let scoped = |router: axum::Router<AppState>, scope: access_tokens::Scope| {
+        router.route_layer(axum::middleware::from_fn_with_state(
+            scope,
+            access_tokens::require_scope,
+        ))
+    };
+
+    let account_routes = axum::Router::new()
         .route(
-            "/api/posts/{post_id}",
-            axum::routing::put(posts::update_post),
+            "/api/me/totp",
+            axum::routing::post(totp::start_enrollment).delete(totp::disable),
         )
         .route(
-            "/api/posts/{post_id}",
-            axum::routing::delete(posts::delete_post),
+            "/api/me/totp/confirm",
+            axum::routing::post(totp::confirm_enrollment),
         )
-        .route_layer(axum::middleware::from_fn_with_state(
-            state.clone(),
-            auth_middleware,
-        ));
+        .route(
+            "/api/me/passkeys",
+            axum::routing::get(passkeys::list_passkeys).post(passkeys::register),
+        )
+        .route(
+            "/api/me/passkeys/options",
+            axum::routing::post(passkeys::registration_options),
+        )
+        .route(
+            "/api/me/passkeys/{passkey_id}",
+            axum::routing::patch(passkeys::rename_passkey).delete(passkeys::delete_passkey),
+        )
+        .route(
+            "/api/me/tokens",
+            axum::routing::get(access_tokens::list_tokens).post(access_tokens::create_token),
+        )
+        .route(
+            "/api/me/tokens/{token_id}",
+            axum::routing::delete(access_tokens::revoke_token),
+        )
+        .route_layer(axum::middleware::from_fn(access_tokens::session_only));
+
+    let profile_routes = scoped(
+        axum::Router::new().route("/api/me", axum::routing::get(accounts::me)),
+        access_tokens::Scope::AccountRead,
+    );
 
-    axum::Router::new()
-        .route("/api/login", axum::routing::post(accounts::login))
-        .route("/api/register", axum::routing::post(accounts::register))
+    let post_routes = scoped(
+        axum::Router::new()
+            .route("/api/posts", axum::routing::post(posts::create_post))
+            .route(
+                "/api/posts/{post_id}",
+                axum::routing::put(posts::update_post),
+            )
+            .route(
+                "/api/posts/{post_id}",
+                axum::routing::delete(posts::delete_post),
+            )
+            .layer(axum::extract::DefaultBodyLimit::max(
+                config.server.post_body_limit_bytes,
+            )),
+        access_tokens::Scope::PostsWrite,
+    );
+
+    let media_routes = scoped(
+        axum::Router::new().route(
+            "/api/media",
+            axum::routing::post(media::upload_media)
+                .layer(axum::extract::DefaultBodyLimit::max(media_body_limit)),
+        ),
+        access_tokens::Scope::MediaWrite,
+    );
+
+    // The rate limit is added first so it runs after authentication and can
+    // key on the account
+    let authenticated_routes = with_rate_limit(
+        account_routes.merge(profile_routes).merge(post_routes),
+        rate_limit("write", &config.rate_limit.write),
+    )
+    .route_layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        auth_middleware,
+    ));
+
+    // Kept apart from the other routes so the request timeout doesn't wrap
+    // uploads and they can have a longer one
+    let upload_routes =
+        with_rate_limit(media_routes, rate_limit("write", &config.rate_limit.write))
+            .route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                std::time::Duration::from_secs(config.server.upload_timeout_secs),
+                limits::request_timeout,
+            ));
+
+    let auth_routes = with_rate_limit(
+        axum::Router::new()
+            .route("/api/login", axum::routing::post(accounts::login))
+            .route("/api/login/mfa", axum::routing::post(accounts::login_mfa))
+            .route(
+                "/api/login/magic",
+                axum::routing::post(magic_links::request_link),
+            )
+            .route(
+                "/api/login/magic/verify",
+                axum::routing::post(magic_links::verify_link),
+            )
+            .route("/api/login/passkey", axum::routing::post(passkeys::login))
+            .route(
+                "/api/login/passkey/options",
+                axum::routing::post(passkeys::authentication_options),
+            )
+            .route("/api/register", axum::routing::post(accounts::register)),
+        rate_limit("auth", &config.rate_limit.auth),
+    );
+
+    let mut read_routes = axum::Router::new()
         .route("/api/posts", axum::routing::get(posts::list_posts))
         .route("/api/posts/{post_id}", axum::routing::get(posts::get_post))
+        .route(
+            "/api/media/{media_id}",
+            axum::routing::get(media::get_media),
+        );
+
+    if state.features.og_images {
+        read_routes = read_routes.route(
+            "/api/posts/{post_id}/og.png",
+            axum::routing::get(seo::og_image),
+        );
+    }
+
+    let read_routes = with_rate_limit(read_routes, rate_limit("read", &config.rate_limit.read));
+
+    let public_routes = axum::Router::new()
+        .route("/sitemap.xml", axum::routing::get(seo::sitemap))
+        .route("/robots.txt", axum::routing::get(seo::robots))
+        .route("/api/logout", axum::routing::post(sessions::logout))
+        .route("/.well-known/jwks.json", axum::routing::get(accounts::jwks))
+        .merge(openapi::router())
+        .merge(auth_routes)
+        .merge(read_routes);
+
+    let app = public_routes
         .merge(authenticated_routes)
-        .fallback_service(axum::routing::get(serve_frontend))
-        .route_layer(axum::middleware::from_fn(print_middleware))
-        .with_state(state)
+        .fallback_service(frontend::service(&config.frontend, state.clone()))
+        .layer(axum::middleware::from_fn_with_state(
+            std::time::Duration::from_secs(config.server.request_timeout_secs),
+            limits::request_timeout,
+        ))
+        .merge(upload_routes)
+        // Routes with a limit of their own override this one
+        .layer(axum::extract::DefaultBodyLimit::max(
+            config.server.body_limit_bytes,
+        ))
+        .layer(axum::middleware::from_fn(limits::payload_too_large))
+        .layer(axum::middleware::from_fn_with_state(
+            security::SecurityHeaders::from_config(config),
+            security::security_headers,
+        ));
+
+    // Outside the security headers so preflights are answered straight away
+    let app = match security::cors_layer(&config.cors) {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+
+    let app = match limits::compression_layer(&config.server) {
+        Some(compression) => app.layer(compression),
+        None => app,
+    };
+
+    app.layer(axum::middleware::from_fn(
+        crate::telemetry::metrics::metrics_middleware,
+    ))
+    .layer(axum::middleware::from_fn(trace_middleware))
+    .with_state(state)
 }
 
-#[derive(rust_embed::RustEmbed)]
-#[folder = "src/frontend/dist"]
-struct ClientAssets;
-
-async fn serve_frontend(request: axum::extract::Request) -> impl axum::response::IntoResponse {
-    let path = request.uri().path().trim_start_matches('/');
-    let path = if path.is_empty() { "index.html" } else { path };
-    match ClientAssets::get(path) {
-        Some(asset) => {
-            let mime_type = mime_guess::from_path(path).first_or_octet_stream();
-
-            axum::response::Response::builder()
-                .header(axum::http::header::CONTENT_TYPE, mime_type.as_ref())
-                .header(axum::http::header::CACHE_CONTROL, "public, max-age=3600")
-                .status(axum::http::StatusCode::OK)
-                .body(axum::body::Body::from(asset.data))
-                .unwrap()
-        }
-        None => {
-            // Fallback to index.html for SPA routing
-            match ClientAssets::get("index.html") {
-                Some(index) => axum::response::Response::builder()
-                    .header(axum::http::header::CONTENT_TYPE, "text/html; charset=utf-8")
-                    .status(axum::http::StatusCode::OK)
-                    .body(axum::body::Body::from(index.data))
-                    .unwrap(),
-                None => axum::response::Response::builder()
-                    .status(axum::http::StatusCode::NOT_FOUND)
-                    .body(axum::body::Body::from("404 Not Found"))
-                    .unwrap(),
-            }
+fn with_rate_limit(
+    router: axum::Router<AppState>,
+    rate_limit: Option<crate::rate_limit::RateLimit>,
+) -> axum::Router<AppState> {
+    match rate_limit {
+        Some(rate_limit) => router.route_layer(axum::middleware::from_fn_with_state(
+            rate_limit,
+            crate::rate_limit::rate_limit_middleware,
+        )),
+        None => router,
+    }
+}
+
+/// The client's address. When the peer is a trusted proxy, `X-Forwarded-For`
+/// is walked from the right and the first address that isn't a trusted proxy
+/// is used, so entries a client forges on the left are ignored. Entries that
+/// aren't plain IP addresses are skipped.
+pub fn client_ip(
+    headers: &axum::http::HeaderMap,
+    peer: std::net::SocketAddr,
+    trusted_proxies: &[ipnet::IpNet],
+) -> std::net::IpAddr {
+    let is_trusted = |ip: &std::net::IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+
+    let mut client = peer.ip();
+    if !is_trusted(&client) {
+        return client;
+    }
+
+    let forwarded: Vec<std::net::IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+
+    for ip in forwarded.into_iter().rev() {
+        client = ip;
+        if !is_trusted(&client) {
+            break;
         }
     }
+
+    client
+}
+
+/// The value of a request cookie.
+pub fn cookie<'a>(headers: &'a axum::http::HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, value)| *key == name && !value.is_empty())
+        .map(|(_, value)| value)
 }
 
-async fn print_middleware(
+async fn trace_middleware(
     request: axum::extract::Request,
     next: axum::middleware::Next,
-) -> impl axum::response::IntoResponse {
-    println!("{} request on {}", request.method(), request.uri());
-    next.run(request).await
+) -> axum::response::Response {
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    // Reuse the id set by a proxy or client so logs can be correlated across services
+    let request_id = request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        route = tracing::field::Empty,
+        request_id = %request_id,
+        status = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    );
+
+    if let Some(route) = request.extensions().get::<axum::extract::MatchedPath>() {
+        span.record("route", route.as_str());
+    }
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&crate::telemetry::HeaderExtractor(request.headers()))
+    });
+    let _ = span.set_parent(parent);
+
+    let start = std::time::Instant::now();
+    let mut response = next.run(request).instrument(span.clone()).await;
+    let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let status = response.status().as_u16();
+
+    span.record("status", status);
+    span.record("duration_ms", duration_ms);
+    span.in_scope(|| {
+        if response.status().is_server_error() {
+            tracing::error!(status, duration_ms, "request failed");
+        } else {
+            tracing::info!(status, duration_ms, "request completed");
+        }
+    });
+
+    if let Ok(value) = axum::http::HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(X_REQUEST_ID, value);
+    }
+
+    response
 }
 
 async fn auth_middleware(
@@ -95,51 +391,379 @@ async fn auth_middleware(
     mut request: axum::extract::Request,
     next: axum::middleware::Next,
 ) -> Result<axum::response::Response, impl axum::response::IntoResponse> {
-    let auth_header = match headers.get(axum::http::header::AUTHORIZATION) {
-        Some(header) => header,
-        None => {
+    let bearer = match headers.get(axum::http::header::AUTHORIZATION) {
+        Some(header) => match header
+            .to_str()
+            .ok()
+            .and_then(|header| header.strip_prefix("Bearer "))
+        {
+            Some(token) => Some(token),
+            None => {
+                return Err((
+                    axum::http::StatusCode::UNAUTHORIZED,
+                    axum::response::Json(
+                        serde_json::json!({"error": "invalid authorization header"}),
+                    ),
+                ));
+            }
+        },
+        None => None,
+    };
+
+    if let Some(token) = bearer
+        && token.starts_with(access_tokens::TOKEN_PREFIX)
+    {
+        return match access_tokens::authenticate(&state, token).await {
+            Ok(Some((claims, scopes))) => {
+                request.extensions_mut().insert(claims);
+                request.extensions_mut().insert(scopes);
+                Ok(next.run(request).await)
+            }
+            Ok(None) => Err((
+                axum::http::StatusCode::UNAUTHORIZED,
+                axum::response::Json(serde_json::json!({"error": "invalid token"})),
+            )),
+            Err(err) => Err((
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )),
+        };
+    }
+
+    let (token, from_cookie) = match bearer {
+        Some(token) if state.session_mode.allows_bearer() => (token, false),
+        Some(_) => {
             return Err((
                 axum::http::StatusCode::UNAUTHORIZED,
-                axum::response::Json(serde_json::json!({"error": "missing authorization header"})),
+                axum::response::Json(
+                    serde_json::json!({"error": "sessions are only accepted as cookies"}),
+                ),
             ));
         }
+        None => match sessions::session_cookie(&state, &headers) {
+            Some(token) => (token, true),
+            None => {
+                return Err((
+                    axum::http::StatusCode::UNAUTHORIZED,
+                    axum::response::Json(
+                        serde_json::json!({"error": "missing authorization header"}),
+                    ),
+                ));
+            }
+        },
     };
 
-    let token = match auth_header.to_str() {
-        Ok(token) => token,
+    // Tokens with an audience are rejected when none is named, which keeps
+    // MFA challenge tokens from being used as sessions
+    let claims = match state.keys.decode::<accounts::Claims>(token, None) {
+        Ok(claims) => claims,
         Err(_) => {
             return Err((
                 axum::http::StatusCode::UNAUTHORIZED,
-                axum::response::Json(serde_json::json!({"error": "invalid authorization header"})),
+                axum::response::Json(serde_json::json!({"error": "invalid token"})),
             ));
         }
     };
 
-    let token = match token.strip_prefix("Bearer ") {
-        Some(token) => token,
-        None => {
+    if from_cookie && !sessions::check_csrf(request.method(), &headers, &claims) {
+        return Err((
+            axum::http::StatusCode::FORBIDDEN,
+            axum::response::Json(serde_json::json!({"error": "missing or invalid CSRF token"})),
+        ));
+    }
+
+    // Tokens stay valid until they expire, so disabled accounts are checked here
+    match state.queries.is_account_active(claims.sub).await {
+        Ok(true) => {}
+        Ok(false) => {
             return Err((
                 axum::http::StatusCode::UNAUTHORIZED,
-                axum::response::Json(serde_json::json!({"error": "invalid authorization header"})),
+                axum::response::Json(serde_json::json!({"error": "account is disabled"})),
             ));
         }
-    };
-
-    let claims = match jsonwebtoken::decode::<accounts::Claims>(
-        token,
-        &jsonwebtoken::DecodingKey::from_secret(state.jwt_secret.as_ref()),
-        &jsonwebtoken::Validation::default(),
-    ) {
-        Ok(claims) => claims,
-        Err(_) => {
+        Err(err) => {
             return Err((
-                axum::http::StatusCode::UNAUTHORIZED,
-                axum::response::Json(serde_json::json!({"error": "invalid token"})),
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
             ));
         }
-    };
+    }
 
-    request.extensions_mut().insert(claims.claims);
+    request.extensions_mut().insert(claims);
 
     Ok(next.run(request).await)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    /// The whole app over `pool` with `config`, keeping uploads and mail in a
+    /// fresh temporary directory.
+    pub(crate) fn app(pool: sqlx::PgPool, config: &crate::config::Config) -> axum::Router {
+        app_with_rate_limit(pool, config, None)
+    }
+
+    /// Like `app`, with the route groups limited through `store`.
+    pub(crate) fn app_with_rate_limit(
+        pool: sqlx::PgPool,
+        config: &crate::config::Config,
+        store: Option<std::sync::Arc<dyn crate::rate_limit::RateLimitStore>>,
+    ) -> axum::Router {
+        let dir = std::env::temp_dir().join(format!("landing-test-{}", uuid::Uuid::new_v4()));
+        let from = config.mail.from.parse().unwrap();
+
+        setup_make_app(
+            pool,
+            config,
+            std::sync::Arc::new(crate::storage::local::LocalStorage::new(dir.join("media"))),
+            std::sync::Arc::new(crate::mail::file::FileMailer::new(from, dir.join("mail"))),
+            store,
+            std::sync::Arc::new(crate::jwt::Keys::new(
+                &config.auth.jwt_secret,
+                chrono::Duration::seconds(config.auth.token_lifetime_secs),
+            )),
+        )
+    }
+
+    /// A pool that never connects, for requests that don't reach the database.
+    pub(crate) fn lazy_pool() -> sqlx::PgPool {
+        sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap()
+    }
+
+    /// A request from 203.0.113.1, with a JSON body and bearer token when given.
+    pub(crate) fn request(
+        method: axum::http::Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> axum::extract::Request {
+        let mut builder = axum::http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            builder = builder.header(
+                axum::http::header::AUTHORIZATION,
+                format!("Bearer {}", token),
+            );
+        }
+        let body = match body {
+            Some(body) => {
+                builder = builder.header(axum::http::header::CONTENT_TYPE, "application/json");
+                axum::body::Body::from(body.to_string())
+            }
+            None => axum::body::Body::empty(),
+        };
+
+        let mut request = builder.body(body).unwrap();
+        request
+            .extensions_mut()
+            .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                [203, 0, 113, 1],
+                443,
+            ))));
+        request
+    }
+
+    /// Sends a request through `app` and reads the body as JSON, or null when
+    /// it isn't any.
+    pub(crate) async fn send(
+        app: &axum::Router,
+        request: axum::extract::Request,
+    ) -> (axum::http::StatusCode, serde_json::Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (
+            status,
+            serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+        )
+    }
+
+    fn client(peer: &str, forwarded: &[&str]) -> String {
+        let trusted: Vec<ipnet::IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let mut headers = axum::http::HeaderMap::new();
+        for value in forwarded {
+            headers.append("x-forwarded-for", value.parse().unwrap());
+        }
+
+        client_ip(&headers, format!("{}:443", peer).parse().unwrap(), &trusted).to_string()
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_for_from_untrusted_peers() {
+        assert_eq!(client("203.0.113.7", &["198.51.100.1"]), "203.0.113.7");
+        assert_eq!(client("203.0.113.7", &[]), "203.0.113.7");
+    }
+
+    #[test]
+    fn client_ip_takes_the_rightmost_untrusted_entry() {
+        assert_eq!(client("10.0.0.1", &["198.51.100.1"]), "198.51.100.1");
+        assert_eq!(
+            client("10.0.0.1", &["198.51.100.1, 10.0.0.2, 10.0.0.3"]),
+            "198.51.100.1"
+        );
+        // Header lines are joined in order, as if they were one list
+        assert_eq!(
+            client("10.0.0.1", &["198.51.100.1", "10.0.0.2"]),
+            "198.51.100.1"
+        );
+    }
+
+    #[test]
+    fn client_ip_ignores_forged_left_hand_entries() {
+        assert_eq!(
+            client("10.0.0.1", &["192.0.2.66, 10.0.0.9, 198.51.100.1"]),
+            "198.51.100.1"
+        );
+        assert_eq!(
+            client("10.0.0.1", &["192.0.2.66", "198.51.100.1, 10.0.0.2"]),
+            "198.51.100.1"
+        );
+    }
+
+    #[test]
+    fn client_ip_uses_the_leftmost_entry_when_all_are_trusted() {
+        assert_eq!(client("10.0.0.1", &["10.0.0.3, 10.0.0.2"]), "10.0.0.3");
+        assert_eq!(client("10.0.0.1", &[]), "10.0.0.1");
+    }
+
+    #[test]
+    fn client_ip_skips_malformed_entries() {
+        assert_eq!(
+            client("10.0.0.1", &["198.51.100.1, not-an-ip, 10.0.0.2"]),
+            "198.51.100.1"
+        );
+        assert_eq!(
+            client("10.0.0.1", &["198.51.100.1:8080, unknown"]),
+            "10.0.0.1"
+        );
+        assert_eq!(client("10.0.0.1", &[" , ,"]), "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_get_a_json_413() {
+        let app = app(lazy_pool(), &crate::config::Config::default());
+        let body = serde_json::json!({
+            "email": "x".repeat(crate::config::Config::default().server.body_limit_bytes),
+            "password": "secret",
+        });
+
+        let (status, body) = send(
+            &app,
+            request(axum::http::Method::POST, "/api/login", None, Some(body)),
+        )
+        .await;
+        assert_eq!(status, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "request body is too large"})
+        );
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn post_routes_take_bodies_over_the_default_limit(pool: sqlx::PgPool) {
+        let config = crate::config::Config::default();
+        let app = app(pool, &config);
+        let (status, body) = send(
+            &app,
+            request(
+                axum::http::Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": "writer",
+                    "email": "writer@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await;
+        assert_eq!(status, axum::http::StatusCode::OK, "{}", body);
+        let token = body["token"].as_str().unwrap();
+        let large = "x".repeat(config.server.body_limit_bytes);
+
+        let (status, _) = send(
+            &app,
+            request(
+                axum::http::Method::POST,
+                "/api/posts",
+                Some(token),
+                Some(serde_json::json!({"content": format!("<p>{}</p>", large)})),
+            ),
+        )
+        .await;
+        assert_eq!(status, axum::http::StatusCode::CREATED);
+
+        let (status, body) = send(
+            &app,
+            request(
+                axum::http::Method::POST,
+                "/api/me/tokens",
+                Some(token),
+                Some(serde_json::json!({"name": large, "scopes": []})),
+            ),
+        )
+        .await;
+        assert_eq!(status, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "request body is too large"})
+        );
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn uploads_have_their_own_timeout(pool: sqlx::PgPool) {
+        let mut config = crate::config::Config::default();
+        config.server.request_timeout_secs = 1;
+        config.server.upload_timeout_secs = 2;
+        let app = app(pool, &config);
+        let (_, body) = send(
+            &app,
+            request(
+                axum::http::Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": "uploader",
+                    "email": "uploader@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await;
+        let token = body["token"].as_str().unwrap();
+
+        let stalled = |uri: &str, content_type: &str| {
+            let mut request = request(axum::http::Method::POST, uri, Some(token), None);
+            request.headers_mut().insert(
+                axum::http::header::CONTENT_TYPE,
+                axum::http::HeaderValue::from_str(content_type).unwrap(),
+            );
+            request.map(|_| axum::body::Body::new(super::limits::tests::StalledBody::default()))
+        };
+
+        for (uri, content_type, timeout) in [
+            ("/api/posts", "application/json", 1),
+            ("/api/media", "multipart/form-data; boundary=x", 2),
+        ] {
+            let started = std::time::Instant::now();
+            let (status, _) = send(&app, stalled(uri, content_type)).await;
+            let elapsed = started.elapsed().as_secs_f64();
+
+            assert_eq!(status, axum::http::StatusCode::REQUEST_TIMEOUT, "{}", uri);
+            assert!(
+                (timeout as f64..timeout as f64 + 0.5).contains(&elapsed),
+                "{} took {}s",
+                uri,
+                elapsed
+            );
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use super::AppState;
+use utoipa::OpenApi;
+
+// Where the spec and the docs UI are served, outside the spec itself
+const SPEC_PATH: &str = "/api/openapi.json";
+const DOCS_PATH: &str = "/api/docs";
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    info(
+        title = "Landing API",
+        description = "The blog's JSON API. Errors come back as `{\"error\": ...}` with a \
+                       4xx or 5xx status."
+    ),
+    paths(
+        super::accounts::register,
+        super::accounts::login,
+        super::accounts::login_mfa,
+        super::accounts::me,
+        super::accounts::jwks,
+        super::sessions::logout,
+        super::magic_links::request_link,
+        super::magic_links::verify_link,
+        super::passkeys::authentication_options,
+        super::passkeys::login,
+        super::passkeys::registration_options,
+        super::passkeys::register,
+        super::passkeys::list_passkeys,
+        super::passkeys::rename_passkey,
+        super::passkeys::delete_passkey,
+        super::totp::start_enrollment,
+        super::totp::confirm_enrollment,
+        super::totp::disable,
+        super::access_tokens::list_tokens,
+        super::access_tokens::create_token,
+        super::access_tokens::revoke_token,
+        super::posts::list_posts,
+        super::posts::get_post,
+        super::posts::create_post,
+        super::posts::update_post,
+        super::posts::delete_post,
+        super::seo::og_image,
+        super::media::upload_media,
+        super::media::get_media,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "auth", description = "Logging in and out"),
+        (name = "account", description = "The logged in account, its passkeys, 2FA and tokens"),
+        (name = "posts"),
+        (name = "media", description = "Uploaded images"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The spec as JSON and Swagger UI to try it out.
+pub fn router() -> axum::Router<AppState> {
+    utoipa_swagger_ui::SwaggerUi::new(DOCS_PATH)
+        .url(SPEC_PATH, ApiDoc::openapi())
+        .into()
+}
+
+struct SecuritySchemes;
+
+impl utoipa::Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{
+            ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme,
+        };
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "A session token, or a personal access token (`lpat_...`) with the \
+                         scope the route needs",
+                    ))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                "session",
+                "The session cookie. Requests other than GET, HEAD and OPTIONS must also send \
+                 the `csrf_token` cookie's value as `X-CSRF-Token`",
+            ))),
+        );
+    }
+}
+
+// The types below only describe response bodies the handlers build with
+// `json!`, nothing constructs them
+
+/// Most responses wrap their payload in `data`.
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct Data<T> {
+    data: T,
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(utoipa::ToSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum ErrorDetail {
+    Message(String),
+    Query(crate::db::repositories::Error),
+}
+
+/// What a login hands out depends on `auth.session_mode`: the bearer token,
+/// the CSRF token for a cookie session, or both.
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct Session {
+    token: Option<String>,
+    csrf_token: Option<String>,
+}
+
+/// The password was right but the account has 2FA, send a code with the
+/// token to `/api/login/mfa`.
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct MfaChallenge {
+    mfa_required: bool,
+    mfa_token: String,
+}
+
+#[derive(utoipa::ToSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+pub enum LoginResult {
+    Session(Session),
+    MfaChallenge(MfaChallenge),
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct Message {
+    message: String,
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct PasskeyOptions {
+    challenge_id: uuid::Uuid,
+    /// Options for `navigator.credentials.create` or `.get`, binary fields
+    /// as base64url
+    #[schema(value_type = Object)]
+    public_key: (),
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct TotpEnrollment {
+    secret: String,
+    otpauth_uri: String,
+    /// The `otpauth_uri` as a PNG data URL
+    qr_code: String,
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct RecoveryCodes {
+    /// Each works once in place of a code, they aren't shown again
+    recovery_codes: Vec<String>,
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct CreatedToken {
+    data: crate::db::repositories::access_tokens::AccessToken,
+    /// The token itself, only ever returned here
+    token: String,
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct UploadedMedia {
+    data: crate::db::repositories::media::Media,
+    url: String,
+    /// An `<img>` tag to paste into a post
+    html: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app, lazy_pool, request, send};
+
+    const METHODS: &[axum::http::Method] = &[
+        axum::http::Method::GET,
+        axum::http::Method::POST,
+        axum::http::Method::PUT,
+        axum::http::Method::PATCH,
+        axum::http::Method::DELETE,
+    ];
+    const MATCHED_PATH: &str = "x-matched-path";
+
+    fn documented() -> std::collections::HashSet<(axum::http::Method, String)> {
+        let mut routes = std::collections::HashSet::new();
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let operations = [
+                (axum::http::Method::GET, &item.get),
+                (axum::http::Method::POST, &item.post),
+                (axum::http::Method::PUT, &item.put),
+                (axum::http::Method::PATCH, &item.patch),
+                (axum::http::Method::DELETE, &item.delete),
+            ];
+            for (method, operation) in operations {
+                if operation.is_some() {
+                    routes.insert((method, path.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    fn documented_paths() -> std::collections::BTreeSet<String> {
+        documented().into_iter().map(|(_, path)| path).collect()
+    }
+
+    /// The API paths `app` routes. A router can't be listed, but its `Debug`
+    /// output names every path. The spec and docs UI aren't in the spec, and
+    /// the sitemap and robots.txt are for crawlers, not API clients.
+    fn routed_paths(app: &axum::Router) -> std::collections::BTreeSet<String> {
+        format!("{:?}", app)
+            .split('"')
+            .filter(|part| part.starts_with("/api/") || part.starts_with("/.well-known/"))
+            .filter(|path| *path != SPEC_PATH && !path.starts_with(DOCS_PATH))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Adds the route a request matched to the response. Without
+    /// `run_handlers` the response is sent straight away, so nothing behind
+    /// the route, not even its authentication, runs.
+    fn probed(app: axum::Router, run_handlers: bool) -> axum::Router {
+        app.route_layer(axum::middleware::from_fn(
+            move |matched: axum::extract::MatchedPath,
+                  request: axum::extract::Request,
+                  next: axum::middleware::Next| async move {
+                let mut response = if run_handlers {
+                    next.run(request).await
+                } else {
+                    axum::response::IntoResponse::into_response(axum::http::StatusCode::NO_CONTENT)
+                };
+                response.headers_mut().insert(
+                    MATCHED_PATH,
+                    axum::http::HeaderValue::from_str(matched.as_str()).unwrap(),
+                );
+                response
+            },
+        ))
+    }
+
+    /// A request to `path` with its parameters filled in.
+    fn uri(path: &str) -> String {
+        path.split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    uuid::Uuid::nil().to_string()
+                } else {
+                    segment.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    async fn matched_path(
+        app: &axum::Router,
+        request: axum::extract::Request,
+    ) -> (axum::http::StatusCode, Option<String>) {
+        let response = tower::ServiceExt::oneshot(app.clone(), request)
+            .await
+            .unwrap();
+        let matched = response
+            .headers()
+            .get(MATCHED_PATH)
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), matched)
+    }
+
+    #[tokio::test]
+    async fn spec_paths_match_routes() {
+        let app = app(lazy_pool(), &crate::config::Config::default());
+        let documented = documented_paths();
+        let routed = routed_paths(&app);
+
+        let undocumented: Vec<_> = routed.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "routes missing from the spec: {:?}",
+            undocumented
+        );
+
+        let app = probed(app, false);
+        for path in documented {
+            let request = request(axum::http::Method::GET, &uri(&path), None, None);
+            let (_, matched) = matched_path(&app, request).await;
+            assert_eq!(
+                matched.as_deref(),
+                Some(path.as_str()),
+                "spec entry without a route"
+            );
+        }
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn spec_methods_match_routes(pool: sqlx::PgPool) {
+        let app = probed(app(pool, &crate::config::Config::default()), true);
+        let (_, body) = send(
+            &app,
+            request(
+                axum::http::Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": "spec",
+                    "email": "spec@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await;
+        let token = body["token"].as_str().unwrap().to_string();
+
+        // Authentication runs before the method is checked, so every request
+        // has a session. None has a body, handlers reject them before doing
+        // anything.
+        let documented = documented();
+        for path in documented_paths() {
+            for method in METHODS {
+                let request = request(method.clone(), &uri(&path), Some(&token), None);
+                let (status, matched) = matched_path(&app, request).await;
+                assert_eq!(
+                    matched.as_deref(),
+                    Some(path.as_str()),
+                    "{} {}",
+                    method,
+                    path
+                );
+
+                let is_documented = documented.contains(&(method.clone(), path.clone()));
+                assert_eq!(
+                    status != axum::http::StatusCode::METHOD_NOT_ALLOWED,
+                    is_documented,
+                    "{} {} is {} but answered {}",
+                    method,
+                    path,
+                    if is_documented {
+                        "documented"
+                    } else {
+                        "not documented"
+                    },
+                    status
+                );
+            }
+        }
+    }
+}
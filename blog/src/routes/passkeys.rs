@@ -0,0 +1,775 @@
+use super::AppState;
+use crate::db::repositories::passkeys::{CreatedPasskey, PasskeyCredential};
+use axum::response::IntoResponse;
+
+// Time allowed to answer a challenge, also passed to the browser as its timeout
+const CHALLENGE_LIFETIME_SECS: i64 = 5 * 60;
+const MAX_NAME_LEN: usize = 100;
+const DEFAULT_NAME: &str = "Passkey";
+
+const REGISTRATION: &str = "registration";
+const AUTHENTICATION: &str = "authentication";
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = PasskeyRegisterParams)]
+pub struct RegisterParams {
+    pub challenge_id: uuid::Uuid,
+    pub name: Option<String>,
+    /// The `PublicKeyCredential` from `navigator.credentials.create`
+    #[schema(value_type = Object)]
+    pub credential: crate::webauthn::RegistrationCredential,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = PasskeyRenameParams)]
+pub struct RenameParams {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = PasskeyLoginParams)]
+pub struct LoginParams {
+    pub challenge_id: uuid::Uuid,
+    /// The `PublicKeyCredential` from `navigator.credentials.get`
+    #[schema(value_type = Object)]
+    pub credential: crate::webauthn::AuthenticationCredential,
+}
+
+/// Options for `navigator.credentials.create()`. Credentials the account
+/// already has are excluded so an authenticator isn't registered twice.
+#[utoipa::path(
+    post,
+    path = "/api/me/passkeys/options",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    responses((status = 200, body = super::openapi::Data<super::openapi::PasskeyOptions>))
+)]
+pub async fn registration_options(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+) -> axum::response::Response {
+    let existing = match state.queries.passkey_descriptors(claims.sub).await {
+        Ok(existing) => existing,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let challenge = crate::webauthn::generate_challenge();
+    let challenge_id = match state
+        .queries
+        .create_passkey_challenge(
+            Some(claims.sub),
+            REGISTRATION,
+            &challenge,
+            CHALLENGE_LIFETIME_SECS,
+        )
+        .await
+    {
+        Ok(id) => id,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let exclude_credentials: Vec<serde_json::Value> = existing
+        .iter()
+        .map(|descriptor| {
+            serde_json::json!({
+                "type": "public-key",
+                "id": crate::webauthn::encode(&descriptor.credential_id),
+                "transports": descriptor.transports,
+            })
+        })
+        .collect();
+
+    let rp = &state.relying_party;
+    (
+        axum::http::StatusCode::OK,
+        axum::response::Json(serde_json::json!({
+            "data": {
+                "challenge_id": challenge_id,
+                "public_key": {
+                    "challenge": crate::webauthn::encode(&challenge),
+                    "rp": {"id": rp.id, "name": rp.name},
+                    "user": {
+                        "id": crate::webauthn::encode(claims.sub.as_bytes()),
+                        "name": claims.email,
+                        "displayName": claims.account_name,
+                    },
+                    "pubKeyCredParams": [{"type": "public-key", "alg": crate::webauthn::ES256}],
+                    "timeout": CHALLENGE_LIFETIME_SECS * 1000,
+                    "attestation": "none",
+                    "excludeCredentials": exclude_credentials,
+                    "authenticatorSelection": {
+                        "residentKey": "required",
+                        "requireResidentKey": true,
+                        "userVerification": "preferred",
+                    },
+                },
+            }
+        })),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/me/passkeys",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    request_body = RegisterParams,
+    responses(
+        (status = 201, body = super::openapi::Data<crate::db::repositories::passkeys::Passkey>),
+        (status = 400, description = "The credential or challenge didn't check out", body = super::openapi::ErrorBody),
+        (status = 409, description = "The passkey is already registered", body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn register(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::Json(params): axum::Json<RegisterParams>,
+) -> axum::response::Response {
+    let name = match passkey_name(params.name.as_deref()) {
+        Ok(name) => name,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let challenge = match state
+        .queries
+        .take_passkey_challenge(params.challenge_id, REGISTRATION, CHALLENGE_LIFETIME_SECS)
+        .await
+    {
+        Ok(Some(challenge)) if challenge.account_id == Some(claims.sub) => challenge,
+        Ok(_) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(
+                    serde_json::json!({"error": "invalid or expired challenge, start again"}),
+                ),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let credential = match crate::webauthn::verify_registration(
+        &state.relying_party,
+        &challenge.challenge,
+        &params.credential,
+    ) {
+        Ok(credential) => credential,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    match state
+        .queries
+        .create_passkey(claims.sub, &name, &credential)
+        .await
+    {
+        Ok(CreatedPasskey::Created(passkey)) => (
+            axum::http::StatusCode::CREATED,
+            axum::response::Json(serde_json::json!({"data": passkey})),
+        )
+            .into_response(),
+        Ok(CreatedPasskey::AlreadyRegistered) => (
+            axum::http::StatusCode::CONFLICT,
+            axum::response::Json(serde_json::json!({"error": "passkey is already registered"})),
+        )
+            .into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me/passkeys",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    responses((status = 200, body = super::openapi::Data<Vec<crate::db::repositories::passkeys::Passkey>>))
+)]
+pub async fn list_passkeys(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+) -> impl axum::response::IntoResponse {
+    match state.queries.list_passkeys(claims.sub).await {
+        Ok(passkeys) => (
+            axum::http::StatusCode::OK,
+            axum::response::Json(serde_json::json!({"data": passkeys})),
+        ),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        ),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/me/passkeys/{passkey_id}",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    params(("passkey_id" = uuid::Uuid, Path)),
+    request_body = RenameParams,
+    responses(
+        (status = 200, body = super::openapi::Data<crate::db::repositories::passkeys::Passkey>),
+        (status = 400, body = super::openapi::ErrorBody),
+        (status = 404, body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn rename_passkey(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::extract::Path(passkey_id): axum::extract::Path<uuid::Uuid>,
+    axum::Json(params): axum::Json<RenameParams>,
+) -> impl axum::response::IntoResponse {
+    let name = match passkey_name(Some(&params.name)) {
+        Ok(name) => name,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(serde_json::json!({"error": err})),
+            );
+        }
+    };
+
+    match state
+        .queries
+        .rename_passkey(claims.sub, passkey_id, &name)
+        .await
+    {
+        Ok(Some(passkey)) => (
+            axum::http::StatusCode::OK,
+            axum::response::Json(serde_json::json!({"data": passkey})),
+        ),
+        Ok(None) => (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::response::Json(serde_json::json!({"error": "passkey not found"})),
+        ),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        ),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/me/passkeys/{passkey_id}",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    params(("passkey_id" = uuid::Uuid, Path)),
+    responses(
+        (status = 204, description = "The passkey was removed"),
+        (status = 404, body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn delete_passkey(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::extract::Path(passkey_id): axum::extract::Path<uuid::Uuid>,
+) -> axum::response::Response {
+    match state.queries.delete_passkey(claims.sub, passkey_id).await {
+        Ok(true) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::response::Json(serde_json::json!({"error": "passkey not found"})),
+        )
+            .into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
+
+/// Options for `navigator.credentials.get()`. No credentials are listed, the
+/// authenticator offers its discoverable ones, so no email is needed and
+/// nothing reveals which accounts exist.
+#[utoipa::path(
+    post,
+    path = "/api/login/passkey/options",
+    tag = "auth",
+    responses((status = 200, body = super::openapi::Data<super::openapi::PasskeyOptions>))
+)]
+pub async fn authentication_options(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl axum::response::IntoResponse {
+    let challenge = crate::webauthn::generate_challenge();
+    let challenge_id = match state
+        .queries
+        .create_passkey_challenge(None, AUTHENTICATION, &challenge, CHALLENGE_LIFETIME_SECS)
+        .await
+    {
+        Ok(id) => id,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            );
+        }
+    };
+
+    (
+        axum::http::StatusCode::OK,
+        axum::response::Json(serde_json::json!({
+            "data": {
+                "challenge_id": challenge_id,
+                "public_key": {
+                    "challenge": crate::webauthn::encode(&challenge),
+                    "rpId": state.relying_party.id,
+                    "timeout": CHALLENGE_LIFETIME_SECS * 1000,
+                    "userVerification": "preferred",
+                    "allowCredentials": [],
+                },
+            }
+        })),
+    )
+}
+
+/// Logs in with a passkey. Accounts with 2FA still get an MFA challenge
+/// unless the authenticator verified the user itself. Failures count against
+/// the credential's account and the IP address like password logins, or only
+/// the IP address when the credential isn't registered.
+#[utoipa::path(
+    post,
+    path = "/api/login/passkey",
+    tag = "auth",
+    request_body = LoginParams,
+    responses(
+        (status = 200, body = super::openapi::LoginResult),
+        (status = 401, body = super::openapi::ErrorBody),
+        (status = 429, description = "Too many failed logins", body = super::openapi::ErrorBody,
+            headers(("Retry-After" = i64, description = "Seconds until the next attempt"))),
+    )
+)]
+pub async fn login(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    headers: axum::http::HeaderMap,
+    axum::Json(params): axum::Json<LoginParams>,
+) -> axum::response::Response {
+    let stored = match crate::webauthn::decode(&params.credential.raw_id) {
+        Ok(credential_id) => state.queries.find_passkey_credential(&credential_id).await,
+        Err(_) => Ok(None),
+    };
+    let stored = match stored {
+        Ok(stored) => stored,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let ip_address = super::client_ip(&headers, peer, &state.trusted_proxies).to_string();
+    let email = stored.as_ref().map(|stored| stored.email.as_str());
+    let attempt = match super::accounts::start_login_attempt(&state, email, &ip_address).await {
+        Ok(attempt) => attempt,
+        Err(response) => return response,
+    };
+
+    let verified = match &stored {
+        Some(stored) => verify_assertion(&state, &params, stored).await,
+        None => {
+            tracing::debug!("passkey login with an unknown credential");
+            Ok(None)
+        }
+    };
+
+    let (account_id, user_verified) = match verified {
+        Ok(Some(verified)) => verified,
+        Ok(None) => {
+            super::accounts::settle_login_attempt(&state, attempt, "failure").await;
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                axum::response::Json(serde_json::json!({"error": "invalid passkey"})),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            super::accounts::cancel_login_attempt(&state, attempt).await;
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let account = match state.queries.me(account_id).await {
+        Ok(account) => account,
+        Err(err) => {
+            super::accounts::cancel_login_attempt(&state, attempt).await;
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    // The login only counts as successful once the second factor is in
+    if account.totp_enabled && !user_verified {
+        super::accounts::cancel_login_attempt(&state, attempt).await;
+        return super::accounts::mfa_challenge(&state, account);
+    }
+
+    super::accounts::settle_login_attempt(&state, attempt, "success").await;
+    super::accounts::session_response(&state, account)
+}
+
+/// Whether the assertion's user handle, when the authenticator sent one, is
+/// the account id given at registration.
+fn user_handle_matches(user_handle: Option<&str>, account_id: uuid::Uuid) -> bool {
+    match user_handle {
+        Some(user_handle) => {
+            crate::webauthn::decode(user_handle).ok().as_deref() == Some(account_id.as_bytes())
+        }
+        None => true,
+    }
+}
+
+/// The account the assertion logs in to and whether the user was verified,
+/// or None when the assertion doesn't check out. The reason is only logged.
+async fn verify_assertion(
+    state: &AppState,
+    params: &LoginParams,
+    stored: &PasskeyCredential,
+) -> Result<Option<(uuid::Uuid, bool)>, crate::db::repositories::Error> {
+    let Some(challenge) = state
+        .queries
+        .take_passkey_challenge(params.challenge_id, AUTHENTICATION, CHALLENGE_LIFETIME_SECS)
+        .await?
+    else {
+        tracing::debug!("passkey login with an unknown or expired challenge");
+        return Ok(None);
+    };
+
+    if !user_handle_matches(
+        params.credential.response.user_handle.as_deref(),
+        stored.account_id,
+    ) {
+        tracing::debug!("passkey login with a mismatched user handle");
+        return Ok(None);
+    }
+
+    let assertion = match crate::webauthn::verify_authentication(
+        &state.relying_party,
+        &challenge.challenge,
+        &crate::webauthn::StoredCredential {
+            public_key: &stored.public_key,
+            algorithm: stored.algorithm,
+            sign_count: stored.sign_count,
+        },
+        &params.credential,
+    ) {
+        Ok(assertion) => assertion,
+        Err(err) => {
+            tracing::debug!("passkey login rejected: {}", err);
+            return Ok(None);
+        }
+    };
+
+    if !state
+        .queries
+        .use_passkey(stored.id, stored.sign_count, assertion.sign_count)
+        .await?
+    {
+        tracing::debug!("passkey login raced another use of the same counter");
+        return Ok(None);
+    }
+
+    Ok(Some((stored.account_id, assertion.user_verified)))
+}
+
+fn passkey_name(name: Option<&str>) -> Result<String, String> {
+    let name = name.map(str::trim).unwrap_or_default();
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(format!(
+            "name can't be longer than {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+
+    Ok(if name.is_empty() {
+        DEFAULT_NAME.to_string()
+    } else {
+        name.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app, request, send};
+    use crate::webauthn::tests::SoftAuthenticator;
+    use axum::http::{Method, StatusCode};
+
+    fn registration_json(
+        credential: &crate::webauthn::RegistrationCredential,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "rawId": credential.raw_id,
+            "response": {
+                "clientDataJSON": credential.response.client_data_json,
+                "attestationObject": credential.response.attestation_object,
+                "transports": credential.response.transports,
+            },
+        })
+    }
+
+    fn assertion_json(credential: &crate::webauthn::AuthenticationCredential) -> serde_json::Value {
+        serde_json::json!({
+            "rawId": credential.raw_id,
+            "response": {
+                "clientDataJSON": credential.response.client_data_json,
+                "authenticatorData": credential.response.authenticator_data,
+                "signature": credential.response.signature,
+                "userHandle": credential.response.user_handle,
+            },
+        })
+    }
+
+    /// The challenge id and challenge from a `.../options` response.
+    fn challenge(options: &serde_json::Value) -> (String, Vec<u8>) {
+        let data = &options["data"];
+        (
+            data["challenge_id"].as_str().unwrap().to_string(),
+            crate::webauthn::decode(data["public_key"]["challenge"].as_str().unwrap()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn user_handle_must_be_the_account_id_when_sent() {
+        let account_id = uuid::Uuid::new_v4();
+        let handle = crate::webauthn::encode(account_id.as_bytes());
+
+        assert!(user_handle_matches(Some(&handle), account_id));
+        assert!(user_handle_matches(None, account_id));
+        assert!(!user_handle_matches(Some(&handle), uuid::Uuid::new_v4()));
+        assert!(!user_handle_matches(Some("not base64!"), account_id));
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn software_authenticator_registers_and_logs_in(pool: sqlx::PgPool) {
+        let config = crate::config::Config::default();
+        let rp = crate::webauthn::RelyingParty::from_base_url(&config.server.base_url).unwrap();
+        let app = app(pool.clone(), &config);
+
+        let account = crate::db::repositories::Queries::new(pool.clone())
+            .create_account(&crate::db::repositories::accounts::CreateAccountParams {
+                email: "passkey@example.com".to_string(),
+                password: "correct horse battery".to_string(),
+                account_name: "passkey".to_string(),
+            })
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap();
+        let (status, body) = send(
+            &app,
+            request(
+                Method::POST,
+                "/api/login",
+                None,
+                Some(serde_json::json!({
+                    "email": "passkey@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let token = body["token"].as_str().unwrap().to_string();
+
+        let mut authenticator = SoftAuthenticator::new();
+        let register = |options: serde_json::Value, authenticator: &SoftAuthenticator| {
+            let (challenge_id, challenge) = challenge(&options);
+            serde_json::json!({
+                "challenge_id": challenge_id,
+                "name": "Laptop",
+                "credential": registration_json(&authenticator.register(&rp.id, &rp.origin, &challenge)),
+            })
+        };
+
+        let (status, options) = send(
+            &app,
+            request(Method::POST, "/api/me/passkeys/options", Some(&token), None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            options["data"]["public_key"]["user"]["id"],
+            crate::webauthn::encode(account.id.as_bytes())
+        );
+        let (status, body) = send(
+            &app,
+            request(
+                Method::POST,
+                "/api/me/passkeys",
+                Some(&token),
+                Some(register(options, &authenticator)),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["data"]["name"], "Laptop");
+
+        // The same authenticator again is a conflict, not a server error
+        let (_, options) = send(
+            &app,
+            request(Method::POST, "/api/me/passkeys/options", Some(&token), None),
+        )
+        .await;
+        assert_eq!(
+            options["data"]["public_key"]["excludeCredentials"][0]["id"],
+            crate::webauthn::encode(&authenticator.credential_id)
+        );
+        let (status, _) = send(
+            &app,
+            request(
+                Method::POST,
+                "/api/me/passkeys",
+                Some(&token),
+                Some(register(options, &authenticator)),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let user_handle = account.id.as_bytes().to_vec();
+        let options = || async {
+            let (status, options) = send(
+                &app,
+                request(Method::POST, "/api/login/passkey/options", None, None),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            challenge(&options)
+        };
+        let login = |challenge_id: &str, assertion: &crate::webauthn::AuthenticationCredential| {
+            request(
+                Method::POST,
+                "/api/login/passkey",
+                None,
+                Some(serde_json::json!({
+                    "challenge_id": challenge_id,
+                    "credential": assertion_json(assertion),
+                })),
+            )
+        };
+
+        let (challenge_id, challenge) = options().await;
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, Some(&user_handle));
+        let (status, body) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["token"].is_string());
+
+        // A challenge answers one ceremony
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, Some(&user_handle));
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (challenge_id, challenge) = options().await;
+        sqlx::query("update passkey_challenges set created_at = now() - interval '1 hour'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, Some(&user_handle));
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // A success clears the account's failures
+        let (challenge_id, challenge) = options().await;
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, None);
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (challenge_id, challenge) = options().await;
+        let other_user = uuid::Uuid::new_v4();
+        let assertion =
+            authenticator.assert(&rp.id, &rp.origin, &challenge, Some(other_user.as_bytes()));
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (challenge_id, challenge) = options().await;
+        authenticator.sign_count = 0;
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, None);
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (challenge_id, challenge) = options().await;
+        let assertion = authenticator.assert(&rp.id, "https://evil.example.com", &challenge, None);
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Unregistered credentials only count against the IP address
+        let mut stranger = SoftAuthenticator::new();
+        stranger.credential_id = b"unregistered".to_vec();
+        let (challenge_id, challenge) = options().await;
+        let assertion = stranger.assert(&rp.id, &rp.origin, &challenge, None);
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let results: Vec<(Option<String>, String)> =
+            sqlx::query_as("select email, result from login_attempts order by created_at")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let account_email = Some("passkey@example.com".to_string());
+        assert_eq!(
+            results,
+            [
+                (account_email.clone(), "success".to_string()),
+                (account_email.clone(), "success".to_string()),
+                (account_email.clone(), "failure".to_string()),
+                (account_email.clone(), "failure".to_string()),
+                (account_email.clone(), "success".to_string()),
+                (account_email.clone(), "failure".to_string()),
+                (account_email.clone(), "failure".to_string()),
+                (account_email.clone(), "failure".to_string()),
+                (None, "failure".to_string()),
+            ]
+        );
+
+        // Three failures since the last success, the account has to wait
+        let (challenge_id, challenge) = options().await;
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, None);
+        let (status, _) = send(&app, login(&challenge_id, &assertion)).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -1,6 +1,25 @@
 use super::AppState;
+use super::media::with_srcset;
 use crate::db::repositories::utils::RawListParams;
 
+#[utoipa::path(
+    get,
+    path = "/api/posts",
+    tag = "posts",
+    params(
+        ("page" = Option<i32>, Query, description = "Starts at 1"),
+        ("page_size" = Option<i32>, Query, description = "Defaults to 10"),
+        ("filters" = Option<std::collections::HashMap<String, String>>, Query, style = DeepObject,
+            description = "Substring matches, e.g. `filters[account_name]=ann`, on `account_name` or `contents`, \
+                or `filters[account_id]` for one author's posts"),
+        ("sorts" = Option<std::collections::HashMap<String, String>>, Query, style = DeepObject,
+            description = "`sorts[account_name]=asc` or `desc`"),
+    ),
+    responses(
+        (status = 200, body = crate::db::repositories::posts::PostsList),
+        (status = 500, body = super::openapi::ErrorBody),
+    )
+)]
 pub async fn list_posts(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Query(params): axum::extract::Query<RawListParams>,
@@ -21,6 +40,16 @@ pub async fn list_posts(
     )
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/posts/{post_id}",
+    tag = "posts",
+    params(("post_id" = uuid::Uuid, Path)),
+    responses(
+        (status = 200, body = super::openapi::Data<crate::db::repositories::posts::Post>),
+        (status = 400, description = "The id isn't a UUID", body = super::openapi::ErrorBody),
+    )
+)]
 pub async fn get_post(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(post_id): axum::extract::Path<String>,
@@ -51,13 +80,34 @@ pub async fn get_post(
     )
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/posts",
+    tag = "posts",
+    security(("bearer" = ["posts:write"]), ("cookie" = [])),
+    request_body = crate::db::repositories::posts::CreatePostParams,
+    responses(
+        (status = 201, body = super::openapi::Data<crate::db::repositories::posts::Post>),
+        (status = 400, body = super::openapi::ErrorBody),
+        (status = 413, description = "Over `server.post_body_limit_bytes`", body = super::openapi::ErrorBody),
+    )
+)]
 pub async fn create_post(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Extension(claims): axum::extract::Extension<crate::routes::accounts::Claims>,
     axum::Json(params): axum::Json<crate::db::repositories::posts::CreatePostParams>,
 ) -> impl axum::response::IntoResponse {
-    let post = match state.queries.create_post(claims.sub, params).await {
-        Ok(post) => post,
+    let rendered_contents = with_srcset(&params.content, &state.image_variants.widths);
+    let media_ids = super::media::referenced_media(&params.content);
+    let post = match state
+        .queries
+        .create_post(claims.sub, params, &rendered_contents, &media_ids)
+        .await
+    {
+        Ok(post) => {
+            metrics::counter!("posts_created_total").increment(1);
+            post
+        }
         Err(err) => {
             return (
                 axum::http::StatusCode::BAD_REQUEST,
@@ -72,13 +122,31 @@ pub async fn create_post(
     )
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/posts/{post_id}",
+    tag = "posts",
+    security(("bearer" = ["posts:write"]), ("cookie" = [])),
+    params(("post_id" = uuid::Uuid, Path)),
+    request_body = crate::db::repositories::posts::UpdatePostParams,
+    responses(
+        (status = 200, body = super::openapi::Data<crate::db::repositories::posts::Post>),
+        (status = 413, description = "Over `server.post_body_limit_bytes`", body = super::openapi::ErrorBody),
+    )
+)]
 pub async fn update_post(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Extension(claims): axum::extract::Extension<crate::routes::accounts::Claims>,
     axum::extract::Path(post_id): axum::extract::Path<uuid::Uuid>,
     axum::Json(params): axum::Json<crate::db::repositories::posts::UpdatePostParams>,
 ) -> impl axum::response::IntoResponse {
-    let post = match state.queries.update_post(claims.sub, post_id, params).await {
+    let rendered_contents = with_srcset(&params.contents, &state.image_variants.widths);
+    let media_ids = super::media::referenced_media(&params.contents);
+    let post = match state
+        .queries
+        .update_post(claims.sub, post_id, params, &rendered_contents, &media_ids)
+        .await
+    {
         Ok(post) => post,
         Err(err) => {
             return (
@@ -94,6 +162,14 @@ pub async fn update_post(
     )
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/posts/{post_id}",
+    tag = "posts",
+    security(("bearer" = ["posts:write"]), ("cookie" = [])),
+    params(("post_id" = uuid::Uuid, Path)),
+    responses((status = 204, description = "The post was deleted"))
+)]
 pub async fn delete_post(
     axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Extension(claims): axum::extract::Extension<crate::routes::accounts::Claims>,
@@ -0,0 +1,379 @@
+use axum::http::HeaderValue;
+
+const NONCE_BYTES: usize = 16;
+
+/// The nonce scripts in the SPA index must carry for this request. Added by
+/// `security_headers`, which puts the same one in the policy.
+#[derive(Clone)]
+pub struct CspNonce(pub String);
+
+/// The headers from `[security]`, worked out once.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    csp_header: Option<axum::http::HeaderName>,
+    frame_ancestors: String,
+    deny_framing: bool,
+    hsts: Option<HeaderValue>,
+    referrer_policy: Option<HeaderValue>,
+    permissions_policy: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        let security = &config.security;
+
+        let csp_header = security
+            .content_security_policy
+            .then_some(if security.csp_report_only {
+                axum::http::header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+            } else {
+                axum::http::header::CONTENT_SECURITY_POLICY
+            });
+
+        let frame_ancestors = if security.frame_ancestors.is_empty() {
+            "'none'".to_string()
+        } else {
+            security.frame_ancestors.join(" ")
+        };
+
+        // Browsers ignore HSTS over plain http, and it would pin localhost
+        let hsts = (config.server.base_url.starts_with("https://")
+            && security.hsts_max_age_secs > 0)
+            .then(|| {
+                let subdomains = if security.hsts_include_subdomains {
+                    "; includeSubDomains"
+                } else {
+                    ""
+                };
+                HeaderValue::from_str(&format!(
+                    "max-age={}{}",
+                    security.hsts_max_age_secs, subdomains
+                ))
+                .expect("hsts header is valid")
+            });
+
+        let optional = |value: &str| {
+            (!value.is_empty())
+                .then(|| HeaderValue::from_str(value).expect("validated with the config"))
+        };
+
+        SecurityHeaders {
+            csp_header,
+            frame_ancestors,
+            deny_framing: security.frame_ancestors.is_empty(),
+            hsts,
+            referrer_policy: optional(&security.referrer_policy),
+            permissions_policy: optional(&security.permissions_policy),
+        }
+    }
+
+    fn policy(&self, html: bool, nonce: &str) -> String {
+        if html {
+            format!(
+                "default-src 'self'; script-src 'self' 'nonce-{}'; style-src 'self' 'unsafe-inline'; \
+                 img-src 'self' data: blob: https:; font-src 'self' data:; connect-src 'self'; \
+                 object-src 'none'; base-uri 'self'; form-action 'self'; frame-ancestors {}",
+                nonce, self.frame_ancestors
+            )
+        } else {
+            // API responses and media are never rendered as documents, so
+            // they get nothing, which also defuses uploaded SVG and HTML
+            format!(
+                "default-src 'none'; sandbox; frame-ancestors {}",
+                self.frame_ancestors
+            )
+        }
+    }
+}
+
+/// Adds the security headers to every response. Handlers that set one
+/// themselves keep theirs.
+pub async fn security_headers(
+    axum::extract::State(config): axum::extract::State<SecurityHeaders>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let nonce = generate_nonce();
+    request.extensions_mut().insert(CspNonce(nonce.clone()));
+
+    let mut response = next.run(request).await;
+
+    let is_html = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let headers = response.headers_mut();
+
+    if let Some(name) = &config.csp_header
+        && let Ok(policy) = HeaderValue::from_str(&config.policy(is_html, &nonce))
+    {
+        headers.entry(name).or_insert(policy);
+    }
+    if config.deny_framing {
+        headers
+            .entry(axum::http::header::X_FRAME_OPTIONS)
+            .or_insert(HeaderValue::from_static("DENY"));
+    }
+    headers
+        .entry(axum::http::header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    if let Some(hsts) = &config.hsts {
+        headers
+            .entry(axum::http::header::STRICT_TRANSPORT_SECURITY)
+            .or_insert(hsts.clone());
+    }
+    if let Some(referrer_policy) = &config.referrer_policy {
+        headers
+            .entry(axum::http::header::REFERRER_POLICY)
+            .or_insert(referrer_policy.clone());
+    }
+    if let Some(permissions_policy) = &config.permissions_policy {
+        headers
+            .entry("permissions-policy")
+            .or_insert(permissions_policy.clone());
+    }
+
+    response
+}
+
+/// Lets the scripts of the index run under the nonce policy.
+pub fn add_script_nonce(html: &str, nonce: &str) -> String {
+    html.replace("<script", &format!("<script nonce=\"{}\"", nonce))
+}
+
+/// Only these origins may call the API from a browser, with cookies. No
+/// layer at all when none are configured, which keeps the API same-origin.
+pub fn cors_layer(config: &crate::config::CorsConfig) -> Option<tower_http::cors::CorsLayer> {
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
+
+    let origins: Vec<HeaderValue> = config
+        .allowed_origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin.trim_end_matches('/')).expect("validated with the config")
+        })
+        .collect();
+
+    Some(
+        tower_http::cors::CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods([
+                axum::http::Method::GET,
+                axum::http::Method::POST,
+                axum::http::Method::PUT,
+                axum::http::Method::PATCH,
+                axum::http::Method::DELETE,
+            ])
+            .allow_headers([
+                axum::http::header::AUTHORIZATION,
+                axum::http::header::CONTENT_TYPE,
+                axum::http::HeaderName::from_static("x-csrf-token"),
+                axum::http::HeaderName::from_static(super::X_REQUEST_ID),
+            ])
+            .expose_headers([
+                axum::http::header::RETRY_AFTER,
+                axum::http::HeaderName::from_static(super::X_REQUEST_ID),
+            ])
+            .allow_credentials(true)
+            .max_age(std::time::Duration::from_secs(600)),
+    )
+}
+
+fn generate_nonce() -> String {
+    use rand::Rng;
+
+    let mut bytes = [0u8; NONCE_BYTES];
+    rand::rng().fill(&mut bytes);
+    data_encoding::BASE64.encode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::tests::{app, lazy_pool};
+    use axum::http::{Method, header};
+
+    async fn send(
+        app: &axum::Router,
+        method: Method,
+        uri: &str,
+        headers: &[(header::HeaderName, &str)],
+    ) -> (axum::http::HeaderMap, String) {
+        let mut request = axum::http::Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        let response = tower::ServiceExt::oneshot(
+            app.clone(),
+            request.body(axum::body::Body::empty()).unwrap(),
+        )
+        .await
+        .unwrap();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (headers, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn header(headers: &axum::http::HeaderMap, name: header::HeaderName) -> Option<&str> {
+        headers.get(name).map(|value| value.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn the_index_scripts_carry_the_nonce_of_the_policy() {
+        let app = app(lazy_pool(), &crate::config::Config::default());
+
+        let mut nonces = Vec::new();
+        for _ in 0..2 {
+            let (headers, body) = send(&app, Method::GET, "/", &[]).await;
+            let policy = header(&headers, header::CONTENT_SECURITY_POLICY).unwrap();
+            let nonce = policy
+                .split_once("'nonce-")
+                .and_then(|(_, rest)| rest.split_once('\''))
+                .map(|(nonce, _)| nonce.to_string())
+                .unwrap();
+
+            assert!(policy.starts_with("default-src 'self';"), "{}", policy);
+            assert!(policy.ends_with("frame-ancestors 'none'"), "{}", policy);
+            assert!(
+                body.contains(&format!(r#"<script nonce="{}" type="module""#, nonce)),
+                "{}",
+                body
+            );
+            assert!(!body.contains("<script type"), "{}", body);
+            nonces.push(nonce);
+        }
+
+        assert_ne!(nonces[0], nonces[1]);
+    }
+
+    #[tokio::test]
+    async fn other_responses_get_the_sandbox_policy() {
+        let enforced = app(lazy_pool(), &crate::config::Config::default());
+
+        for uri in ["/robots.txt", "/api/me", "/favicon.ico"] {
+            let (headers, _) = send(&enforced, Method::GET, uri, &[]).await;
+            assert_eq!(
+                header(&headers, header::CONTENT_SECURITY_POLICY),
+                Some("default-src 'none'; sandbox; frame-ancestors 'none'"),
+                "{}",
+                uri
+            );
+            assert_eq!(header(&headers, header::X_FRAME_OPTIONS), Some("DENY"));
+            assert_eq!(
+                header(&headers, header::X_CONTENT_TYPE_OPTIONS),
+                Some("nosniff")
+            );
+        }
+
+        let mut config = crate::config::Config::default();
+        config.security.csp_report_only = true;
+        config.security.frame_ancestors = vec!["'self'".to_string()];
+        let report_only = app(lazy_pool(), &config);
+
+        let (headers, _) = send(&report_only, Method::GET, "/robots.txt", &[]).await;
+        assert_eq!(header(&headers, header::CONTENT_SECURITY_POLICY), None);
+        assert_eq!(
+            header(&headers, header::CONTENT_SECURITY_POLICY_REPORT_ONLY),
+            Some("default-src 'none'; sandbox; frame-ancestors 'self'")
+        );
+        assert_eq!(header(&headers, header::X_FRAME_OPTIONS), None);
+    }
+
+    #[tokio::test]
+    async fn hsts_is_only_sent_over_https() {
+        let cases = [
+            ("http://localhost:3000", 31536000, false, None),
+            (
+                "https://example.com",
+                31536000,
+                false,
+                Some("max-age=31536000"),
+            ),
+            (
+                "https://example.com",
+                600,
+                true,
+                Some("max-age=600; includeSubDomains"),
+            ),
+            ("https://example.com", 0, false, None),
+        ];
+
+        for (base_url, max_age_secs, include_subdomains, expected) in cases {
+            let mut config = crate::config::Config::default();
+            config.server.base_url = base_url.to_string();
+            config.security.hsts_max_age_secs = max_age_secs;
+            config.security.hsts_include_subdomains = include_subdomains;
+            let app = app(lazy_pool(), &config);
+
+            let (headers, _) = send(&app, Method::GET, "/robots.txt", &[]).await;
+            assert_eq!(
+                header(&headers, header::STRICT_TRANSPORT_SECURITY),
+                expected,
+                "{} {}",
+                base_url,
+                max_age_secs
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn cors_only_answers_allowed_origins() {
+        let preflight = |origin: &'static str| {
+            [
+                (header::ORIGIN, origin),
+                (header::ACCESS_CONTROL_REQUEST_METHOD, "POST"),
+                (header::ACCESS_CONTROL_REQUEST_HEADERS, "x-csrf-token"),
+            ]
+        };
+
+        let mut config = crate::config::Config::default();
+        config.cors.allowed_origins = vec!["https://admin.example.com/".to_string()];
+        let allow_listed = app(lazy_pool(), &config);
+
+        let (headers, _) = send(
+            &allow_listed,
+            Method::OPTIONS,
+            "/api/posts",
+            &preflight("https://admin.example.com"),
+        )
+        .await;
+        assert_eq!(
+            header(&headers, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("https://admin.example.com")
+        );
+        assert_eq!(
+            header(&headers, header::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            Some("true")
+        );
+        assert!(
+            header(&headers, header::ACCESS_CONTROL_ALLOW_HEADERS)
+                .unwrap()
+                .contains("x-csrf-token")
+        );
+
+        let (headers, _) = send(
+            &allow_listed,
+            Method::OPTIONS,
+            "/api/posts",
+            &preflight("https://evil.example.com"),
+        )
+        .await;
+        assert_eq!(header(&headers, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+
+        // Without an allow-list the API stays same-origin
+        let same_origin = app(lazy_pool(), &crate::config::Config::default());
+        let (headers, _) = send(
+            &same_origin,
+            Method::GET,
+            "/robots.txt",
+            &[(header::ORIGIN, "https://admin.example.com")],
+        )
+        .await;
+        assert_eq!(header(&headers, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+    }
+}
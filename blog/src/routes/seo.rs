@@ -0,0 +1,794 @@
+use super::AppState;
+use axum::response::IntoResponse;
+
+// Sitemap protocol limit on the number of URLs in a single file
+const SITEMAP_MAX_URLS: i64 = 50_000;
+
+#[derive(serde::Deserialize)]
+pub struct SitemapParams {
+    pub page: Option<i64>,
+}
+
+pub async fn sitemap(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<SitemapParams>,
+) -> axum::response::Response {
+    let total = match state.queries.sitemap_total().await {
+        Ok(total) => total,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({ "error": err })),
+            )
+                .into_response();
+        }
+    };
+
+    // The home page is always the first URL
+    let total = total + 1;
+    let page_total = (total + SITEMAP_MAX_URLS - 1) / SITEMAP_MAX_URLS;
+
+    let page = match params.page {
+        None if page_total > 1 => {
+            return xml_response(sitemap_index(&state.base_url, page_total));
+        }
+        None => 1,
+        Some(page) if page >= 1 && page <= page_total => page,
+        Some(_) => {
+            return (
+                axum::http::StatusCode::NOT_FOUND,
+                axum::response::Json(serde_json::json!({ "error": "sitemap page not found" })),
+            )
+                .into_response();
+        }
+    };
+
+    let start = (page - 1) * SITEMAP_MAX_URLS;
+    let end = (start + SITEMAP_MAX_URLS).min(total);
+    let offset = (start - 1).max(0);
+    let limit = end - 1 - offset;
+
+    let entries = match state.queries.sitemap_entries(offset, limit).await {
+        Ok(entries) => entries,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({ "error": err })),
+            )
+                .into_response();
+        }
+    };
+
+    let base_url = xml_escape(&state.base_url);
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#,
+    );
+
+    if start == 0 {
+        xml.push_str(&format!(
+            "  <url>\n    <loc>{}/</loc>\n  </url>\n",
+            base_url
+        ));
+    }
+
+    for entry in entries {
+        let path = match entry.kind.as_str() {
+            "author" => author_path(entry.id),
+            _ => post_path(entry.id),
+        };
+        xml.push_str(&format!(
+            "  <url>\n    <loc>{}{}</loc>\n    <lastmod>{}</lastmod>\n  </url>\n",
+            base_url,
+            path,
+            entry
+                .updated_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        ));
+    }
+
+    xml.push_str("</urlset>\n");
+
+    xml_response(xml)
+}
+
+pub async fn robots(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl axum::response::IntoResponse {
+    let body = format!(
+        "# https://www.robotstxt.org/robotstxt.html\nUser-agent: *\nDisallow:\n\nSitemap: {}/sitemap.xml\n",
+        state.base_url
+    );
+
+    (
+        axum::http::StatusCode::OK,
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; charset=utf-8",
+        )],
+        body,
+    )
+}
+
+/// Where the SPA shows a post.
+pub fn post_path(post_id: uuid::Uuid) -> String {
+    format!("/posts/{}", post_id)
+}
+
+/// Where the SPA lists an author's posts.
+pub fn author_path(account_id: uuid::Uuid) -> String {
+    format!("/authors/{}", account_id)
+}
+
+fn sitemap_index(base_url: &str, page_total: i64) -> String {
+    let base_url = xml_escape(base_url);
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#,
+    );
+
+    for page in 1..=page_total {
+        xml.push_str(&format!(
+            "  <sitemap>\n    <loc>{}/sitemap.xml?page={}</loc>\n  </sitemap>\n",
+            base_url, page
+        ));
+    }
+
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+fn xml_response(xml: String) -> axum::response::Response {
+    (
+        axum::http::StatusCode::OK,
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "application/xml; charset=utf-8",
+        )],
+        xml,
+    )
+        .into_response()
+}
+
+pub(super) fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Lengths search engines and link previews display without truncation
+const TITLE_MAX_CHARS: usize = 70;
+const DESCRIPTION_MAX_CHARS: usize = 160;
+
+/// Renders the SPA index for `path`, injecting the post's meta tags when the
+/// path is a post page. Unknown posts are served with a 404 status.
+pub async fn render_index(
+    state: &AppState,
+    path: &str,
+    index: std::borrow::Cow<'_, str>,
+) -> (axum::http::StatusCode, String) {
+    let post_id = match path
+        .trim_end_matches('/')
+        .strip_prefix("posts/")
+        .and_then(|post_id| sqlx::types::Uuid::parse_str(post_id).ok())
+    {
+        Some(post_id) => post_id,
+        None => return (axum::http::StatusCode::OK, index.into_owned()),
+    };
+
+    match state.queries.find_post(post_id).await {
+        Ok(Some(post)) => (
+            axum::http::StatusCode::OK,
+            inject_post_meta(&index, &state.base_url, &post, state.features.og_images),
+        ),
+        Ok(None) => (axum::http::StatusCode::NOT_FOUND, index.into_owned()),
+        Err(_) => (axum::http::StatusCode::OK, index.into_owned()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/posts/{post_id}/og.png",
+    tag = "posts",
+    params(("post_id" = uuid::Uuid, Path)),
+    responses(
+        (status = 200, description = "The post's social preview card", content_type = "image/png"),
+        (status = 404, body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn og_image(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(post_id): axum::extract::Path<uuid::Uuid>,
+) -> axum::response::Response {
+    let card = match state.queries.get_post_card(post_id).await {
+        Ok(Some(card)) => card,
+        Ok(None) => {
+            return (
+                axum::http::StatusCode::NOT_FOUND,
+                axum::response::Json(serde_json::json!({ "error": "post not found" })),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({ "error": err })),
+            )
+                .into_response();
+        }
+    };
+
+    // Any edit to the post or its author bumps updated_at, which gives the
+    // post a new cache entry
+    let cache_prefix = format!("{}-", card.id);
+    let cache_name = format!(
+        "{}v{}-{}.png",
+        cache_prefix,
+        crate::og::CARD_VERSION,
+        card.updated_at.timestamp_micros()
+    );
+    let cache_path = state.og_cache_dir.join(&cache_name);
+
+    if let Ok(png) = tokio::fs::read(&cache_path).await {
+        return png_response(png);
+    }
+
+    let photo = match card.photo_hash {
+        Some(key) => match state.storage.get(&key).await {
+            Ok(photo) => photo,
+            Err(err) => {
+                tracing::warn!("can't read author photo: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let (title, excerpt) = post_summary(&card.contents);
+    let site_host = state
+        .base_url
+        .split_once("://")
+        .map(|(_, host)| host)
+        .unwrap_or(&state.base_url)
+        .to_string();
+
+    let rendered = crate::og::render(crate::og::Card {
+        title,
+        excerpt,
+        author: card.account_name,
+        photo,
+        site_name: site_name(),
+        site_host,
+    })
+    .await;
+
+    let png = match rendered {
+        Ok(png) => png,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({ "error": err })),
+            )
+                .into_response();
+        }
+    };
+
+    if let Err(err) = write_og_cache(&state.og_cache_dir, &cache_prefix, &cache_name, &png).await {
+        tracing::warn!("can't write og image cache: {}", err);
+    }
+
+    png_response(png)
+}
+
+/// Writes the rendered card and removes older versions of the same post.
+async fn write_og_cache(
+    dir: &std::path::Path,
+    prefix: &str,
+    name: &str,
+    png: &[u8],
+) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+
+    // Write to a temporary file first so concurrent readers never see a partial image
+    let temp_path = dir.join(format!("{}.{}.tmp", name, uuid::Uuid::new_v4()));
+    tokio::fs::write(&temp_path, png).await?;
+    tokio::fs::rename(&temp_path, dir.join(name)).await?;
+
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.starts_with(prefix) && file_name.ends_with(".png") && file_name != name {
+            tokio::fs::remove_file(entry.path()).await.ok();
+        }
+    }
+
+    Ok(())
+}
+
+fn png_response(png: Vec<u8>) -> axum::response::Response {
+    (
+        axum::http::StatusCode::OK,
+        [
+            (axum::http::header::CONTENT_TYPE, "image/png"),
+            (axum::http::header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        png,
+    )
+        .into_response()
+}
+
+/// The site name is the `<title>` of the embedded index.
+fn site_name() -> String {
+    super::frontend::ClientAssets::get("index.html")
+        .and_then(|index| tag_contents(&String::from_utf8_lossy(&index.data), "title"))
+        .unwrap_or_default()
+}
+
+fn inject_post_meta(
+    index: &str,
+    base_url: &str,
+    post: &crate::db::repositories::posts::Post,
+    og_images: bool,
+) -> String {
+    let (title, description) = post_summary(&post.contents);
+    let site_name = tag_contents(index, "title").unwrap_or_default();
+    let url = format!("{}{}", base_url, post_path(post.id));
+    let image = format!("{}/api/posts/{}/og.png", base_url, post.id);
+
+    let title = xml_escape(&title);
+    let description = xml_escape(&description);
+    let site_name = xml_escape(&site_name);
+    let url = xml_escape(&url);
+    let image = xml_escape(&image);
+    let author = xml_escape(&post.account_name);
+
+    let meta = format!(
+        r#"<title>{title} | {site_name}</title>
+    <meta name="description" content="{description}" />
+    <meta name="author" content="{author}" />
+    <link rel="canonical" href="{url}" />
+    <meta property="og:type" content="article" />
+    <meta property="og:site_name" content="{site_name}" />
+    <meta property="og:title" content="{title}" />
+    <meta property="og:description" content="{description}" />
+    <meta property="og:url" content="{url}" />
+    <meta name="twitter:title" content="{title}" />
+    <meta name="twitter:description" content="{description}" />"#,
+    );
+
+    let meta = if og_images {
+        format!(
+            r#"{meta}
+    <meta property="og:image" content="{image}" />
+    <meta property="og:image:type" content="image/png" />
+    <meta property="og:image:width" content="{width}" />
+    <meta property="og:image:height" content="{height}" />
+    <meta name="twitter:card" content="summary_large_image" />
+    <meta name="twitter:image" content="{image}" />"#,
+            width = crate::og::WIDTH,
+            height = crate::og::HEIGHT,
+        )
+    } else {
+        format!(
+            r#"{meta}
+    <meta name="twitter:card" content="summary" />"#
+        )
+    };
+
+    let html = remove_tag(index, "<title>", "</title>");
+    let html = remove_tag(&html, "<meta", r#"name="description""#);
+
+    match html.find("</head>") {
+        Some(position) => format!("{}  {}\n  {}", &html[..position], meta, &html[position..]),
+        None => html,
+    }
+}
+
+/// Takes the first line of text as the title and the rest as the description.
+fn post_summary(contents: &str) -> (String, String) {
+    let text = html_to_text(contents);
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+    let title = lines.next().unwrap_or_default().to_string();
+    let rest = lines.collect::<Vec<_>>().join(" ");
+    let description = if rest.is_empty() { title.clone() } else { rest };
+
+    (
+        truncate(&title, TITLE_MAX_CHARS),
+        truncate(&description, DESCRIPTION_MAX_CHARS),
+    )
+}
+
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut tag = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                if matches!(
+                    name.as_str(),
+                    "p" | "br"
+                        | "div"
+                        | "li"
+                        | "blockquote"
+                        | "pre"
+                        | "h1"
+                        | "h2"
+                        | "h3"
+                        | "h4"
+                        | "h5"
+                        | "h6"
+                ) {
+                    text.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => text.push(c),
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    if value.chars().count() <= max_chars {
+        return value.to_string();
+    }
+
+    let truncated: String = value.chars().take(max_chars - 1).collect();
+    format!("{}…", truncated.trim_end())
+}
+
+fn tag_contents(html: &str, tag: &str) -> Option<String> {
+    let start = html.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + html[start..].find(&format!("</{}>", tag))?;
+    Some(html[start..end].trim().to_string())
+}
+
+/// Removes the first element that starts with `open` and contains `marker`,
+/// up to and including `marker`'s closing `>` (or `marker` itself when it is
+/// a closing tag).
+fn remove_tag(html: &str, open: &str, marker: &str) -> String {
+    let Some(marker_start) = html.find(marker) else {
+        return html.to_string();
+    };
+    let Some(start) = html[..marker_start].rfind(open) else {
+        return html.to_string();
+    };
+    let end = if marker.ends_with('>') {
+        marker_start + marker.len()
+    } else {
+        match html[marker_start..].find('>') {
+            Some(offset) => marker_start + offset + 1,
+            None => return html.to_string(),
+        }
+    };
+
+    let before = html[..start].trim_end_matches([' ', '\t']);
+    let after = html[end..].strip_prefix('\n').unwrap_or(&html[end..]);
+    format!("{}{}", before, after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app, lazy_pool};
+
+    async fn get(app: &axum::Router, uri: &str) -> (axum::http::StatusCode, String, String) {
+        let request = axum::http::Request::builder()
+            .uri(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(app.clone(), request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response.headers()[axum::http::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (
+            status,
+            content_type,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    async fn create_account(pool: &sqlx::PgPool, name: &str) -> uuid::Uuid {
+        crate::db::repositories::Queries::new(pool.clone())
+            .create_account(&crate::db::repositories::accounts::CreateAccountParams {
+                email: format!("{}@example.com", name),
+                password: "correct horse battery".to_string(),
+                account_name: name.to_string(),
+            })
+            .await
+            .map_err(|err| err.to_string())
+            .unwrap()
+            .id
+    }
+
+    #[tokio::test]
+    async fn robots_points_at_the_sitemap() {
+        let mut config = crate::config::Config::default();
+        config.server.base_url = "https://blog.example.com".to_string();
+        let app = app(lazy_pool(), &config);
+
+        let (status, content_type, body) = get(&app, "/robots.txt").await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(content_type, "text/plain; charset=utf-8");
+        assert_eq!(
+            body,
+            "# https://www.robotstxt.org/robotstxt.html\nUser-agent: *\nDisallow:\n\n\
+             Sitemap: https://blog.example.com/sitemap.xml\n"
+        );
+    }
+
+    #[test]
+    fn sitemap_index_lists_every_page() {
+        assert_eq!(
+            sitemap_index("https://blog.example.com/?a&b", 2),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap>
+    <loc>https://blog.example.com/?a&amp;b/sitemap.xml?page=1</loc>
+  </sitemap>
+  <sitemap>
+    <loc>https://blog.example.com/?a&amp;b/sitemap.xml?page=2</loc>
+  </sitemap>
+</sitemapindex>
+"#
+        );
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn sitemap_lists_posts_and_authors(pool: sqlx::PgPool) {
+        let author = create_account(&pool, "author").await;
+        create_account(&pool, "reader").await;
+        let post: uuid::Uuid = sqlx::query_scalar(
+            "insert into posts (account_id, contents, rendered_contents)
+            values ($1, '<p>Hello</p>', '<p>Hello</p>')
+            returning id",
+        )
+        .bind(author)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "insert into posts (account_id, contents, rendered_contents, deleted_at)
+            values ($1, '<p>Gone</p>', '<p>Gone</p>', now())",
+        )
+        .bind(author)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut config = crate::config::Config::default();
+        config.server.base_url = "https://blog.example.com".to_string();
+        let app = app(pool, &config);
+
+        let (status, content_type, body) = get(&app, "/sitemap.xml").await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(content_type, "application/xml; charset=utf-8");
+        assert!(body.contains("<urlset"), "{}", body);
+
+        // The home page, the post and its author, not the deleted post or an
+        // account without posts
+        let locs: Vec<_> = body
+            .split("<loc>")
+            .skip(1)
+            .map(|loc| loc.split("</loc>").next().unwrap())
+            .collect();
+        assert_eq!(
+            locs,
+            [
+                "https://blog.example.com/".to_string(),
+                format!("https://blog.example.com/posts/{}", post),
+                format!("https://blog.example.com/authors/{}", author),
+            ]
+        );
+        assert_eq!(body.matches("<lastmod>").count(), 2);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn sitemap_switches_to_an_index_past_the_url_limit(pool: sqlx::PgPool) {
+        let author = create_account(&pool, "prolific").await;
+        let app = app(pool.clone(), &crate::config::Config::default());
+        let base_url = crate::config::Config::default().server.base_url;
+
+        // With the home page and the author this is exactly the limit
+        let insert_posts = |count: i64| {
+            sqlx::query(
+                "insert into posts (account_id, contents, rendered_contents)
+                select $1, '<p>Post</p>', '<p>Post</p>' from generate_series(1, $2)",
+            )
+            .bind(author)
+            .bind(count)
+            .execute(&pool)
+        };
+        insert_posts(SITEMAP_MAX_URLS - 2).await.unwrap();
+
+        let (_, _, body) = get(&app, "/sitemap.xml").await;
+        assert!(body.contains("<urlset"));
+        assert_eq!(body.matches("<url>").count() as i64, SITEMAP_MAX_URLS);
+
+        insert_posts(1).await.unwrap();
+
+        let (status, _, body) = get(&app, "/sitemap.xml").await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body, sitemap_index(&base_url, 2));
+
+        let (_, _, first) = get(&app, "/sitemap.xml?page=1").await;
+        assert_eq!(first.matches("<url>").count() as i64, SITEMAP_MAX_URLS);
+        assert!(first.contains(&format!("<loc>{}/</loc>", base_url)));
+
+        // The author comes after the posts
+        let (_, _, second) = get(&app, "/sitemap.xml?page=2").await;
+        assert_eq!(second.matches("<url>").count(), 1);
+        assert!(second.contains(&format!("<loc>{}/authors/{}</loc>", base_url, author)));
+
+        let (status, _, _) = get(&app, "/sitemap.xml?page=3").await;
+        assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn post_meta_escapes_the_post_and_author() {
+        let post = crate::db::repositories::posts::Post {
+            id: uuid::Uuid::nil(),
+            account_id: uuid::Uuid::nil(),
+            account_name: r#""><img src=x onerror=alert(1)>"#.to_string(),
+            contents: r#"<p>&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;</p><p>Tom's "quote" &amp; more</p>"#
+                .to_string(),
+            rendered_contents: String::new(),
+        };
+        let index = "<html>\n  <head>\n    <title>Site</title>\n  </head>\n</html>";
+
+        let html = inject_post_meta(index, "https://blog.example.com", &post, true);
+
+        let cases = [
+            r#"<title>&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt; | Site</title>"#,
+            r#"<meta property="og:title" content="&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;" />"#,
+            r#"<meta name="description" content="Tom&apos;s &quot;quote&quot; &amp; more" />"#,
+            r#"<meta name="author" content="&quot;&gt;&lt;img src=x onerror=alert(1)&gt;" />"#,
+            r#"<link rel="canonical" href="https://blog.example.com/posts/00000000-0000-0000-0000-000000000000" />"#,
+        ];
+        for expected in cases {
+            assert!(html.contains(expected), "{} in {}", expected, html);
+        }
+        assert!(!html.contains("<script>"), "{}", html);
+        assert!(!html.contains("<img"), "{}", html);
+        assert_eq!(html.matches("<title>").count(), 1, "{}", html);
+    }
+
+    async fn create_post(
+        pool: &sqlx::PgPool,
+        account_id: uuid::Uuid,
+        contents: &str,
+    ) -> uuid::Uuid {
+        sqlx::query_scalar(
+            "insert into posts (account_id, contents, rendered_contents)
+            values ($1, $2, $2)
+            returning id",
+        )
+        .bind(account_id)
+        .bind(contents)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn post_pages_are_404s_unless_the_post_exists(pool: sqlx::PgPool) {
+        let author = create_account(&pool, "author").await;
+        let post = create_post(&pool, author, "<p>Hello &lt;world&gt;</p>").await;
+        let app = app(pool, &crate::config::Config::default());
+
+        let (status, content_type, body) = get(&app, &format!("/posts/{}", post)).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(content_type, "text/html; charset=utf-8");
+        assert!(
+            body.contains(r#"<meta property="og:title" content="Hello &lt;world&gt;" />"#),
+            "{}",
+            body
+        );
+
+        let unknown = uuid::Uuid::new_v4();
+        let (status, content_type, body) = get(&app, &format!("/posts/{}", unknown)).await;
+        assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+        assert_eq!(content_type, "text/html; charset=utf-8");
+        assert!(!body.contains("og:title"), "{}", body);
+
+        // Anything that isn't a post id is left to the SPA
+        let (status, _, _) = get(&app, "/posts/not-a-post").await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn og_images_are_rendered_again_after_an_edit(pool: sqlx::PgPool) {
+        let author = create_account(&pool, "author").await;
+        let post = create_post(&pool, author, "<p>Hello</p>").await;
+        let mut config = crate::config::Config::default();
+        config.og.cache_dir =
+            std::env::temp_dir().join(format!("landing-test-{}", uuid::Uuid::new_v4()));
+        let app = app(pool.clone(), &config);
+
+        let cached = || {
+            let mut names: Vec<String> = std::fs::read_dir(&config.og.cache_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let render = || async {
+            let (status, content_type, _) = get(&app, &format!("/api/posts/{}/og.png", post)).await;
+            assert_eq!(status, axum::http::StatusCode::OK);
+            assert_eq!(content_type, "image/png");
+            let cached = cached();
+            assert_eq!(cached.len(), 1, "{:?}", cached);
+            cached[0].clone()
+        };
+
+        let first = render().await;
+        assert!(first.starts_with(&format!("{}-", post)), "{}", first);
+        assert_eq!(render().await, first);
+
+        sqlx::query(
+            "update posts set contents = '<p>Edited</p>', updated_at = now() where id = $1",
+        )
+        .bind(post)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let edited = render().await;
+        assert_ne!(edited, first);
+
+        sqlx::query(
+            "update accounts set account_name = 'renamed', updated_at = now() where id = $1",
+        )
+        .bind(author)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let renamed = render().await;
+        assert_ne!(renamed, edited);
+        assert_ne!(renamed, first);
+
+        let (status, _, _) =
+            get(&app, &format!("/api/posts/{}/og.png", uuid::Uuid::new_v4())).await;
+        assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&config.og.cache_dir).unwrap();
+    }
+}
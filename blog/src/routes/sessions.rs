@@ -0,0 +1,260 @@
+use super::AppState;
+use axum::response::IntoResponse;
+
+// Holds the session token in cookie mode, out of reach of scripts
+const SESSION_COOKIE: &str = "session";
+// Readable by the frontend, so it can send the CSRF token after a reload
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "x-csrf-token";
+const CSRF_TOKEN_BYTES: usize = 32;
+
+pub fn generate_csrf_token() -> String {
+    use rand::Rng;
+
+    let mut bytes = [0u8; CSRF_TOKEN_BYTES];
+    rand::rng().fill(&mut bytes);
+    data_encoding::BASE64URL_NOPAD.encode(&bytes)
+}
+
+/// The session token from the cookie, when cookie sessions are enabled.
+pub fn session_cookie<'a>(state: &AppState, headers: &'a axum::http::HeaderMap) -> Option<&'a str> {
+    if state.session_mode.allows_cookie() {
+        super::cookie(headers, SESSION_COOKIE)
+    } else {
+        None
+    }
+}
+
+/// Browsers attach cookies to cross-site requests too, so requests that can
+/// change something must also send the CSRF token their session was issued
+/// with. The token is a claim of the signed session, a forged cookie can't
+/// swap it.
+pub fn check_csrf(
+    method: &axum::http::Method,
+    headers: &axum::http::HeaderMap,
+    claims: &super::accounts::Claims,
+) -> bool {
+    use subtle::ConstantTimeEq;
+
+    if method.is_safe() {
+        return true;
+    }
+
+    let (Some(expected), Some(sent)) = (
+        claims.csrf.as_deref(),
+        headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    ) else {
+        return false;
+    };
+
+    expected.as_bytes().ct_eq(sent.as_bytes()).into()
+}
+
+pub fn set_cookies(
+    state: &AppState,
+    response: &mut axum::response::Response,
+    token: &str,
+    csrf_token: &str,
+) {
+    let max_age = state.token_lifetime.num_seconds();
+    append_cookie(state, response, SESSION_COOKIE, token, max_age, true);
+    append_cookie(state, response, CSRF_COOKIE, csrf_token, max_age, false);
+}
+
+/// Ends a cookie session. The token itself stays valid until it expires,
+/// like bearer tokens do.
+#[utoipa::path(
+    post,
+    path = "/api/logout",
+    tag = "auth",
+    responses((status = 204, description = "The session cookies were cleared"))
+)]
+pub async fn logout(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> axum::response::Response {
+    let mut response = axum::http::StatusCode::NO_CONTENT.into_response();
+    append_cookie(&state, &mut response, SESSION_COOKIE, "", 0, true);
+    append_cookie(&state, &mut response, CSRF_COOKIE, "", 0, false);
+    response
+}
+
+fn append_cookie(
+    state: &AppState,
+    response: &mut axum::response::Response,
+    name: &str,
+    value: &str,
+    max_age_secs: i64,
+    http_only: bool,
+) {
+    let secure = if state.base_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    let http_only = if http_only { "; HttpOnly" } else { "" };
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; SameSite=Strict{}{}",
+        name, value, max_age_secs, http_only, secure
+    );
+
+    if let Ok(value) = axum::http::HeaderValue::from_str(&cookie) {
+        response
+            .headers_mut()
+            .append(axum::http::header::SET_COOKIE, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::tests::{app, request, send};
+    use axum::http::{Method, StatusCode};
+
+    fn claims(csrf: Option<&str>) -> crate::routes::accounts::Claims {
+        crate::routes::accounts::Claims {
+            sub: uuid::Uuid::nil(),
+            exp: i64::MAX,
+            email: "someone@example.com".to_string(),
+            account_name: "someone".to_string(),
+            csrf: csrf.map(|csrf| csrf.to_string()),
+        }
+    }
+
+    #[test]
+    fn check_csrf_needs_the_session_token_on_unsafe_requests() {
+        let cases: &[(Method, Option<&str>, Option<&str>, bool)] = &[
+            (Method::GET, Some("token"), None, true),
+            (Method::HEAD, Some("token"), None, true),
+            (Method::POST, Some("token"), Some("token"), true),
+            (Method::POST, Some("token"), None, false),
+            (Method::POST, Some("token"), Some("other"), false),
+            (Method::POST, Some("token"), Some(""), false),
+            (Method::PUT, Some("token"), Some("token-"), false),
+            (Method::DELETE, Some("token"), Some("token"), true),
+            (Method::DELETE, None, Some("token"), false),
+        ];
+
+        for (method, expected, sent, allowed) in cases {
+            let mut headers = axum::http::HeaderMap::new();
+            if let Some(sent) = sent {
+                headers.insert(CSRF_HEADER, sent.parse().unwrap());
+            }
+            assert_eq!(
+                check_csrf(method, &headers, &claims(*expected)),
+                *allowed,
+                "{} sending {:?}",
+                method,
+                sent
+            );
+        }
+    }
+
+    /// Registers an account, returning the `session` and `csrf_token` cookies
+    /// set and the token in the body, when any.
+    async fn register(app: &axum::Router) -> (Option<String>, Option<String>, Option<String>) {
+        let response = tower::ServiceExt::oneshot(
+            app.clone(),
+            request(
+                Method::POST,
+                "/api/register",
+                None,
+                Some(serde_json::json!({
+                    "account_name": "someone",
+                    "email": "someone@example.com",
+                    "password": "correct horse battery",
+                })),
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut headers = axum::http::HeaderMap::new();
+        for set_cookie in response.headers().get_all(axum::http::header::SET_COOKIE) {
+            let pair = set_cookie.to_str().unwrap().split(';').next().unwrap();
+            headers.append(axum::http::header::COOKIE, pair.parse().unwrap());
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        (
+            crate::routes::cookie(&headers, SESSION_COOKIE).map(|value| value.to_string()),
+            crate::routes::cookie(&headers, CSRF_COOKIE).map(|value| value.to_string()),
+            body["token"].as_str().map(|token| token.to_string()),
+        )
+    }
+
+    /// Creates a post with the session cookie and `csrf` in the header.
+    fn create_post(session: &str, csrf: Option<&str>) -> axum::extract::Request {
+        let mut request = request(
+            Method::POST,
+            "/api/posts",
+            None,
+            Some(serde_json::json!({"content": "hello"})),
+        );
+        let headers = request.headers_mut();
+        headers.insert(
+            axum::http::header::COOKIE,
+            format!("{}={}", SESSION_COOKIE, session).parse().unwrap(),
+        );
+        if let Some(csrf) = csrf {
+            headers.insert(CSRF_HEADER, csrf.parse().unwrap());
+        }
+        request
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn cookie_sessions_need_the_csrf_token_to_change_things(pool: sqlx::PgPool) {
+        let mut config = crate::config::Config::default();
+        config.auth.session_mode = crate::config::SessionMode::Cookie;
+        let app = app(pool, &config);
+
+        let (session, csrf, token) = register(&app).await;
+        let (session, csrf) = (session.unwrap(), csrf.unwrap());
+        assert_eq!(token, None);
+
+        for sent in [None, Some("forged"), Some(session.as_str())] {
+            let (status, body) = send(&app, create_post(&session, sent)).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{:?}", sent);
+            assert_eq!(body["error"], "missing or invalid CSRF token");
+        }
+
+        let (status, body) = send(&app, create_post(&session, Some(&csrf))).await;
+        assert!(status.is_success(), "{} {}", status, body);
+
+        // Reading doesn't need it
+        let mut me = request(Method::GET, "/api/me", None, None);
+        me.headers_mut().insert(
+            axum::http::header::COOKIE,
+            format!("{}={}", SESSION_COOKIE, session).parse().unwrap(),
+        );
+        let (status, _) = send(&app, me).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Nor is the session accepted as a bearer token
+        let (status, _) = send(&app, request(Method::GET, "/api/me", Some(&session), None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(migrations = "src/db/migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn bearer_mode_ignores_the_session_cookie(pool: sqlx::PgPool) {
+        let app = app(pool, &crate::config::Config::default());
+
+        let (session, csrf, token) = register(&app).await;
+        assert_eq!((session, csrf), (None, None));
+        let token = token.unwrap();
+
+        let (status, body) = send(&app, create_post(&token, None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "missing authorization header");
+
+        let (status, _) = send(&app, request(Method::GET, "/api/me", Some(&token), None)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
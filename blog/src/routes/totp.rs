@@ -0,0 +1,266 @@
+use super::AppState;
+use crate::db::repositories::totp::TotpState;
+use axum::response::IntoResponse;
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = TotpCodeParams)]
+pub struct CodeParams {
+    pub code: String,
+}
+
+/// Starts enrollment with a new secret. 2FA stays off until the secret is
+/// confirmed with a code, so calling this again just replaces the secret.
+#[utoipa::path(
+    post,
+    path = "/api/me/totp",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    responses(
+        (status = 200, body = super::openapi::Data<super::openapi::TotpEnrollment>),
+        (status = 409, description = "2FA is already enabled", body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn start_enrollment(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+) -> axum::response::Response {
+    let secret = crate::totp::generate_secret();
+
+    match state
+        .queries
+        .start_totp_enrollment(claims.sub, &secret)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                axum::http::StatusCode::CONFLICT,
+                axum::response::Json(
+                    serde_json::json!({"error": "two-factor authentication is already enabled"}),
+                ),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    }
+
+    let issuer = issuer(&state.base_url);
+    let otpauth_uri = crate::totp::otpauth_uri(&issuer, &claims.email, &secret);
+    let qr_png = match crate::totp::qr_png(&otpauth_uri) {
+        Ok(png) => png,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    (
+        axum::http::StatusCode::OK,
+        axum::response::Json(serde_json::json!({
+            "data": {
+                "secret": secret,
+                "otpauth_uri": otpauth_uri,
+                "qr_code": format!(
+                    "data:image/png;base64,{}",
+                    data_encoding::BASE64.encode(&qr_png)
+                ),
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Enables 2FA once the first code from the authenticator app checks out and
+/// returns the recovery codes. They are only ever shown here.
+#[utoipa::path(
+    post,
+    path = "/api/me/totp/confirm",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    request_body = CodeParams,
+    responses(
+        (status = 200, body = super::openapi::Data<super::openapi::RecoveryCodes>),
+        (status = 400, description = "Wrong code", body = super::openapi::ErrorBody),
+        (status = 409, description = "No enrollment was started", body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn confirm_enrollment(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::Json(params): axum::Json<CodeParams>,
+) -> axum::response::Response {
+    let totp = match state.queries.totp_state(claims.sub).await {
+        Ok(totp) => totp,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    let secret = match totp.secret {
+        Some(secret) if !totp.enabled => secret,
+        _ => {
+            return (
+                axum::http::StatusCode::CONFLICT,
+                axum::response::Json(
+                    serde_json::json!({"error": "no two-factor enrollment is pending"}),
+                ),
+            )
+                .into_response();
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let Some(step) = crate::totp::verify(&secret, &params.code, now, None) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::response::Json(serde_json::json!({"error": "invalid code"})),
+        )
+            .into_response();
+    };
+
+    let recovery_codes = crate::totp::generate_recovery_codes();
+    let recovery_code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| crate::totp::hash_recovery_code(code))
+        .collect();
+
+    match state
+        .queries
+        .enable_totp(claims.sub, step, &recovery_code_hashes)
+        .await
+    {
+        Ok(true) => (
+            axum::http::StatusCode::OK,
+            axum::response::Json(serde_json::json!({"data": {"recovery_codes": recovery_codes}})),
+        )
+            .into_response(),
+        Ok(false) => (
+            axum::http::StatusCode::CONFLICT,
+            axum::response::Json(
+                serde_json::json!({"error": "no two-factor enrollment is pending"}),
+            ),
+        )
+            .into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
+
+/// Turns 2FA off, which takes a current code or a recovery code so a stolen
+/// session token alone can't remove the second factor.
+#[utoipa::path(
+    delete,
+    path = "/api/me/totp",
+    tag = "account",
+    security(("bearer" = []), ("cookie" = [])),
+    request_body = CodeParams,
+    responses(
+        (status = 204, description = "2FA is off"),
+        (status = 400, description = "Wrong code", body = super::openapi::ErrorBody),
+        (status = 409, description = "2FA isn't enabled", body = super::openapi::ErrorBody),
+    )
+)]
+pub async fn disable(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Extension(claims): axum::extract::Extension<super::accounts::Claims>,
+    axum::Json(params): axum::Json<CodeParams>,
+) -> axum::response::Response {
+    let totp = match state.queries.totp_state(claims.sub).await {
+        Ok(totp) => totp,
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    };
+
+    if !totp.enabled {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            axum::response::Json(
+                serde_json::json!({"error": "two-factor authentication is not enabled"}),
+            ),
+        )
+            .into_response();
+    }
+
+    match check_code(&state, claims.sub, &totp, &params.code).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::response::Json(serde_json::json!({"error": "invalid code"})),
+            )
+                .into_response();
+        }
+        Err(err) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::response::Json(serde_json::json!({"error": err})),
+            )
+                .into_response();
+        }
+    }
+
+    match state.queries.disable_totp(claims.sub).await {
+        Ok(()) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::response::Json(serde_json::json!({"error": err})),
+        )
+            .into_response(),
+    }
+}
+
+/// Accepts either a code from the authenticator app or an unused recovery
+/// code. Both are spent on success.
+pub async fn check_code(
+    state: &AppState,
+    account_id: uuid::Uuid,
+    totp: &TotpState,
+    code: &str,
+) -> Result<bool, crate::db::repositories::Error> {
+    let Some(secret) = totp.secret.as_deref().filter(|_| totp.enabled) else {
+        return Ok(false);
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    if let Some(step) = crate::totp::verify(secret, code, now, totp.last_step) {
+        return state.queries.use_totp_step(account_id, step).await;
+    }
+
+    state
+        .queries
+        .use_recovery_code(account_id, &crate::totp::hash_recovery_code(code))
+        .await
+}
+
+/// The site's host name, which authenticator apps show next to the code.
+fn issuer(base_url: &str) -> String {
+    base_url
+        .split("://")
+        .nth(1)
+        .unwrap_or(base_url)
+        .split(['/', ':'])
+        .next()
+        .unwrap_or(base_url)
+        .to_string()
+}
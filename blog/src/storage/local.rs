@@ -0,0 +1,51 @@
+pub struct LocalStorage {
+    dir: std::path::PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(dir: std::path::PathBuf) -> Self {
+        Self { dir }
+    }
+
+    // Fan out into subdirectories so no single directory grows too large
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        let prefix = key.get(..2).unwrap_or(key);
+        self.dir.join(prefix).join(key)
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), String> {
+        let path = self.path(key);
+        let parent = path.parent().unwrap_or(&self.dir);
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        // Write to a temporary file first so readers never see a partial upload
+        let temp_path = parent.join(format!("{}.{}.tmp", key, uuid::Uuid::new_v4()));
+        tokio::fs::write(&temp_path, data)
+            .await
+            .map_err(|err| err.to_string())?;
+        tokio::fs::rename(&temp_path, &path)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, String> {
+        tokio::fs::try_exists(self.path(key))
+            .await
+            .map_err(|err| err.to_string())
+    }
+}
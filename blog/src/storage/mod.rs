@@ -0,0 +1,37 @@
+pub mod local;
+pub mod s3;
+
+/// Content-addressed blob storage for uploaded media. Keys are produced by the
+/// application (hex-encoded SHA-256 digests), never taken from user input.
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), String>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+    async fn exists(&self, key: &str) -> Result<bool, String>;
+}
+
+/// Builds the backend selected by `media.backend`.
+pub fn from_config(
+    config: &crate::config::MediaConfig,
+) -> Result<std::sync::Arc<dyn Storage>, String> {
+    match config.backend {
+        crate::config::StorageBackend::S3 => {
+            let bucket = config
+                .s3
+                .bucket
+                .as_deref()
+                .ok_or("media.s3.bucket is not set")?;
+            let storage = s3::S3Storage::new(
+                bucket,
+                config.s3.endpoint.clone(),
+                &config.s3.region,
+                config.s3.access_key_id.clone(),
+                config.s3.secret_access_key.clone(),
+            )?;
+            Ok(std::sync::Arc::new(storage))
+        }
+        crate::config::StorageBackend::Local => Ok(std::sync::Arc::new(local::LocalStorage::new(
+            config.dir.clone(),
+        ))),
+    }
+}
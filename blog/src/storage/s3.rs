@@ -0,0 +1,101 @@
+use object_store::ObjectStore;
+
+/// Stores media in an S3-compatible bucket (AWS S3, MinIO, ...).
+pub struct S3Storage {
+    store: object_store::aws::AmazonS3,
+}
+
+impl S3Storage {
+    pub fn new(
+        bucket: &str,
+        endpoint: Option<String>,
+        region: &str,
+        access_key_id: Option<String>,
+        secret_access_key: Option<String>,
+    ) -> Result<Self, String> {
+        let mut builder = object_store::aws::AmazonS3Builder::new()
+            .with_bucket_name(bucket)
+            .with_region(region);
+
+        if let Some(endpoint) = endpoint {
+            // Local stand-ins such as MinIO are usually served over plain HTTP
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
+        }
+        if let Some(access_key_id) = access_key_id {
+            builder = builder.with_access_key_id(access_key_id);
+        }
+        if let Some(secret_access_key) = secret_access_key {
+            builder = builder.with_secret_access_key(secret_access_key);
+        }
+
+        let store = builder.build().map_err(|err| err.to_string())?;
+
+        Ok(Self { store })
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Storage for S3Storage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), String> {
+        self.store
+            .put(&object_store::path::Path::from(key), data.into())
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let result = match self.store.get(&object_store::path::Path::from(key)).await {
+            Ok(result) => result,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let data = result.bytes().await.map_err(|err| err.to_string())?;
+
+        Ok(Some(data.to_vec()))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, String> {
+        match self.store.head(&object_store::path::Path::from(key)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible server in S3_TEST_ENDPOINT"]
+    async fn stores_and_reads_back_objects() {
+        let env = |name: &str, default: &str| std::env::var(name).unwrap_or(default.to_string());
+        let storage = S3Storage::new(
+            &env("S3_TEST_BUCKET", "landing"),
+            Some(std::env::var("S3_TEST_ENDPOINT").unwrap()),
+            &env("S3_TEST_REGION", "us-east-1"),
+            Some(env("S3_TEST_ACCESS_KEY_ID", "minio")),
+            Some(env("S3_TEST_SECRET_ACCESS_KEY", "password")),
+        )
+        .unwrap();
+        let key = uuid::Uuid::new_v4().simple().to_string();
+
+        assert!(!storage.exists(&key).await.unwrap());
+        assert_eq!(storage.get(&key).await.unwrap(), None);
+
+        storage.put(&key, b"contents".to_vec()).await.unwrap();
+
+        assert!(storage.exists(&key).await.unwrap());
+        assert_eq!(
+            storage.get(&key).await.unwrap().as_deref(),
+            Some(&b"contents"[..])
+        );
+    }
+}
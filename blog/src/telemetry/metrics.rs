@@ -0,0 +1,113 @@
+// Buckets (in seconds) covering fast API calls through slow image renders
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// How often the pool gauges are refreshed and stale metrics are cleaned up
+const SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Installs the global Prometheus recorder.
+pub fn init() -> metrics_exporter_prometheus::PrometheusHandle {
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            metrics_exporter_prometheus::Matcher::Suffix("_seconds".to_string()),
+            LATENCY_BUCKETS,
+        )
+        .expect("latency buckets are not empty")
+        .install_recorder()
+        .expect("can't install metrics recorder")
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    handle: metrics_exporter_prometheus::PrometheusHandle,
+    pool: sqlx::PgPool,
+}
+
+/// Router serving `/metrics` on the internal metrics listener.
+pub fn router(
+    handle: metrics_exporter_prometheus::PrometheusHandle,
+    pool: sqlx::PgPool,
+) -> axum::Router {
+    axum::Router::new()
+        .route("/metrics", axum::routing::get(render))
+        .with_state(MetricsState { handle, pool })
+}
+
+async fn render(
+    axum::extract::State(state): axum::extract::State<MetricsState>,
+) -> impl axum::response::IntoResponse {
+    record_pool(&state.pool);
+
+    (
+        axum::http::StatusCode::OK,
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        state.handle.render(),
+    )
+}
+
+/// Periodically records the pool gauges, so they stay current between
+/// scrapes, and runs recorder upkeep.
+pub fn spawn_pool_sampler(
+    handle: metrics_exporter_prometheus::PrometheusHandle,
+    pool: sqlx::PgPool,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            record_pool(&pool);
+            handle.run_upkeep();
+        }
+    });
+}
+
+fn record_pool(pool: &sqlx::PgPool) {
+    let size = pool.size() as f64;
+    let idle = pool.num_idle() as f64;
+
+    metrics::gauge!("db_pool_connections").set(size);
+    metrics::gauge!("db_pool_idle_connections").set(idle);
+    metrics::gauge!("db_pool_in_use_connections").set(size - idle);
+    metrics::gauge!("db_pool_max_connections").set(pool.options().get_max_connections() as f64);
+}
+
+/// Records request counts and latency by route template and status.
+pub async fn metrics_middleware(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    // Unmatched paths are grouped so arbitrary URLs can't explode label cardinality
+    let route = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .map(|route| route.as_str().to_string())
+        .unwrap_or_else(|| "fallback".to_string());
+    let method = request.method().to_string();
+
+    let start = std::time::Instant::now();
+    let response = next.run(request).await;
+    let duration = start.elapsed().as_secs_f64();
+    let status = response.status().as_u16().to_string();
+
+    metrics::counter!(
+        "http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status.clone(),
+    )
+    .increment(1);
+    metrics::histogram!(
+        "http_request_duration_seconds",
+        "method" => method,
+        "route" => route,
+        "status" => status,
+    )
+    .record(duration);
+
+    response
+}
@@ -0,0 +1,98 @@
+pub mod metrics;
+
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+pub struct Telemetry {
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Flushes spans that are still queued for export.
+    pub fn shutdown(self) {
+        if let Some(tracer_provider) = self.tracer_provider
+            && let Err(err) = tracer_provider.shutdown()
+        {
+            tracing::warn!("can't shut down tracer provider: {}", err);
+        }
+    }
+}
+
+/// Installs the global subscriber. Logs are filtered with `RUST_LOG` and
+/// written in the configured format. Traces are exported over OTLP/HTTP when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+pub fn init(config: &crate::config::TelemetryConfig) -> Telemetry {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+
+    let fmt_layer = match config.log_format {
+        crate::config::LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+        crate::config::LogFormat::Pretty => tracing_subscriber::fmt::layer().pretty().boxed(),
+    };
+
+    let tracer_provider = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(_) => match otlp_tracer_provider() {
+            Ok(tracer_provider) => Some(tracer_provider),
+            Err(err) => {
+                eprintln!("can't configure otlp exporter: {}", err);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    let otel_layer = tracer_provider.as_ref().map(|tracer_provider| {
+        use opentelemetry::trace::TracerProvider;
+
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("landing"))
+    });
+
+    opentelemetry::global::set_text_map_propagator(
+        opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+    );
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .init();
+
+    Telemetry { tracer_provider }
+}
+
+fn otlp_tracer_provider()
+-> Result<opentelemetry_sdk::trace::SdkTracerProvider, opentelemetry_otlp::ExporterBuildError> {
+    // The endpoint and headers are read from the standard OTEL_EXPORTER_OTLP_* variables
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "landing".to_string());
+
+    Ok(opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(service_name)
+                .build(),
+        )
+        .build())
+}
+
+/// Reads W3C trace context (`traceparent`, `tracestate`) from request headers.
+pub struct HeaderExtractor<'a>(pub &'a axum::http::HeaderMap);
+
+impl opentelemetry::propagation::Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
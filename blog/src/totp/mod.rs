@@ -0,0 +1,244 @@
+use hmac::Mac;
+use rand::Rng;
+use subtle::ConstantTimeEq;
+
+// The parameters authenticator apps assume when the URI doesn't say otherwise
+const STEP_SECS: i64 = 30;
+const DIGITS: usize = 6;
+const SECRET_BYTES: usize = 20;
+// Codes from one step either side are accepted to allow for clock drift
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_BYTES: usize = 5;
+
+const QR_MODULE_PIXELS: u32 = 8;
+const QR_QUIET_ZONE_MODULES: u32 = 4;
+
+/// A new random secret, base32 encoded without padding.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::rng().fill(&mut secret);
+    data_encoding::BASE32_NOPAD.encode(&secret)
+}
+
+/// The `otpauth://` URI authenticator apps enroll from.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = percent_encode(issuer);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        percent_encode(account),
+        secret,
+        issuer,
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+/// Checks `code` against the steps around `unix_time` and returns the step
+/// it matched. Steps up to and including `last_step` are rejected so a code
+/// can't be used twice.
+pub fn verify(secret: &str, code: &str, unix_time: i64, last_step: Option<i64>) -> Option<i64> {
+    let secret = data_encoding::BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let code = code.trim();
+    if code.len() != DIGITS || !code.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let current = unix_time / STEP_SECS;
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| bool::from(hotp(&secret, *step).as_bytes().ct_eq(code.as_bytes())))
+}
+
+/// RFC 4226 HOTP with HMAC-SHA1 and dynamic truncation.
+fn hotp(secret: &[u8], counter: i64) -> String {
+    let mut mac =
+        hmac::Hmac::<sha1::Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS as u32),
+        width = DIGITS
+    )
+}
+
+/// Fresh one-time recovery codes, formatted like `abcd-efgh`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_BYTES];
+            rng.fill(&mut bytes);
+            let code = data_encoding::BASE32_NOPAD.encode(&bytes).to_lowercase();
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect()
+}
+
+/// Recovery codes are random enough that a plain SHA-256 is a safe way to
+/// store them, and it lets a code be looked up by its hash.
+pub fn hash_recovery_code(code: &str) -> String {
+    use sha2::Digest;
+
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(sha2::Sha256::digest(normalized.as_bytes()))
+}
+
+/// Renders `data` as a QR code PNG with a quiet zone around it.
+pub fn qr_png(data: &str) -> Result<Vec<u8>, String> {
+    let code = qrcode::QrCode::new(data.as_bytes()).map_err(|err| err.to_string())?;
+    let modules = code.width() as u32;
+    let colors = code.to_colors();
+    let size = (modules + 2 * QR_QUIET_ZONE_MODULES) * QR_MODULE_PIXELS;
+
+    let image = image::GrayImage::from_fn(size, size, |x, y| {
+        let x = (x / QR_MODULE_PIXELS).checked_sub(QR_QUIET_ZONE_MODULES);
+        let y = (y / QR_MODULE_PIXELS).checked_sub(QR_QUIET_ZONE_MODULES);
+        let dark = match (x, y) {
+            (Some(x), Some(y)) if x < modules && y < modules => {
+                colors[(y * modules + x) as usize] == qrcode::Color::Dark
+            }
+            _ => false,
+        };
+        image::Luma([if dark { 0 } else { 255 }])
+    });
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|err| err.to_string())?;
+    Ok(png.into_inner())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 Appendix B uses this ASCII secret for its SHA-1 vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn secret() -> String {
+        data_encoding::BASE32_NOPAD.encode(RFC_SECRET)
+    }
+
+    fn code_at(unix_time: i64) -> String {
+        hotp(RFC_SECRET, unix_time / STEP_SECS)
+    }
+
+    #[test]
+    fn matches_rfc_6238_sha1_vectors() {
+        // The RFC lists 8 digit codes, these are their last 6 digits
+        for (unix_time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(code_at(unix_time), code, "at {}", unix_time);
+            assert_eq!(
+                verify(&secret(), code, unix_time, None),
+                Some(unix_time / STEP_SECS)
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_drift() {
+        let now = 1111111111;
+        let step = now / STEP_SECS;
+
+        for drift in [-1, 0, 1] {
+            let code = code_at(now + drift * STEP_SECS);
+            assert_eq!(verify(&secret(), &code, now, None), Some(step + drift));
+        }
+        for drift in [-2, 2] {
+            let code = code_at(now + drift * STEP_SECS);
+            assert_eq!(verify(&secret(), &code, now, None), None);
+        }
+    }
+
+    #[test]
+    fn rejects_steps_up_to_the_last_used_one() {
+        let now = 1111111111;
+        let step = now / STEP_SECS;
+        let code = code_at(now);
+
+        assert_eq!(verify(&secret(), &code, now, Some(step - 1)), Some(step));
+        assert_eq!(verify(&secret(), &code, now, Some(step)), None);
+        assert_eq!(verify(&secret(), &code, now, Some(step + 1)), None);
+
+        // A code from the previous step can't follow one from this step
+        let earlier = code_at(now - STEP_SECS);
+        assert_eq!(verify(&secret(), &earlier, now, Some(step)), None);
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        let now = 1111111111;
+        let code = code_at(now);
+
+        assert_eq!(
+            verify(&secret(), &format!(" {} ", code), now, None),
+            Some(now / STEP_SECS)
+        );
+        assert_eq!(verify(&secret(), &code[..5], now, None), None);
+        assert_eq!(verify(&secret(), &format!("{}0", code), now, None), None);
+        assert_eq!(verify(&secret(), "05O471", now, None), None);
+        assert_eq!(verify("not base32!", &code, now, None), None);
+    }
+
+    #[test]
+    fn recovery_code_hashes_ignore_case_and_separators() {
+        let hash = hash_recovery_code("abcd-efgh");
+
+        assert_eq!(hash_recovery_code("ABCD-EFGH"), hash);
+        assert_eq!(hash_recovery_code("abcdefgh"), hash);
+        assert_eq!(hash_recovery_code(" abcd efgh\n"), hash);
+        assert_ne!(hash_recovery_code("abcd-efgi"), hash);
+    }
+
+    #[test]
+    fn generated_recovery_codes_round_trip_through_the_hash() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 9);
+            assert_eq!(
+                hash_recovery_code(&code.to_uppercase().replace('-', " ")),
+                hash_recovery_code(code)
+            );
+        }
+    }
+}
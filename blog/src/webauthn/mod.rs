@@ -0,0 +1,595 @@
+// The parts of WebAuthn level 2 needed for passkeys: ES256 credentials,
+// `none` attestation and discoverable-credential logins. Everything here is
+// plain data in and out so ceremonies can be driven by a software
+// authenticator.
+
+use p256::ecdsa::signature::Verifier;
+use sha2::Digest;
+
+/// COSE algorithm identifier for ECDSA with P-256 and SHA-256
+pub const ES256: i64 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+const CHALLENGE_BYTES: usize = 32;
+
+/// Where ceremonies are expected to come from.
+#[derive(Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    /// Uses the host of `base_url` as the RP ID and its scheme and authority
+    /// as the origin.
+    pub fn from_base_url(base_url: &str) -> Result<Self, String> {
+        let uri: axum::http::Uri = base_url.parse().map_err(|_| "invalid base url")?;
+        let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+            return Err("base url needs a scheme and host".to_string());
+        };
+
+        Ok(RelyingParty {
+            id: authority.host().to_string(),
+            name: authority.host().to_string(),
+            origin: format!("{}://{}", scheme, authority),
+        })
+    }
+}
+
+/// A credential as returned by `navigator.credentials.create()`, with binary
+/// fields base64url encoded.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationCredential {
+    pub raw_id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+/// An assertion as returned by `navigator.credentials.get()`.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationCredential {
+    pub raw_id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+/// What gets stored for a newly registered credential.
+pub struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub algorithm: i64,
+    pub sign_count: i64,
+    pub transports: Vec<String>,
+}
+
+/// The stored credential an assertion is checked against.
+pub struct StoredCredential<'a> {
+    pub public_key: &'a [u8],
+    pub algorithm: i64,
+    pub sign_count: i64,
+}
+
+pub struct Assertion {
+    pub sign_count: i64,
+    pub user_verified: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    rest: &'a [u8],
+}
+
+pub fn generate_challenge() -> Vec<u8> {
+    use rand::Rng;
+
+    let mut challenge = vec![0u8; CHALLENGE_BYTES];
+    rand::rng().fill(challenge.as_mut_slice());
+    challenge
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    data_encoding::BASE64URL_NOPAD.encode(bytes)
+}
+
+/// Browsers omit padding but some libraries keep it, both are accepted.
+pub fn decode(value: &str) -> Result<Vec<u8>, String> {
+    data_encoding::BASE64URL_NOPAD
+        .decode(value.trim_end_matches('=').as_bytes())
+        .map_err(|_| "invalid base64url".to_string())
+}
+
+/// Checks a registration ceremony against the challenge that was issued for
+/// it and extracts the credential.
+pub fn verify_registration(
+    rp: &RelyingParty,
+    challenge: &[u8],
+    credential: &RegistrationCredential,
+) -> Result<RegisteredCredential, String> {
+    let client_data_json = decode(&credential.response.client_data_json)?;
+    check_client_data(rp, challenge, &client_data_json, "webauthn.create")?;
+
+    let attestation_object = decode(&credential.response.attestation_object)?;
+    let attestation: ciborium::Value = ciborium::from_reader(attestation_object.as_slice())
+        .map_err(|_| "invalid attestation object")?;
+    // Attestation isn't requested, so the statement is not checked, only
+    // the authenticator data the credential is taken from
+    let auth_data = map_get(&attestation, &ciborium::Value::from("authData"))
+        .and_then(|value| value.as_bytes())
+        .ok_or("attestation object has no authenticator data")?;
+
+    let auth_data = parse_authenticator_data(auth_data)?;
+    check_authenticator_data(rp, &auth_data)?;
+    if auth_data.flags & FLAG_ATTESTED_CREDENTIAL_DATA == 0 {
+        return Err("authenticator data has no credential".to_string());
+    }
+
+    // aaguid (16 bytes), credential id length (2 bytes), credential id, key
+    let rest = auth_data.rest;
+    if rest.len() < 18 {
+        return Err("attested credential data is truncated".to_string());
+    }
+    let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+    let credential_id = rest
+        .get(18..18 + id_len)
+        .ok_or("attested credential data is truncated")?
+        .to_vec();
+
+    if credential_id != decode(&credential.raw_id)? {
+        return Err("credential id doesn't match authenticator data".to_string());
+    }
+
+    let mut key_bytes = &rest[18 + id_len..];
+    let cose_key: ciborium::Value =
+        ciborium::from_reader(&mut key_bytes).map_err(|_| "invalid credential public key")?;
+    let (algorithm, public_key) = parse_cose_key(&cose_key)?;
+
+    Ok(RegisteredCredential {
+        credential_id,
+        public_key,
+        algorithm,
+        sign_count: auth_data.sign_count as i64,
+        transports: credential.response.transports.clone(),
+    })
+}
+
+/// Checks a login ceremony against the challenge that was issued for it and
+/// the credential the assertion claims to come from.
+pub fn verify_authentication(
+    rp: &RelyingParty,
+    challenge: &[u8],
+    stored: &StoredCredential,
+    credential: &AuthenticationCredential,
+) -> Result<Assertion, String> {
+    let client_data_json = decode(&credential.response.client_data_json)?;
+    check_client_data(rp, challenge, &client_data_json, "webauthn.get")?;
+
+    let raw_auth_data = decode(&credential.response.authenticator_data)?;
+    let auth_data = parse_authenticator_data(&raw_auth_data)?;
+    check_authenticator_data(rp, &auth_data)?;
+
+    if stored.algorithm != ES256 {
+        return Err("unsupported credential algorithm".to_string());
+    }
+    let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(stored.public_key)
+        .map_err(|_| "invalid stored public key")?;
+    let signature = p256::ecdsa::Signature::from_der(&decode(&credential.response.signature)?)
+        .map_err(|_| "invalid signature encoding")?;
+
+    let mut signed = raw_auth_data.clone();
+    signed.extend_from_slice(&sha2::Sha256::digest(&client_data_json));
+    key.verify(&signed, &signature)
+        .map_err(|_| "invalid signature")?;
+
+    // Authenticators that keep a counter must increase it, a counter that
+    // goes backwards means the credential was cloned. Zero means no counter.
+    let sign_count = auth_data.sign_count as i64;
+    if (sign_count != 0 || stored.sign_count != 0) && sign_count <= stored.sign_count {
+        return Err("signature counter did not increase".to_string());
+    }
+
+    Ok(Assertion {
+        sign_count,
+        user_verified: auth_data.flags & FLAG_USER_VERIFIED != 0,
+    })
+}
+
+fn check_client_data(
+    rp: &RelyingParty,
+    challenge: &[u8],
+    client_data_json: &[u8],
+    kind: &str,
+) -> Result<(), String> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).map_err(|_| "invalid client data")?;
+
+    if client_data.kind != kind {
+        return Err("unexpected ceremony type".to_string());
+    }
+    if decode(&client_data.challenge)? != challenge {
+        return Err("challenge doesn't match".to_string());
+    }
+    if client_data.origin != rp.origin {
+        return Err("origin doesn't match".to_string());
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData<'_>, String> {
+    if data.len() < 37 {
+        return Err("authenticator data is truncated".to_string());
+    }
+
+    Ok(AuthenticatorData {
+        rp_id_hash: &data[..32],
+        flags: data[32],
+        sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+        rest: &data[37..],
+    })
+}
+
+fn check_authenticator_data(rp: &RelyingParty, data: &AuthenticatorData) -> Result<(), String> {
+    if data.rp_id_hash != sha2::Sha256::digest(rp.id.as_bytes()).as_slice() {
+        return Err("relying party doesn't match".to_string());
+    }
+    if data.flags & FLAG_USER_PRESENT == 0 {
+        return Err("user was not present".to_string());
+    }
+
+    Ok(())
+}
+
+/// Turns an EC2 P-256 COSE key into its algorithm and SEC1 encoded point.
+fn parse_cose_key(key: &ciborium::Value) -> Result<(i64, Vec<u8>), String> {
+    let int = |label: i64| {
+        map_get(key, &ciborium::Value::from(label))
+            .and_then(|value| value.as_integer())
+            .and_then(|value| i64::try_from(value).ok())
+    };
+    let bytes = |label: i64| {
+        map_get(key, &ciborium::Value::from(label))
+            .and_then(|value| value.as_bytes())
+            .filter(|bytes| bytes.len() == 32)
+    };
+
+    // kty 2 is EC2, crv 1 is P-256
+    if int(1) != Some(2) || int(3) != Some(ES256) || int(-1) != Some(1) {
+        return Err("only ES256 credentials are supported".to_string());
+    }
+    let (Some(x), Some(y)) = (bytes(-2), bytes(-3)) else {
+        return Err("invalid credential public key".to_string());
+    };
+
+    let mut point = Vec::with_capacity(65);
+    point.push(0x04);
+    point.extend_from_slice(x);
+    point.extend_from_slice(y);
+
+    // Rejects points that aren't on the curve
+    p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+        .map_err(|_| "invalid credential public key")?;
+
+    Ok((ES256, point))
+}
+
+fn map_get<'a>(map: &'a ciborium::Value, key: &ciborium::Value) -> Option<&'a ciborium::Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+
+    /// A software authenticator with one ES256 credential and a counter.
+    pub(crate) struct SoftAuthenticator {
+        key: p256::ecdsa::SigningKey,
+        pub(crate) credential_id: Vec<u8>,
+        pub(crate) sign_count: u32,
+    }
+
+    impl SoftAuthenticator {
+        pub(crate) fn new() -> Self {
+            SoftAuthenticator {
+                key: p256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap(),
+                credential_id: b"soft-credential".to_vec(),
+                sign_count: 0,
+            }
+        }
+
+        fn client_data(kind: &str, challenge: &[u8], origin: &str) -> Vec<u8> {
+            serde_json::to_vec(&serde_json::json!({
+                "type": kind,
+                "challenge": encode(challenge),
+                "origin": origin,
+            }))
+            .unwrap()
+        }
+
+        fn authenticator_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+            let mut data = sha2::Sha256::digest(rp_id.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            data
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            let key = ciborium::Value::Map(vec![
+                (1.into(), 2.into()),
+                (3.into(), ES256.into()),
+                ((-1).into(), 1.into()),
+                (
+                    (-2).into(),
+                    ciborium::Value::Bytes(point.x().unwrap().to_vec()),
+                ),
+                (
+                    (-3).into(),
+                    ciborium::Value::Bytes(point.y().unwrap().to_vec()),
+                ),
+            ]);
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&key, &mut bytes).unwrap();
+            bytes
+        }
+
+        /// Answers `navigator.credentials.create()` for `rp_id` from `origin`.
+        pub(crate) fn register(
+            &self,
+            rp_id: &str,
+            origin: &str,
+            challenge: &[u8],
+        ) -> RegistrationCredential {
+            let mut auth_data = self.authenticator_data(
+                rp_id,
+                FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_CREDENTIAL_DATA,
+            );
+            auth_data.extend_from_slice(&[0u8; 16]);
+            auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            auth_data.extend_from_slice(&self.credential_id);
+            auth_data.extend_from_slice(&self.cose_key());
+
+            let attestation = ciborium::Value::Map(vec![
+                ("fmt".into(), "none".into()),
+                ("attStmt".into(), ciborium::Value::Map(Vec::new())),
+                ("authData".into(), ciborium::Value::Bytes(auth_data)),
+            ]);
+            let mut attestation_object = Vec::new();
+            ciborium::into_writer(&attestation, &mut attestation_object).unwrap();
+
+            RegistrationCredential {
+                raw_id: encode(&self.credential_id),
+                response: AttestationResponse {
+                    client_data_json: encode(&Self::client_data(
+                        "webauthn.create",
+                        challenge,
+                        origin,
+                    )),
+                    attestation_object: encode(&attestation_object),
+                    transports: vec!["internal".to_string()],
+                },
+            }
+        }
+
+        /// Answers `navigator.credentials.get()`, bumping the counter first.
+        pub(crate) fn assert(
+            &mut self,
+            rp_id: &str,
+            origin: &str,
+            challenge: &[u8],
+            user_handle: Option<&[u8]>,
+        ) -> AuthenticationCredential {
+            self.sign_count += 1;
+
+            let auth_data = self.authenticator_data(rp_id, FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+            let client_data_json = Self::client_data("webauthn.get", challenge, origin);
+
+            let mut signed = auth_data.clone();
+            signed.extend_from_slice(&sha2::Sha256::digest(&client_data_json));
+            let signature: p256::ecdsa::Signature = self.key.sign(&signed);
+
+            AuthenticationCredential {
+                raw_id: encode(&self.credential_id),
+                response: AssertionResponse {
+                    client_data_json: encode(&client_data_json),
+                    authenticator_data: encode(&auth_data),
+                    signature: encode(signature.to_der().as_bytes()),
+                    user_handle: user_handle.map(encode),
+                },
+            }
+        }
+    }
+
+    fn rp() -> RelyingParty {
+        RelyingParty::from_base_url("https://blog.example.com").unwrap()
+    }
+
+    fn stored(credential: &RegisteredCredential) -> StoredCredential<'_> {
+        StoredCredential {
+            public_key: &credential.public_key,
+            algorithm: credential.algorithm,
+            sign_count: credential.sign_count,
+        }
+    }
+
+    #[test]
+    fn relying_party_comes_from_the_base_url() {
+        let rp = RelyingParty::from_base_url("http://localhost:8080/blog").unwrap();
+        assert_eq!(rp.id, "localhost");
+        assert_eq!(rp.origin, "http://localhost:8080");
+        assert!(RelyingParty::from_base_url("/relative").is_err());
+    }
+
+    #[test]
+    fn registers_and_authenticates_a_software_authenticator() {
+        let rp = rp();
+        let mut authenticator = SoftAuthenticator::new();
+
+        let challenge = generate_challenge();
+        let credential = verify_registration(
+            &rp,
+            &challenge,
+            &authenticator.register(&rp.id, &rp.origin, &challenge),
+        )
+        .unwrap();
+        assert_eq!(credential.credential_id, authenticator.credential_id);
+        assert_eq!(credential.algorithm, ES256);
+        assert_eq!(credential.sign_count, 0);
+        assert_eq!(credential.transports, ["internal"]);
+
+        let challenge = generate_challenge();
+        let assertion = verify_authentication(
+            &rp,
+            &challenge,
+            &stored(&credential),
+            &authenticator.assert(&rp.id, &rp.origin, &challenge, None),
+        )
+        .unwrap();
+        assert_eq!(assertion.sign_count, 1);
+        assert!(assertion.user_verified);
+    }
+
+    #[test]
+    fn rejects_the_wrong_origin_and_relying_party() {
+        let rp = rp();
+        let mut authenticator = SoftAuthenticator::new();
+        let challenge = generate_challenge();
+
+        let err = verify_registration(
+            &rp,
+            &challenge,
+            &authenticator.register(&rp.id, "https://evil.example.com", &challenge),
+        )
+        .err();
+        assert_eq!(err.as_deref(), Some("origin doesn't match"));
+
+        let err = verify_registration(
+            &rp,
+            &challenge,
+            &authenticator.register("evil.example.com", &rp.origin, &challenge),
+        )
+        .err();
+        assert_eq!(err.as_deref(), Some("relying party doesn't match"));
+
+        let credential = verify_registration(
+            &rp,
+            &challenge,
+            &authenticator.register(&rp.id, &rp.origin, &challenge),
+        )
+        .unwrap();
+
+        let assertion = authenticator.assert(&rp.id, "https://evil.example.com", &challenge, None);
+        let err = verify_authentication(&rp, &challenge, &stored(&credential), &assertion).err();
+        assert_eq!(err.as_deref(), Some("origin doesn't match"));
+
+        let assertion = authenticator.assert("evil.example.com", &rp.origin, &challenge, None);
+        let err = verify_authentication(&rp, &challenge, &stored(&credential), &assertion).err();
+        assert_eq!(err.as_deref(), Some("relying party doesn't match"));
+    }
+
+    #[test]
+    fn rejects_an_answer_to_another_challenge_or_ceremony() {
+        let rp = rp();
+        let mut authenticator = SoftAuthenticator::new();
+        let challenge = generate_challenge();
+        let registration = authenticator.register(&rp.id, &rp.origin, &challenge);
+
+        let err = verify_registration(&rp, &generate_challenge(), &registration).err();
+        assert_eq!(err.as_deref(), Some("challenge doesn't match"));
+
+        let credential = verify_registration(&rp, &challenge, &registration).unwrap();
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, None);
+        let err =
+            verify_authentication(&rp, &generate_challenge(), &stored(&credential), &assertion)
+                .err();
+        assert_eq!(err.as_deref(), Some("challenge doesn't match"));
+
+        // An assertion's client data can't stand in for a registration's
+        let mut registration = authenticator.register(&rp.id, &rp.origin, &challenge);
+        registration.response.client_data_json = assertion.response.client_data_json;
+        let err = verify_registration(&rp, &challenge, &registration).err();
+        assert_eq!(err.as_deref(), Some("unexpected ceremony type"));
+    }
+
+    #[test]
+    fn rejects_a_counter_that_did_not_increase() {
+        let rp = rp();
+        let mut authenticator = SoftAuthenticator::new();
+        let challenge = generate_challenge();
+        let mut credential = verify_registration(
+            &rp,
+            &challenge,
+            &authenticator.register(&rp.id, &rp.origin, &challenge),
+        )
+        .unwrap();
+        credential.sign_count = 5;
+
+        authenticator.sign_count = 4;
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, None);
+        let err = verify_authentication(&rp, &challenge, &stored(&credential), &assertion).err();
+        assert_eq!(err.as_deref(), Some("signature counter did not increase"));
+
+        let assertion = authenticator.assert(&rp.id, &rp.origin, &challenge, None);
+        let assertion =
+            verify_authentication(&rp, &challenge, &stored(&credential), &assertion).unwrap();
+        assert_eq!(assertion.sign_count, 6);
+    }
+
+    #[test]
+    fn rejects_a_signature_from_another_key() {
+        let rp = rp();
+        let authenticator = SoftAuthenticator::new();
+        let challenge = generate_challenge();
+        let credential = verify_registration(
+            &rp,
+            &challenge,
+            &authenticator.register(&rp.id, &rp.origin, &challenge),
+        )
+        .unwrap();
+
+        let mut impostor = SoftAuthenticator {
+            key: p256::ecdsa::SigningKey::from_slice(&[9u8; 32]).unwrap(),
+            ..SoftAuthenticator::new()
+        };
+        let assertion = impostor.assert(&rp.id, &rp.origin, &challenge, None);
+        let err = verify_authentication(&rp, &challenge, &stored(&credential), &assertion).err();
+        assert_eq!(err.as_deref(), Some("invalid signature"));
+    }
+}